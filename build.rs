@@ -0,0 +1,35 @@
+use std::path::Path;
+use std::process::Command;
+
+fn main() {
+    // `sqlx::migrate!` embeds the SQL files at compile time, so pick up new migrations.
+    println!("cargo:rerun-if-changed=migrations");
+
+    // The commit reported by the health probes: `GIT_COMMIT` when building an image,
+    // otherwise the checked-out revision.
+    println!("cargo:rerun-if-env-changed=GIT_COMMIT");
+    if let Ok(head) = std::fs::read_to_string(".git/HEAD") {
+        println!("cargo:rerun-if-changed=.git/HEAD");
+        if let Some(branch) = head.trim().strip_prefix("ref: ") {
+            let branch = Path::new(".git").join(branch);
+            if branch.exists() {
+                println!("cargo:rerun-if-changed={}", branch.display());
+            }
+        }
+    }
+
+    let commit = std::env::var("GIT_COMMIT")
+        .ok()
+        .filter(|commit| !commit.is_empty())
+        .or_else(|| {
+            Command::new("git")
+                .args(["rev-parse", "--short", "HEAD"])
+                .output()
+                .ok()
+                .filter(|output| output.status.success())
+                .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+        });
+    if let Some(commit) = commit {
+        println!("cargo:rustc-env=BUILD_GIT_COMMIT={}", commit);
+    }
+}
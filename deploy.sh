@@ -12,7 +12,7 @@ IMAGE_NAME="library-server"
 TAR_FILE="library-server.tar"
 
 echo "=== 开始构建 Docker 镜像 ==="
-docker build -t ${IMAGE_NAME}:latest .
+docker build --build-arg GIT_COMMIT="$(git rev-parse --short HEAD 2>/dev/null)" -t ${IMAGE_NAME}:latest .
 
 echo "=== 保存 Docker 镜像为 tar 文件 ==="
 docker save -o ${TAR_FILE} ${IMAGE_NAME}:latest
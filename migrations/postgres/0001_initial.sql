@@ -0,0 +1,71 @@
+-- Ids are the hex form of the ObjectIds used by the rest of the application.
+
+CREATE TABLE users (
+    id CHAR(24) PRIMARY KEY,
+    email TEXT NOT NULL,
+    username TEXT NOT NULL,
+    password_hash TEXT NOT NULL,
+    password_history TEXT[] NOT NULL DEFAULT '{}',
+    password_changed_at TIMESTAMPTZ,
+    email_verified BOOLEAN NOT NULL DEFAULT FALSE,
+    pending_email TEXT,
+    oidc_subject TEXT,
+    ldap_dn TEXT,
+    disabled BOOLEAN NOT NULL DEFAULT FALSE,
+    suspension_reason TEXT,
+    suspended_at TIMESTAMPTZ,
+    suspension_expires_at TIMESTAMPTZ,
+    suspended_by CHAR(24),
+    deleted_at TIMESTAMPTZ,
+    category TEXT NOT NULL DEFAULT 'public'
+        CHECK (category IN ('student', 'staff', 'public', 'child')),
+    membership_started_at TIMESTAMPTZ,
+    membership_expires_at TIMESTAMPTZ,
+    guardian_id CHAR(24) REFERENCES users (id) ON DELETE SET NULL,
+    loan_limit BIGINT CHECK (loan_limit >= 0),
+    family_loan_cap BIGINT CHECK (family_loan_cap >= 0),
+    is_admin BOOLEAN NOT NULL DEFAULT FALSE,
+    token_version INTEGER NOT NULL DEFAULT 0,
+    CHECK ((suspended_at IS NULL) = (suspended_by IS NULL))
+);
+
+CREATE INDEX users_email_idx ON users (email);
+CREATE INDEX users_oidc_subject_idx ON users (oidc_subject) WHERE oidc_subject IS NOT NULL;
+CREATE INDEX users_ldap_dn_idx ON users (ldap_dn) WHERE ldap_dn IS NOT NULL;
+CREATE INDEX users_guardian_id_idx ON users (guardian_id) WHERE guardian_id IS NOT NULL;
+CREATE INDEX users_deleted_at_idx ON users (deleted_at) WHERE deleted_at IS NOT NULL;
+
+CREATE TABLE books (
+    id CHAR(24) PRIMARY KEY,
+    title TEXT NOT NULL,
+    author TEXT NOT NULL,
+    stock INTEGER NOT NULL DEFAULT 0 CHECK (stock >= 0),
+    UNIQUE (title, author)
+);
+
+CREATE INDEX books_author_idx ON books (author);
+
+-- One row per book a user currently holds. Deleting either side ends the loan.
+CREATE TABLE loans (
+    user_id CHAR(24) NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    book_id CHAR(24) NOT NULL REFERENCES books (id) ON DELETE CASCADE,
+    seq BIGINT GENERATED ALWAYS AS IDENTITY,
+    borrowed_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (user_id, book_id)
+);
+
+CREATE INDEX loans_book_id_idx ON loans (book_id);
+
+CREATE TABLE api_keys (
+    id CHAR(24) PRIMARY KEY,
+    user_id CHAR(24) NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    key_hash TEXT NOT NULL UNIQUE,
+    prefix TEXT NOT NULL,
+    scopes TEXT[] NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL,
+    expires_at TIMESTAMPTZ,
+    last_used_at TIMESTAMPTZ
+);
+
+CREATE INDEX api_keys_user_id_idx ON api_keys (user_id);
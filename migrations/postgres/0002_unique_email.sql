@@ -0,0 +1,3 @@
+-- Each address belongs to one account. Managed dependents have an empty email, so
+-- only non-empty addresses are covered.
+CREATE UNIQUE INDEX users_email_key ON users (email) WHERE email <> '';
@@ -0,0 +1,106 @@
+-- Ids are the hex form of the ObjectIds used by the rest of the application,
+-- timestamps are milliseconds since the Unix epoch and lists are JSON arrays.
+
+CREATE TABLE users (
+    id TEXT PRIMARY KEY,
+    email TEXT NOT NULL,
+    username TEXT NOT NULL,
+    password_hash TEXT NOT NULL,
+    password_history TEXT NOT NULL DEFAULT '[]',
+    password_changed_at INTEGER,
+    email_verified INTEGER NOT NULL DEFAULT 0,
+    pending_email TEXT,
+    oidc_subject TEXT,
+    ldap_dn TEXT,
+    disabled INTEGER NOT NULL DEFAULT 0,
+    suspension_reason TEXT,
+    suspended_at INTEGER,
+    suspension_expires_at INTEGER,
+    suspended_by TEXT,
+    deleted_at INTEGER,
+    category TEXT NOT NULL DEFAULT 'public'
+        CHECK (category IN ('student', 'staff', 'public', 'child')),
+    membership_started_at INTEGER,
+    membership_expires_at INTEGER,
+    guardian_id TEXT REFERENCES users (id) ON DELETE SET NULL,
+    loan_limit INTEGER CHECK (loan_limit >= 0),
+    family_loan_cap INTEGER CHECK (family_loan_cap >= 0),
+    is_admin INTEGER NOT NULL DEFAULT 0,
+    token_version INTEGER NOT NULL DEFAULT 0,
+    CHECK ((suspended_at IS NULL) = (suspended_by IS NULL))
+);
+
+CREATE INDEX users_email_idx ON users (email);
+CREATE INDEX users_oidc_subject_idx ON users (oidc_subject) WHERE oidc_subject IS NOT NULL;
+CREATE INDEX users_ldap_dn_idx ON users (ldap_dn) WHERE ldap_dn IS NOT NULL;
+CREATE INDEX users_guardian_id_idx ON users (guardian_id) WHERE guardian_id IS NOT NULL;
+CREATE INDEX users_deleted_at_idx ON users (deleted_at) WHERE deleted_at IS NOT NULL;
+
+CREATE TABLE books (
+    id TEXT PRIMARY KEY,
+    title TEXT NOT NULL,
+    author TEXT NOT NULL,
+    stock INTEGER NOT NULL DEFAULT 0 CHECK (stock >= 0),
+    UNIQUE (title, author)
+);
+
+CREATE INDEX books_author_idx ON books (author);
+
+-- One row per book a user currently holds, in borrowing order by rowid.
+CREATE TABLE loans (
+    user_id TEXT NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    book_id TEXT NOT NULL REFERENCES books (id) ON DELETE CASCADE,
+    borrowed_at INTEGER NOT NULL,
+    PRIMARY KEY (user_id, book_id)
+);
+
+CREATE INDEX loans_book_id_idx ON loans (book_id);
+
+CREATE TABLE api_keys (
+    id TEXT PRIMARY KEY,
+    user_id TEXT NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    key_hash TEXT NOT NULL UNIQUE,
+    prefix TEXT NOT NULL,
+    scopes TEXT NOT NULL,
+    created_at INTEGER NOT NULL,
+    expires_at INTEGER,
+    last_used_at INTEGER
+);
+
+CREATE INDEX api_keys_user_id_idx ON api_keys (user_id);
+
+-- Short-lived state kept in Redis by the other backends. Expired rows are ignored
+-- on read and swept whenever new state of the same kind is written.
+
+CREATE TABLE revoked_tokens (
+    token TEXT PRIMARY KEY,
+    expires_at INTEGER NOT NULL
+);
+
+CREATE INDEX revoked_tokens_expires_at_idx ON revoked_tokens (expires_at);
+
+CREATE TABLE password_resets (
+    token_hash TEXT PRIMARY KEY,
+    user_id TEXT NOT NULL UNIQUE,
+    expires_at INTEGER NOT NULL
+);
+
+CREATE TABLE email_verifications (
+    token_hash TEXT PRIMARY KEY,
+    user_id TEXT NOT NULL,
+    email TEXT NOT NULL,
+    expires_at INTEGER NOT NULL
+);
+
+CREATE TABLE email_verification_cooldowns (
+    user_id TEXT PRIMARY KEY,
+    expires_at INTEGER NOT NULL
+);
+
+CREATE TABLE oidc_states (
+    state TEXT PRIMARY KEY,
+    pkce_verifier TEXT NOT NULL,
+    nonce TEXT NOT NULL,
+    expires_at INTEGER NOT NULL
+);
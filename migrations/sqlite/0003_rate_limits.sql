@@ -0,0 +1,11 @@
+-- Requests counted by the rate limiter when no Redis is configured, one row per
+-- request still inside its window.
+CREATE TABLE rate_limit_hits (
+    id INTEGER PRIMARY KEY,
+    key TEXT NOT NULL,
+    hit_at INTEGER NOT NULL,
+    expires_at INTEGER NOT NULL
+);
+
+CREATE INDEX rate_limit_hits_key_idx ON rate_limit_hits (key, hit_at);
+CREATE INDEX rate_limit_hits_expires_at_idx ON rate_limit_hits (expires_at);
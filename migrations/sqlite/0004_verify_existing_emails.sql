@@ -0,0 +1,5 @@
+-- Every account present at upgrade time keeps the access it had and counts as
+-- verified, like the MongoDB migration does for accounts stored before addresses were
+-- verified. Anonymized accounts and pending address changes are left alone.
+UPDATE users SET email_verified = TRUE
+WHERE NOT email_verified AND email <> '' AND pending_email IS NULL;
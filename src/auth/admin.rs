@@ -1,6 +1,10 @@
+use crate::auth::api_key::{authenticate_api_key, extract_api_key};
+use crate::auth::session::extract_session_token;
+use crate::auth::status::ensure_account_active;
 use crate::config::app_config::AppConfig;
-use crate::constants::{AUTH_REQUIRED, PERMISSION_DENIED};
-use crate::database::mongodb::UserRepository;
+use crate::constants::{AUTH_REQUIRED, PERMISSION_DENIED, TOKEN_BLACKLISTED};
+use crate::database::repository::{ApiKeyRepository, UserRepository};
+use crate::database::store::TokenBlacklist;
 use crate::errors::AppError;
 use crate::utils::token::decode_token;
 use actix_web::dev::Payload;
@@ -11,7 +15,9 @@ use std::future::Future;
 use std::pin::Pin;
 
 #[derive(Clone)]
-pub struct AdminUser {}
+pub struct AdminUser {
+    pub user_id: String,
+}
 
 impl FromRequest for AdminUser {
     type Error = ActixError;
@@ -23,25 +29,50 @@ impl FromRequest for AdminUser {
             None => return Box::pin(async { Err(AppError::Internal.into()) }),
         };
 
-        let repo = match req.app_data::<Data<UserRepository>>().cloned() {
+        let repo = match req.app_data::<Data<dyn UserRepository>>().cloned() {
             Some(repo) => repo,
             None => return Box::pin(async { Err(AppError::Internal.into()) }),
         };
 
-        let token = match req
-            .headers()
-            .get("Authorization")
-            .and_then(|h| h.to_str().ok())
-        {
-            Some(h) if h.starts_with("Bearer") => h.trim_start_matches("Bearer").trim(),
-            _ => {
+        if let Some(api_key) = extract_api_key(req) {
+            let key_repo = match req.app_data::<Data<dyn ApiKeyRepository>>().cloned() {
+                Some(key_repo) => key_repo,
+                None => return Box::pin(async { Err(AppError::Internal.into()) }),
+            };
+            let method = req.method().clone();
+            let path = req.path().to_string();
+
+            return Box::pin(async move {
+                let user = authenticate_api_key(
+                    &method,
+                    &path,
+                    &api_key,
+                    repo.get_ref(),
+                    key_repo.get_ref(),
+                )
+                .await?;
+
+                if !user.is_admin {
+                    return Err(AppError::Forbidden(PERMISSION_DENIED.into()).into());
+                }
+
+                Ok(AdminUser {
+                    user_id: user.id.to_hex(),
+                })
+            });
+        }
+
+        let token = match extract_session_token(req, &cfg) {
+            Ok(Some(token)) => token,
+            Ok(None) => {
                 return Box::pin(async {
                     Err(AppError::Unauthorized(AUTH_REQUIRED.into()).into())
                 });
             }
+            Err(e) => return Box::pin(async { Err(e.into()) }),
         };
 
-        let claims = match decode_token(&cfg, token) {
+        let claims = match decode_token(&cfg, &token) {
             Ok(c) => c,
             Err(_) => {
                 return Box::pin(async {
@@ -51,8 +82,15 @@ impl FromRequest for AdminUser {
         };
 
         let user_id = claims.sub.clone();
+        let blacklist = req.app_data::<Data<dyn TokenBlacklist>>().cloned();
 
         Box::pin(async move {
+            if let Some(bl) = blacklist {
+                if bl.is_blacklisted(&token).await? {
+                    return Err(AppError::Unauthorized(TOKEN_BLACKLISTED.into()).into());
+                }
+            }
+
             let object_id = ObjectId::parse_str(&user_id)
                 .map_err(|_| AppError::Unauthorized(AUTH_REQUIRED.into()))?;
 
@@ -66,11 +104,14 @@ impl FromRequest for AdminUser {
                 return Err(AppError::Unauthorized(AUTH_REQUIRED.into()).into());
             }
 
-            if !user.is_admin {
+            ensure_account_active(&user)?;
+
+            // An impersonation token never carries admin rights, even for an admin subject.
+            if !user.is_admin || claims.act.is_some() {
                 return Err(AppError::Forbidden(PERMISSION_DENIED.into()).into());
             }
 
-            Ok(AdminUser {})
+            Ok(AdminUser { user_id })
         })
     }
 }
@@ -0,0 +1,118 @@
+use crate::auth::status::ensure_request_allowed;
+use crate::constants::{
+    API_KEY_HEADER, API_KEY_NOT_ALLOWED, API_KEY_PREFIX, API_KEY_SCOPE_MISSING, AUTH_REQUIRED,
+    INVALID_API_KEY, SCOPE_CATALOG_READ, SCOPE_CATALOG_WRITE, SCOPE_CIRCULATION_WRITE,
+    SCOPE_PROFILE_READ, SCOPE_PROFILE_WRITE, SCOPE_USERS_READ, SCOPE_USERS_WRITE,
+};
+use crate::database::repository::{ApiKeyRepository, UserRepository};
+use crate::errors::AppError;
+use crate::models::user::User;
+use crate::utils::random_token::hash_random_token;
+use actix_web::http::Method;
+use actix_web::HttpRequest;
+use mongodb::bson::DateTime;
+
+/// Reads an API key from `X-API-Key`, or from a bearer token carrying the key prefix.
+pub(crate) fn extract_api_key(req: &HttpRequest) -> Option<String> {
+    if let Some(key) = req
+        .headers()
+        .get(API_KEY_HEADER)
+        .and_then(|h| h.to_str().ok())
+    {
+        return Some(key.trim().to_string());
+    }
+
+    req.headers()
+        .get("Authorization")
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Bearer "))
+        .map(str::trim)
+        .filter(|token| token.starts_with(API_KEY_PREFIX))
+        .map(str::to_string)
+}
+
+/// The scope an API key needs for a request. `None` means the route only accepts
+/// session tokens: credentials, email, account deletion, API key management,
+/// impersonation and logout.
+fn required_scope(method: &Method, path: &str) -> Option<&'static str> {
+    let read = method == Method::GET;
+
+    if (path == "/user/me" && method == Method::DELETE)
+        || path.contains("/api-keys")
+        || path.ends_with("/impersonate")
+        || path == "/user/password"
+        || path.starts_with("/user/email")
+    {
+        return None;
+    }
+
+    // Borrowing for a dependent is circulation, not a profile change.
+    if path.starts_with("/user/me/dependents/")
+        && (path.contains("/borrow/") || path.contains("/return/"))
+    {
+        return Some(SCOPE_CIRCULATION_WRITE);
+    }
+
+    if path.starts_with("/user/") {
+        return Some(if read { SCOPE_PROFILE_READ } else { SCOPE_PROFILE_WRITE });
+    }
+
+    if read && (path == "/books" || path.starts_with("/books/")) {
+        return Some(SCOPE_CATALOG_READ);
+    }
+
+    if path.starts_with("/books/borrow/") || path.starts_with("/books/return/") {
+        return Some(SCOPE_CIRCULATION_WRITE);
+    }
+
+    if path.starts_with("/admin/books") {
+        return Some(SCOPE_CATALOG_WRITE);
+    }
+
+    if path.starts_with("/admin/users") || path.starts_with("/admin/memberships") {
+        return Some(if read { SCOPE_USERS_READ } else { SCOPE_USERS_WRITE });
+    }
+
+    None
+}
+
+/// Resolves an API key to its owner, checking expiry and that the key carries the
+/// scope the request needs. Records the time of use on success.
+pub(crate) async fn authenticate_api_key(
+    method: &Method,
+    path: &str,
+    raw_key: &str,
+    user_repo: &dyn UserRepository,
+    key_repo: &dyn ApiKeyRepository,
+) -> Result<User, AppError> {
+    let key = key_repo
+        .find_by_hash(&hash_random_token(raw_key))
+        .await
+        .map_err(|_| AppError::Unauthorized(AUTH_REQUIRED.into()))?
+        .ok_or_else(|| AppError::Unauthorized(INVALID_API_KEY.into()))?;
+
+    if key.expires_at.is_some_and(|exp| exp < DateTime::now()) {
+        return Err(AppError::Unauthorized(INVALID_API_KEY.into()));
+    }
+
+    let scope = required_scope(method, path)
+        .ok_or_else(|| AppError::Forbidden(API_KEY_NOT_ALLOWED.into()))?;
+
+    if !key.scopes.iter().any(|s| s == scope) {
+        return Err(AppError::Forbidden(API_KEY_SCOPE_MISSING.into()));
+    }
+
+    let user = user_repo
+        .find_by_id(&key.user_id)
+        .await
+        .map_err(|_| AppError::Unauthorized(AUTH_REQUIRED.into()))?
+        .ok_or_else(|| AppError::Unauthorized(INVALID_API_KEY.into()))?;
+
+    ensure_request_allowed(&user, method, path)?;
+
+    if let Err(e) = key_repo.touch_last_used(&key.id).await {
+        tracing::warn!("failed to record api key usage: {:?}", e);
+    }
+
+    Ok(user)
+}
@@ -0,0 +1,36 @@
+use crate::auth::api_key::{authenticate_api_key, extract_api_key};
+use crate::database::repository::{ApiKeyRepository, UserRepository};
+use crate::errors::AppError;
+use actix_web::dev::Payload;
+use actix_web::web::Data;
+use actix_web::{Error as ActixError, FromRequest, HttpRequest};
+use std::future::Future;
+use std::pin::Pin;
+
+/// Guards the public catalogue. Anonymous requests pass, but an API key that is
+/// presented must be valid and carry `catalog:read`.
+pub struct CatalogReader;
+
+impl FromRequest for CatalogReader {
+    type Error = ActixError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, ActixError>>>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let repo = req.app_data::<Data<dyn UserRepository>>().cloned();
+        let key_repo = req.app_data::<Data<dyn ApiKeyRepository>>().cloned();
+        let api_key = extract_api_key(req);
+        let method = req.method().clone();
+        let path = req.path().to_string();
+
+        Box::pin(async move {
+            let Some(api_key) = api_key else {
+                return Ok(CatalogReader);
+            };
+            let repo = repo.ok_or(AppError::Internal)?;
+            let key_repo = key_repo.ok_or(AppError::Internal)?;
+            authenticate_api_key(&method, &path, &api_key, repo.get_ref(), key_repo.get_ref())
+                .await?;
+            Ok(CatalogReader)
+        })
+    }
+}
@@ -0,0 +1,277 @@
+use crate::config::app_config::LdapConfig;
+use crate::constants::LDAP_TIMEOUT_SECONDS;
+use crate::database::repository::UserRepository;
+use crate::errors::AppError;
+use ldap3::{ldap_escape, Ldap, LdapConnAsync, LdapConnSettings, LdapError, Scope, SearchEntry};
+use rustls::{ClientConfig, RootCertStore};
+use rustls_pemfile::certs;
+use std::fs::File;
+use std::io::BufReader;
+use std::sync::Arc;
+use std::time::Duration;
+
+const LDAP_SUCCESS: u32 = 0;
+const LDAP_NO_SUCH_OBJECT: u32 = 32;
+const LDAP_INVALID_CREDENTIALS: u32 = 49;
+
+/// Attributes read from a directory entry.
+pub struct LdapIdentity {
+    pub dn: String,
+    pub username: Option<String>,
+    pub email: String,
+    pub is_admin: bool,
+}
+
+pub enum LdapLogin {
+    Authenticated(LdapIdentity),
+    /// No directory entry matches, so the login may fall back to a local account.
+    UnknownUser,
+    /// The entry exists but the bind failed, or the match was ambiguous.
+    Rejected,
+    /// The directory could not be reached or answered with an error.
+    Unavailable,
+}
+
+/// LDAP / Active Directory authentication using search-then-bind.
+#[derive(Clone)]
+pub struct LdapDirectory {
+    cfg: LdapConfig,
+    tls_config: Option<Arc<ClientConfig>>,
+}
+
+impl LdapDirectory {
+    pub fn new(cfg: &LdapConfig) -> Result<Self, String> {
+        let tls_config = match cfg.ca_cert_path {
+            Some(ref path) => Some(Arc::new(load_ca_config(path)?)),
+            None => None,
+        };
+
+        Ok(Self {
+            cfg: cfg.clone(),
+            tls_config,
+        })
+    }
+
+    async fn connect(&self) -> Result<Ldap, LdapError> {
+        let mut settings = LdapConnSettings::new()
+            .set_conn_timeout(Duration::from_secs(LDAP_TIMEOUT_SECONDS))
+            .set_starttls(self.cfg.starttls);
+        if let Some(ref tls_config) = self.tls_config {
+            settings = settings.set_config(tls_config.clone());
+        }
+
+        let (conn, mut ldap) = LdapConnAsync::with_settings(settings, &self.cfg.url).await?;
+        ldap3::drive!(conn);
+
+        if let (Some(dn), Some(password)) = (&self.cfg.bind_dn, &self.cfg.bind_password) {
+            ldap.with_timeout(timeout())
+                .simple_bind(dn, password)
+                .await?
+                .success()?;
+        }
+
+        Ok(ldap)
+    }
+
+    fn attributes(&self) -> Vec<&str> {
+        vec![
+            &self.cfg.username_attribute,
+            &self.cfg.email_attribute,
+            &self.cfg.group_attribute,
+        ]
+    }
+
+    fn identity(&self, entry: SearchEntry, fallback_email: &str) -> LdapIdentity {
+        let first = |name: &str| attribute(&entry, name).and_then(|v| v.first().cloned());
+        let is_admin = attribute(&entry, &self.cfg.group_attribute).is_some_and(|groups| {
+            groups.iter().any(|group| {
+                self.cfg
+                    .admin_groups
+                    .iter()
+                    .any(|admin| admin.eq_ignore_ascii_case(group))
+            })
+        });
+
+        LdapIdentity {
+            username: first(&self.cfg.username_attribute),
+            email: first(&self.cfg.email_attribute).unwrap_or_else(|| fallback_email.into()),
+            is_admin,
+            dn: entry.dn,
+        }
+    }
+
+    /// Finds the entry for an email address with the service account, then binds as
+    /// that entry with the supplied password.
+    pub async fn authenticate(&self, email: &str, password: &str) -> LdapLogin {
+        // An empty password turns a simple bind into an unauthenticated bind,
+        // which most servers accept.
+        if password.is_empty() {
+            return LdapLogin::Rejected;
+        }
+
+        let mut ldap = match self.connect().await {
+            Ok(ldap) => ldap,
+            Err(e) => {
+                tracing::warn!("LDAP connection failed: {}", e);
+                return LdapLogin::Unavailable;
+            }
+        };
+
+        let filter = self.cfg.user_filter.replace("{email}", &ldap_escape(email));
+        let search = ldap
+            .with_timeout(timeout())
+            .search(
+                &self.cfg.base_dn,
+                Scope::Subtree,
+                &filter,
+                self.attributes(),
+            )
+            .await
+            .and_then(|result| result.success());
+
+        let mut entries = match search {
+            Ok((entries, _)) => entries,
+            Err(e) => {
+                tracing::warn!("LDAP user search failed: {}", e);
+                return LdapLogin::Unavailable;
+            }
+        };
+
+        if entries.len() > 1 {
+            tracing::warn!(
+                "LDAP filter matched {} entries for one login",
+                entries.len()
+            );
+            return LdapLogin::Rejected;
+        }
+
+        let entry = match entries.pop() {
+            Some(entry) => SearchEntry::construct(entry),
+            None => return LdapLogin::UnknownUser,
+        };
+
+        let outcome = match ldap
+            .with_timeout(timeout())
+            .simple_bind(&entry.dn, password)
+            .await
+        {
+            Ok(result) if result.rc == LDAP_SUCCESS => {
+                LdapLogin::Authenticated(self.identity(entry, email))
+            }
+            Ok(result) if result.rc == LDAP_INVALID_CREDENTIALS => LdapLogin::Rejected,
+            Ok(result) => {
+                tracing::warn!("LDAP bind for {} failed: {}", entry.dn, result);
+                LdapLogin::Unavailable
+            }
+            Err(e) => {
+                tracing::warn!("LDAP bind for {} failed: {}", entry.dn, e);
+                LdapLogin::Unavailable
+            }
+        };
+
+        let _ = ldap.unbind().await;
+        outcome
+    }
+
+    /// Reads an entry by DN. `Ok(None)` means the directory no longer has it.
+    async fn lookup(&self, ldap: &mut Ldap, dn: &str) -> Result<Option<LdapIdentity>, LdapError> {
+        let result = ldap
+            .with_timeout(timeout())
+            .search(dn, Scope::Base, "(objectClass=*)", self.attributes())
+            .await?;
+
+        if result.1.rc == LDAP_NO_SUCH_OBJECT {
+            return Ok(None);
+        }
+
+        let (mut entries, _) = result.success()?;
+        Ok(entries
+            .pop()
+            .map(|entry| self.identity(SearchEntry::construct(entry), "")))
+    }
+
+    /// Disables local accounts whose directory entry has been removed and applies
+    /// group membership changes to the others.
+    pub async fn sync_users(&self, user_repo: &dyn UserRepository) -> Result<(), AppError> {
+        let users = user_repo.find_ldap_users().await?;
+        if users.is_empty() {
+            return Ok(());
+        }
+
+        let mut ldap = self.connect().await.map_err(|e| {
+            tracing::error!("LDAP sync could not connect: {}", e);
+            AppError::Internal
+        })?;
+
+        for user in users {
+            let dn = match user.ldap_dn {
+                Some(ref dn) => dn,
+                None => continue,
+            };
+
+            // Any error other than a missing entry stops the sync, so an outage
+            // never disables accounts.
+            let identity = self.lookup(&mut ldap, dn).await.map_err(|e| {
+                tracing::error!("LDAP sync lookup of {} failed: {}", dn, e);
+                AppError::Internal
+            })?;
+
+            match identity {
+                None => {
+                    user_repo.disable(&user.id).await?;
+                    tracing::info!("disabled user {} removed from the directory", user.id);
+                }
+                Some(identity) if identity.is_admin != user.is_admin => {
+                    user_repo.set_admin(&user.id, identity.is_admin).await?;
+                }
+                Some(_) => {}
+            }
+        }
+
+        let _ = ldap.unbind().await;
+        Ok(())
+    }
+}
+
+/// Runs the directory sync forever at the given interval.
+pub async fn run_directory_sync(
+    directory: LdapDirectory,
+    user_repo: Arc<dyn UserRepository>,
+    interval_seconds: u64,
+) {
+    let mut interval = actix_web::rt::time::interval(Duration::from_secs(interval_seconds));
+    loop {
+        interval.tick().await;
+        if let Err(e) = directory.sync_users(user_repo.as_ref()).await {
+            tracing::error!("LDAP sync failed: {:?}", e);
+        }
+    }
+}
+
+fn timeout() -> Duration {
+    Duration::from_secs(LDAP_TIMEOUT_SECONDS)
+}
+
+/// Attribute names are case-insensitive in LDAP, but servers echo them in their own case.
+fn attribute<'a>(entry: &'a SearchEntry, name: &str) -> Option<&'a Vec<String>> {
+    entry
+        .attrs
+        .iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case(name))
+        .map(|(_, values)| values)
+}
+
+fn load_ca_config(path: &str) -> Result<ClientConfig, String> {
+    let file = File::open(path).map_err(|e| format!("failed to read {}: {}", path, e))?;
+    let mut roots = RootCertStore::empty();
+    for cert in certs(&mut BufReader::new(file)) {
+        let cert = cert.map_err(|e| format!("invalid certificate in {}: {}", path, e))?;
+        roots
+            .add(cert)
+            .map_err(|e| format!("invalid certificate in {}: {}", path, e))?;
+    }
+
+    Ok(ClientConfig::builder()
+        .with_root_certificates(roots)
+        .with_no_client_auth())
+}
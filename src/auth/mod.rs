@@ -1,5 +1,20 @@
 mod admin;
+mod api_key;
+mod catalog;
+mod ldap;
+mod oidc;
+mod session;
+mod status;
 mod user;
 
 pub use admin::AdminUser;
+pub(crate) use api_key::extract_api_key;
+pub use catalog::CatalogReader;
+pub use ldap::{run_directory_sync, LdapDirectory, LdapIdentity, LdapLogin};
+pub use oidc::OidcProvider;
+pub use session::{
+    clear_oidc_state_cookie, clear_session_cookies, issue_session, set_oidc_state_cookie,
+};
+pub(crate) use session::{constant_time_eq, extract_session_token, oidc_state_matches};
+pub use status::{ensure_account_active, ensure_login_allowed};
 pub use user::AuthenticatedUser;
@@ -0,0 +1,164 @@
+use crate::config::app_config::OidcConfig;
+use crate::constants::{OIDC_EMAIL_NOT_VERIFIED, OIDC_LOGIN_FAILED};
+use crate::errors::AppError;
+use crate::utils::telemetry::inject_trace_context;
+use openidconnect::core::{CoreAuthenticationFlow, CoreClient, CoreProviderMetadata};
+use openidconnect::{
+    reqwest, AsyncHttpClient, AuthorizationCode, ClientId, ClientSecret, CsrfToken,
+    EndpointMaybeSet, EndpointNotSet, EndpointSet, HttpRequest, IssuerUrl, Nonce,
+    PkceCodeChallenge, PkceCodeVerifier, RedirectUrl, Scope, TokenResponse,
+};
+
+type DiscoveredClient = CoreClient<
+    EndpointSet,
+    EndpointNotSet,
+    EndpointNotSet,
+    EndpointNotSet,
+    EndpointMaybeSet,
+    EndpointMaybeSet,
+>;
+
+/// Where to send the browser, plus the secrets that must come back with the callback.
+pub struct OidcLoginStart {
+    pub authorize_url: String,
+    pub state: String,
+    pub pkce_verifier: String,
+    pub nonce: String,
+}
+
+/// Identity asserted by a validated ID token.
+pub struct OidcIdentity {
+    pub subject: String,
+    pub email: String,
+    pub preferred_username: Option<String>,
+}
+
+/// OpenID Connect relying party using the authorization code flow with PKCE.
+#[derive(Clone)]
+pub struct OidcProvider {
+    issuer_url: IssuerUrl,
+    client_id: ClientId,
+    client_secret: Option<ClientSecret>,
+    redirect_url: RedirectUrl,
+    scopes: Vec<String>,
+    http_client: reqwest::Client,
+}
+
+impl OidcProvider {
+    pub fn new(cfg: &OidcConfig) -> Result<Self, String> {
+        let http_client = reqwest::ClientBuilder::new()
+            // Following redirects would open the client up to SSRF.
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .map_err(|e| format!("failed to build OIDC http client: {}", e))?;
+
+        Ok(Self {
+            issuer_url: IssuerUrl::new(cfg.issuer_url.clone())
+                .map_err(|e| format!("invalid OIDC issuer url: {}", e))?,
+            client_id: ClientId::new(cfg.client_id.clone()),
+            client_secret: cfg.client_secret.clone().map(ClientSecret::new),
+            redirect_url: RedirectUrl::new(cfg.redirect_url.clone())
+                .map_err(|e| format!("invalid OIDC redirect url: {}", e))?,
+            scopes: cfg.scopes.clone(),
+            http_client,
+        })
+    }
+
+    /// Sends a request to the provider as part of the current trace.
+    fn traced_http_client(&self) -> impl for<'c> AsyncHttpClient<'c> {
+        let http_client = self.http_client.clone();
+        move |mut request: HttpRequest| {
+            inject_trace_context(request.headers_mut());
+            let http_client = http_client.clone();
+            async move { http_client.call(request).await }
+        }
+    }
+
+    /// Fetches the provider metadata and signing keys. Done per login so that key
+    /// rotation at the identity provider is picked up without a restart.
+    async fn client(&self) -> Result<DiscoveredClient, AppError> {
+        let metadata = CoreProviderMetadata::discover_async(
+            self.issuer_url.clone(),
+            &self.traced_http_client(),
+        )
+        .await
+        .map_err(|e| {
+            tracing::error!("OIDC discovery failed: {:?}", e);
+            AppError::Internal
+        })?;
+
+        Ok(CoreClient::from_provider_metadata(
+            metadata,
+            self.client_id.clone(),
+            self.client_secret.clone(),
+        )
+        .set_redirect_uri(self.redirect_url.clone()))
+    }
+
+    pub async fn begin_login(&self) -> Result<OidcLoginStart, AppError> {
+        let client = self.client().await?;
+        let (pkce_challenge, pkce_verifier) = PkceCodeChallenge::new_random_sha256();
+
+        let (url, state, nonce) = client
+            .authorize_url(
+                CoreAuthenticationFlow::AuthorizationCode,
+                CsrfToken::new_random,
+                Nonce::new_random,
+            )
+            .add_scopes(self.scopes.iter().cloned().map(Scope::new))
+            .set_pkce_challenge(pkce_challenge)
+            .url();
+
+        Ok(OidcLoginStart {
+            authorize_url: url.to_string(),
+            state: state.secret().clone(),
+            pkce_verifier: pkce_verifier.secret().clone(),
+            nonce: nonce.secret().clone(),
+        })
+    }
+
+    /// Redeems the authorization code and validates the ID token's signature,
+    /// issuer, audience, expiry and nonce. Only verified email addresses are accepted.
+    pub async fn complete_login(
+        &self,
+        code: &str,
+        pkce_verifier: &str,
+        nonce: &str,
+    ) -> Result<OidcIdentity, AppError> {
+        let login_failed = || AppError::Unauthorized(OIDC_LOGIN_FAILED.into());
+        let client = self.client().await?;
+
+        let token_response = client
+            .exchange_code(AuthorizationCode::new(code.to_string()))
+            .map_err(|e| {
+                tracing::error!("OIDC provider has no token endpoint: {:?}", e);
+                AppError::Internal
+            })?
+            .set_pkce_verifier(PkceCodeVerifier::new(pkce_verifier.to_string()))
+            .request_async(&self.traced_http_client())
+            .await
+            .map_err(|e| {
+                tracing::warn!("OIDC code exchange failed: {:?}", e);
+                login_failed()
+            })?;
+
+        let id_token = token_response.id_token().ok_or_else(login_failed)?;
+        let claims = id_token
+            .claims(&client.id_token_verifier(), &Nonce::new(nonce.to_string()))
+            .map_err(|e| {
+                tracing::warn!("OIDC ID token rejected: {:?}", e);
+                login_failed()
+            })?;
+
+        let email = match (claims.email(), claims.email_verified()) {
+            (Some(email), Some(true)) => email.as_str().to_string(),
+            _ => return Err(AppError::Unauthorized(OIDC_EMAIL_NOT_VERIFIED.into())),
+        };
+
+        Ok(OidcIdentity {
+            subject: claims.subject().as_str().to_string(),
+            email,
+            preferred_username: claims.preferred_username().map(|u| u.as_str().to_string()),
+        })
+    }
+}
@@ -0,0 +1,157 @@
+use crate::config::app_config::{AppConfig, SessionCookieConfig};
+use crate::constants::{
+    CSRF_HEADER, CSRF_TOKEN_INVALID, OIDC_STATE_COOKIE_NAME, OIDC_STATE_TTL_SECONDS,
+};
+use crate::errors::AppError;
+use crate::models::response::Token;
+use crate::utils::random_token::generate_random_token;
+use actix_web::cookie::time::Duration;
+use actix_web::cookie::{Cookie, SameSite};
+use actix_web::http::Method;
+use actix_web::{HttpRequest, HttpResponseBuilder};
+
+/// Reads the session JWT from a bearer `Authorization` header, or from the session
+/// cookie when cookie sessions are enabled. A cookie-authenticated request that can
+/// change state must echo the CSRF cookie in the `X-CSRF-Token` header, which a
+/// cross-site page cannot read.
+pub(crate) fn extract_session_token(
+    req: &HttpRequest,
+    cfg: &AppConfig,
+) -> Result<Option<String>, AppError> {
+    if let Some(token) = req
+        .headers()
+        .get("Authorization")
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Bearer "))
+    {
+        return Ok(Some(token.trim().to_string()));
+    }
+
+    let session_cfg = match cfg.session_cookie {
+        Some(ref session_cfg) => session_cfg,
+        None => return Ok(None),
+    };
+
+    let token = match req.cookie(&session_cfg.name) {
+        Some(cookie) => cookie.value().to_string(),
+        None => return Ok(None),
+    };
+
+    let safe_method = matches!(*req.method(), Method::GET | Method::HEAD | Method::OPTIONS);
+    if !safe_method {
+        let header = req.headers().get(CSRF_HEADER).and_then(|h| h.to_str().ok());
+        let cookie = req.cookie(&session_cfg.csrf_name);
+
+        match (header, cookie) {
+            (Some(header), Some(cookie)) if constant_time_eq(header, cookie.value()) => {}
+            _ => return Err(AppError::Forbidden(CSRF_TOKEN_INVALID.into())),
+        }
+    }
+
+    Ok(Some(token))
+}
+
+/// Hands a newly issued token to the client. With cookie sessions it goes into the
+/// HttpOnly cookie, next to a fresh CSRF cookie, and is kept out of the response body
+/// where scripts could read it. Otherwise it is returned for the body.
+pub fn issue_session(
+    builder: &mut HttpResponseBuilder,
+    cfg: &AppConfig,
+    token: String,
+) -> Option<Token> {
+    let Some(ref session_cfg) = cfg.session_cookie else {
+        return Some(Token { token });
+    };
+
+    let max_age = Duration::hours(cfg.jwt_exp_hours);
+    builder.cookie(cookie(session_cfg, &session_cfg.name, token, true, max_age));
+    builder.cookie(cookie(
+        session_cfg,
+        &session_cfg.csrf_name,
+        generate_random_token(),
+        false,
+        max_age,
+    ));
+    None
+}
+
+/// Expires the session and CSRF cookies. Does nothing unless cookie sessions are enabled.
+pub fn clear_session_cookies(builder: &mut HttpResponseBuilder, cfg: &AppConfig) {
+    if let Some(ref session_cfg) = cfg.session_cookie {
+        for name in [&session_cfg.name, &session_cfg.csrf_name] {
+            builder.cookie(cookie(
+                session_cfg,
+                name,
+                String::new(),
+                true,
+                Duration::ZERO,
+            ));
+        }
+    }
+}
+
+/// Ties an OIDC login to the browser that started it. The callback only accepts a
+/// state that matches this cookie, so a victim cannot be signed in to someone
+/// else's account with a forged callback link.
+pub fn set_oidc_state_cookie(builder: &mut HttpResponseBuilder, cfg: &AppConfig, state: &str) {
+    builder.cookie(oidc_state_cookie(
+        cfg,
+        state.to_string(),
+        Duration::seconds(OIDC_STATE_TTL_SECONDS),
+    ));
+}
+
+/// Expires the OIDC state cookie once the callback has used it.
+pub fn clear_oidc_state_cookie(builder: &mut HttpResponseBuilder, cfg: &AppConfig) {
+    builder.cookie(oidc_state_cookie(cfg, String::new(), Duration::ZERO));
+}
+
+/// Whether the request carries the OIDC state cookie for `state`.
+pub(crate) fn oidc_state_matches(req: &HttpRequest, state: &str) -> bool {
+    req.cookie(OIDC_STATE_COOKIE_NAME)
+        .is_some_and(|cookie| constant_time_eq(cookie.value(), state))
+}
+
+fn oidc_state_cookie(cfg: &AppConfig, value: String, max_age: Duration) -> Cookie<'static> {
+    // Lax, because the provider's redirect back to the callback is a cross-site GET.
+    let secure = cfg
+        .oidc
+        .as_ref()
+        .is_some_and(|oidc| oidc.redirect_url.starts_with("https://"));
+    Cookie::build(OIDC_STATE_COOKIE_NAME, value)
+        .path("/")
+        .secure(secure)
+        .http_only(true)
+        .same_site(SameSite::Lax)
+        .max_age(max_age)
+        .finish()
+}
+
+fn cookie(
+    session_cfg: &SessionCookieConfig,
+    name: &str,
+    value: String,
+    http_only: bool,
+    max_age: Duration,
+) -> Cookie<'static> {
+    let mut cookie = Cookie::build(name.to_string(), value)
+        .path("/")
+        .secure(session_cfg.secure)
+        .http_only(http_only)
+        .same_site(session_cfg.same_site)
+        .max_age(max_age)
+        .finish();
+
+    if let Some(ref domain) = session_cfg.domain {
+        cookie.set_domain(domain.clone());
+    }
+    cookie
+}
+
+pub(crate) fn constant_time_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0u8, |acc, (x, y)| acc | (x ^ y))
+            == 0
+}
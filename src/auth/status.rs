@@ -0,0 +1,50 @@
+use crate::constants::{ACCOUNT_DISABLED, ACCOUNT_SUSPENDED, AUTH_REQUIRED};
+use crate::errors::AppError;
+use crate::models::user::User;
+use actix_web::http::Method;
+
+/// Rejects accounts that are deleted, disabled or under an active suspension.
+pub fn ensure_account_active(user: &User) -> Result<(), AppError> {
+    check_account(user, false)
+}
+
+/// Rejects deleted and disabled accounts at sign-in. Suspended patrons still get a
+/// session, which [`ensure_request_allowed`] then limits.
+pub fn ensure_login_allowed(user: &User) -> Result<(), AppError> {
+    check_account(user, true)
+}
+
+/// Like [`ensure_account_active`], but lets a suspended patron view their profile
+/// and return books, their dependents' included, so a suspension never traps
+/// loaned copies.
+pub(crate) fn ensure_request_allowed(
+    user: &User,
+    method: &Method,
+    path: &str,
+) -> Result<(), AppError> {
+    let allowed_while_suspended = (method == Method::GET && path == "/user/me")
+        || (method == Method::POST
+            && (path.starts_with("/books/return/") || is_dependent_return(path)));
+    check_account(user, allowed_while_suspended)
+}
+
+fn is_dependent_return(path: &str) -> bool {
+    path.starts_with("/user/me/dependents/") && path.contains("/return/")
+}
+
+fn check_account(user: &User, allow_suspended: bool) -> Result<(), AppError> {
+    if user.deleted_at.is_some() {
+        return Err(AppError::Unauthorized(AUTH_REQUIRED.into()));
+    }
+
+    if user.disabled {
+        return Err(AppError::Forbidden(ACCOUNT_DISABLED.into()));
+    }
+
+    match user.suspension {
+        Some(ref suspension) if suspension.is_active() && !allow_suspended => Err(
+            AppError::Forbidden(format!("{}: {}", ACCOUNT_SUSPENDED, suspension.reason)),
+        ),
+        _ => Ok(()),
+    }
+}
@@ -1,7 +1,10 @@
+use crate::auth::api_key::{authenticate_api_key, extract_api_key};
+use crate::auth::session::extract_session_token;
+use crate::auth::status::{ensure_account_active, ensure_request_allowed};
 use crate::config::app_config::AppConfig;
-use crate::constants::{AUTH_REQUIRED, TOKEN_BLACKLISTED};
-use crate::database::mongodb::UserRepository;
-use crate::database::redis::TokenBlacklist;
+use crate::constants::{AUTH_REQUIRED, NOT_ALLOWED_WHILE_IMPERSONATING, TOKEN_BLACKLISTED};
+use crate::database::repository::{ApiKeyRepository, UserRepository};
+use crate::database::store::TokenBlacklist;
 use crate::errors::AppError;
 use crate::utils::token::decode_token;
 use actix_web::dev::Payload;
@@ -16,6 +19,20 @@ pub struct AuthenticatedUser {
     pub user_id: String,
     pub token: String,
     pub exp: usize,
+    /// When the session token was issued
+    pub iat: usize,
+    /// Id of the admin acting as this user, set for impersonation tokens
+    pub impersonator: Option<String>,
+}
+
+impl AuthenticatedUser {
+    /// Rejects actions that only the account owner may take, such as changing credentials.
+    pub fn ensure_not_impersonated(&self) -> Result<(), AppError> {
+        match self.impersonator {
+            Some(_) => Err(AppError::Forbidden(NOT_ALLOWED_WHILE_IMPERSONATING.into())),
+            None => Ok(()),
+        }
+    }
 }
 
 impl FromRequest for AuthenticatedUser {
@@ -24,19 +41,43 @@ impl FromRequest for AuthenticatedUser {
 
     fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
         let cfg = req.app_data::<Data<AppConfig>>().cloned();
-        let blacklist = req.app_data::<Data<TokenBlacklist>>().cloned();
-        let repo = req.app_data::<Data<UserRepository>>().cloned();
-        let token = req
-            .headers()
-            .get("Authorization")
-            .and_then(|h| h.to_str().ok())
-            .filter(|h| h.starts_with("Bearer "))
-            .map(|h| h.trim_start_matches("Bearer ").trim().to_string());
+        let blacklist = req.app_data::<Data<dyn TokenBlacklist>>().cloned();
+        let repo = req.app_data::<Data<dyn UserRepository>>().cloned();
+        let key_repo = req.app_data::<Data<dyn ApiKeyRepository>>().cloned();
+        let api_key = extract_api_key(req);
+        let method = req.method().clone();
+        let path = req.path().to_string();
+        let token = match cfg {
+            Some(ref cfg) => extract_session_token(req, cfg),
+            None => Ok(None),
+        };
 
         Box::pin(async move {
             let cfg = cfg.ok_or(AppError::Internal)?;
             let repo = repo.ok_or(AppError::Internal)?;
-            let token = token.ok_or(AppError::Unauthorized(AUTH_REQUIRED.into()))?;
+
+            if let Some(api_key) = api_key {
+                let key_repo = key_repo.ok_or(AppError::Internal)?;
+                let user = authenticate_api_key(
+                    &method,
+                    &path,
+                    &api_key,
+                    repo.get_ref(),
+                    key_repo.get_ref(),
+                )
+                .await?;
+
+                // API keys have no session to blacklist, so the token stays empty.
+                return Ok(AuthenticatedUser {
+                    user_id: user.id.to_hex(),
+                    token: String::new(),
+                    exp: 0,
+                    iat: 0,
+                    impersonator: None,
+                });
+            }
+
+            let token = token?.ok_or(AppError::Unauthorized(AUTH_REQUIRED.into()))?;
             let claims = decode_token(&cfg, &token)?;
 
             if let Some(bl) = blacklist {
@@ -58,10 +99,42 @@ impl FromRequest for AuthenticatedUser {
                 return Err(AppError::Unauthorized(AUTH_REQUIRED.into()).into());
             }
 
+            ensure_request_allowed(&user, &method, &path)?;
+
+            let impersonator = match claims.act {
+                Some(actor) => {
+                    // The impersonation ends as soon as the admin loses the role or is locked out.
+                    let admin_id = ObjectId::parse_str(&actor.sub)
+                        .map_err(|_| AppError::Unauthorized(AUTH_REQUIRED.into()))?;
+                    let is_admin = repo
+                        .find_by_id(&admin_id)
+                        .await
+                        .map_err(|_| AppError::Unauthorized(AUTH_REQUIRED.into()))?
+                        .is_some_and(|admin| {
+                            admin.is_admin && ensure_account_active(&admin).is_ok()
+                        });
+                    if !is_admin {
+                        return Err(AppError::Unauthorized(AUTH_REQUIRED.into()).into());
+                    }
+
+                    tracing::info!(
+                        admin_id = %actor.sub,
+                        user_id = %claims.sub,
+                        "impersonated request {} {}",
+                        method,
+                        path
+                    );
+                    Some(actor.sub)
+                }
+                None => None,
+            };
+
             Ok(AuthenticatedUser {
                 user_id: claims.sub,
                 token,
                 exp: claims.exp,
+                iat: claims.iat,
+                impersonator,
             })
         })
     }
@@ -1,82 +1,888 @@
+use crate::config::jwt_keys::JwtKeys;
+use crate::config::source::ConfigSource;
 use crate::constants::*;
-use dotenvy::dotenv;
-use std::env;
+use crate::models::user::PatronCategory;
+use actix_web::cookie::SameSite;
+use jsonwebtoken::Algorithm;
+use std::collections::HashMap;
+use std::net::IpAddr;
 use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+use tracing_subscriber::EnvFilter;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SmtpTls {
+    /// Plain connection upgraded with STARTTLS (usually port 587).
+    StartTls,
+    /// Implicit TLS from the first byte (usually port 465).
+    Tls,
+    /// No encryption, only meant for local mail catchers.
+    None,
+}
+
+impl std::str::FromStr for SmtpTls {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "starttls" => Ok(SmtpTls::StartTls),
+            "tls" => Ok(SmtpTls::Tls),
+            "none" => Ok(SmtpTls::None),
+            _ => Err(format!("{} must be one of starttls, tls, none", SMTP_TLS)),
+        }
+    }
+}
+
+/// Where users, books and loans are stored, chosen with `DATABASE_BACKEND`.
+#[derive(Clone)]
+pub enum DatabaseConfig {
+    MongoDb {
+        uri: String,
+        database: String,
+    },
+    /// Only available when built with the `postgres` feature.
+    #[cfg(feature = "postgres")]
+    Postgres { url: String, max_connections: u32 },
+    /// A single database file, only available when built with the `sqlite` feature.
+    #[cfg(feature = "sqlite")]
+    Sqlite { url: String, max_connections: u32 },
+}
+
+impl DatabaseConfig {
+    /// Whether the database can stand in for Redis, so `REDIS_URI` becomes optional.
+    pub fn has_state_store(&self) -> bool {
+        #[cfg(feature = "sqlite")]
+        if let DatabaseConfig::Sqlite { .. } = self {
+            return true;
+        }
+        false
+    }
+}
+
+/// A request budget per client: at most `requests` in any `window_seconds`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RateLimit {
+    pub requests: u32,
+    pub window_seconds: u64,
+}
+
+impl RateLimit {
+    /// Parses `requests/seconds`, e.g. `20/60`, or `off` for no limit.
+    fn parse(name: &str, value: &str) -> Result<Option<Self>, String> {
+        if value.trim().eq_ignore_ascii_case("off") {
+            return Ok(None);
+        }
+        let invalid = || format!("{} must be requests/seconds, e.g. 20/60, or off", name);
+        let (requests, window_seconds) = value.split_once('/').ok_or_else(invalid)?;
+        let limit = RateLimit {
+            requests: requests.trim().parse().map_err(|_| invalid())?,
+            window_seconds: window_seconds.trim().parse().map_err(|_| invalid())?,
+        };
+        if limit.requests == 0 || limit.window_seconds == 0 {
+            return Err(invalid());
+        }
+        Ok(Some(limit))
+    }
+}
+
+/// An address or CIDR block, e.g. `10.0.0.0/8`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IpNetwork {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl IpNetwork {
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.addr, ip.to_canonical()) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - u32::from(self.prefix)).unwrap_or(0);
+                u32::from(net) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - u32::from(self.prefix)).unwrap_or(0);
+                u128::from(net) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl std::str::FromStr for IpNetwork {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("{} is not an IP address or CIDR block", s);
+        let (addr, prefix) = match s.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (s, None),
+        };
+        let addr: IpAddr = addr.parse().map_err(|_| invalid())?;
+        let addr = addr.to_canonical();
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => prefix.parse().ok().filter(|p| *p <= max).ok_or_else(invalid)?,
+            None => max,
+        };
+        Ok(IpNetwork { addr, prefix })
+    }
+}
+
+/// Request budgets of the rate limited route groups; `None` leaves a group unlimited.
+#[derive(Clone, PartialEq)]
+pub struct RateLimitConfig {
+    pub auth: Option<RateLimit>,
+    pub book: Option<RateLimit>,
+    pub admin: Option<RateLimit>,
+    /// Clients that are never limited, such as trusted internal services
+    pub allowlist: Vec<IpNetwork>,
+    /// Take the client address from `Forwarded` or `X-Forwarded-For`. Only safe behind
+    /// a reverse proxy that overwrites those headers.
+    pub trust_forwarded_for: bool,
+}
+
+/// Browser session cookie settings, present when `SESSION_COOKIES=true`.
+#[derive(Clone)]
+pub struct SessionCookieConfig {
+    /// HttpOnly cookie carrying the session JWT
+    pub name: String,
+    /// Script-readable cookie whose value must be echoed in the CSRF header
+    pub csrf_name: String,
+    pub secure: bool,
+    /// Only `Strict` and `Lax` are accepted, as a second line of defence next to the
+    /// CORS origin list.
+    pub same_site: SameSite,
+    pub domain: Option<String>,
+}
+
+/// Rules every newly set password must satisfy.
+#[derive(Clone)]
+pub struct PasswordPolicy {
+    pub min_length: usize,
+    pub max_length: usize,
+    pub require_lowercase: bool,
+    pub require_uppercase: bool,
+    pub require_digit: bool,
+    pub require_symbol: bool,
+    /// Reject passwords containing the account's email local part or username
+    pub disallow_personal_info: bool,
+    /// Number of recent passwords, the current one included, that cannot be reused;
+    /// 0 disables the check
+    pub history_size: usize,
+    /// Days after which a password must be reset, 0 disables expiry
+    pub max_age_days: i64,
+    /// Directory of k-anonymity range files named by the first five SHA-1 hex digits
+    pub breached_passwords_dir: Option<String>,
+}
+
+/// OpenID Connect relying party settings, present when `OIDC_ISSUER_URL` is set.
+#[derive(Clone)]
+pub struct OidcConfig {
+    pub issuer_url: String,
+    pub client_id: String,
+    pub client_secret: Option<String>,
+    pub redirect_url: String,
+    pub scopes: Vec<String>,
+    /// Create accounts for unknown identities instead of rejecting them
+    pub auto_provision: bool,
+}
+
+/// Directory authentication settings, present when `LDAP_URL` is set.
+#[derive(Clone)]
+pub struct LdapConfig {
+    /// `ldap://` or `ldaps://` URL of the directory server
+    pub url: String,
+    pub starttls: bool,
+    /// PEM bundle trusted instead of the system roots
+    pub ca_cert_path: Option<String>,
+    /// Service account used for searches, anonymous when absent
+    pub bind_dn: Option<String>,
+    pub bind_password: Option<String>,
+    pub base_dn: String,
+    /// Search filter with an `{email}` placeholder for the login address
+    pub user_filter: String,
+    pub username_attribute: String,
+    pub email_attribute: String,
+    pub group_attribute: String,
+    /// Members of any of these group DNs are made administrators
+    pub admin_groups: Vec<String>,
+    /// Seconds between directory syncs, 0 disables the sync
+    pub sync_interval_seconds: u64,
+}
 
 #[derive(Clone)]
 pub struct AppConfig {
-    pub mongo_uri: String,
-    pub mongo_db: String,
-    pub redis_uri: String,
-    pub jwt_secret: String,
+    pub database: DatabaseConfig,
+    /// Apply pending schema migrations before serving; `--migrate` runs them on demand
+    pub migrate_on_startup: bool,
+    /// Unset when the embedded database keeps the short-lived state
+    pub redis_uri: Option<String>,
+    pub jwt_keys: Arc<JwtKeys>,
+    pub jwt_issuer: String,
+    pub jwt_audience: String,
+    pub argon2_params: argon2::Params,
+    pub password_policy: PasswordPolicy,
+    pub session_cookie: Option<SessionCookieConfig>,
     pub jwt_exp_hours: i64,
     pub host: String,
     pub port: u16,
     pub ssl_cert_path: Option<String>,
     pub ssl_key_path: Option<String>,
+    pub smtp_host: Option<String>,
+    pub smtp_port: u16,
+    pub smtp_username: Option<String>,
+    pub smtp_password: Option<String>,
+    pub smtp_tls: SmtpTls,
+    pub mail_from: String,
+    pub public_url: Option<String>,
+    pub password_reset_ttl_minutes: i64,
+    /// Lifetime of the set-password link sent to invited users
+    pub invite_ttl_hours: i64,
+    pub impersonation_ttl_minutes: i64,
+    /// Days a soft-deleted user can still be restored before the purge job removes it
+    pub deleted_user_retention_days: i64,
+    /// Length of a new or renewed membership, 0 for memberships that never expire
+    pub membership_duration_days: i64,
+    /// Maximum concurrent loans per patron category
+    pub loan_limits: HashMap<PatronCategory, usize>,
+    pub email_verification_ttl_hours: i64,
+    pub email_verification_resend_cooldown_seconds: i64,
+    pub require_verified_email_to_borrow: bool,
+    /// How long the readiness probe waits for each dependency to answer
+    pub health_check_timeout_ms: u64,
+    /// Bearer token required to scrape `/metrics`, which is open when unset
+    pub metrics_token: Option<String>,
+    pub rate_limits: RateLimitConfig,
+    /// Origins allowed to make cross-origin requests, any origin when empty
+    pub cors_allowed_origins: Vec<String>,
+    /// `tracing` filter directives, e.g. `info,lib_management_sys=debug`
+    pub log_level: String,
+    pub oidc: Option<OidcConfig>,
+    pub ldap: Option<LdapConfig>,
+}
+
+/// Reads settings from a [`ConfigSource`], collecting every problem instead of
+/// stopping at the first.
+struct Settings<'a> {
+    source: &'a ConfigSource,
+    errors: Vec<String>,
+}
+
+impl Settings<'_> {
+    fn get(&self, name: &'static str) -> Option<String> {
+        self.source.get(name)
+    }
+
+    fn get_or(&self, name: &'static str, default: impl ToString) -> String {
+        self.source.get_or(name, default)
+    }
+
+    fn required(&mut self, name: &'static str) -> Option<String> {
+        let value = self.get(name);
+        self.check(value.is_some(), || format!("{} is required", name));
+        value
+    }
+
+    /// Falls back to the default after recording an unparsable value, so that checks
+    /// on the result do not report the same setting twice.
+    fn parse<T: FromStr + Default>(
+        &mut self,
+        name: &'static str,
+        default: impl ToString,
+        expected: &str,
+    ) -> T {
+        let default = default.to_string();
+        match self.get_or(name, &default).trim().parse() {
+            Ok(value) => value,
+            Err(_) => {
+                self.errors.push(format!("{} must be {}", name, expected));
+                default.parse().unwrap_or_default()
+            }
+        }
+    }
+
+    fn number<T: FromStr + Default>(&mut self, name: &'static str, default: impl ToString) -> T {
+        self.parse(name, default, "a valid number")
+    }
+
+    fn flag(&mut self, name: &'static str, default: bool) -> bool {
+        self.parse(name, default, "true or false")
+    }
+
+    fn rate_limit(&mut self, name: &'static str, default: &str) -> Option<RateLimit> {
+        let limit = RateLimit::parse(name, &self.get_or(name, default));
+        self.ok(limit).flatten()
+    }
+
+    fn check(&mut self, valid: bool, error: impl FnOnce() -> String) {
+        if !valid {
+            self.errors.push(error());
+        }
+    }
+
+    fn ok<T>(&mut self, result: Result<T, String>) -> Option<T> {
+        result.map_err(|e| self.errors.push(e)).ok()
+    }
 }
 
 impl AppConfig {
-    pub fn from_env() -> Result<Self, String> {
-        dotenv().ok();
+    /// Builds the configuration, reporting every invalid or missing setting at once.
+    pub fn load(source: &ConfigSource) -> Result<Self, String> {
+        let mut settings = Settings {
+            source,
+            errors: Vec::new(),
+        };
 
-        let jwt_secret = env::var(JWT_SECRET).map_err(|_| format!("{} is required", JWT_SECRET))?;
+        let jwt_algorithm = match settings
+            .get_or(JWT_ALGORITHM, DEFAULT_JWT_ALGORITHM)
+            .as_str()
+        {
+            "HS256" => Some(Algorithm::HS256),
+            "RS256" => Some(Algorithm::RS256),
+            "EdDSA" => Some(Algorithm::EdDSA),
+            _ => {
+                settings.errors.push(format!(
+                    "{} must be one of HS256, RS256, EdDSA",
+                    JWT_ALGORITHM
+                ));
+                None
+            }
+        };
 
-        if jwt_secret.len() < MIN_JWT_SECRET_LENGTH {
-            return Err(format!(
-                "{} must be at least {} characters",
-                JWT_SECRET, MIN_JWT_SECRET_LENGTH,
-            ));
+        let jwt_key_id = settings.get(JWT_KEY_ID);
+
+        let jwt_keys = match jwt_algorithm {
+            None => None,
+            Some(Algorithm::HS256) => match settings.required(JWT_SECRET) {
+                Some(jwt_secret) if jwt_secret.len() < MIN_JWT_SECRET_LENGTH => {
+                    settings.errors.push(format!(
+                        "{} must be at least {} characters",
+                        JWT_SECRET, MIN_JWT_SECRET_LENGTH,
+                    ));
+                    None
+                }
+                Some(jwt_secret) => Some(JwtKeys::from_secret(&jwt_secret, jwt_key_id)),
+                None => None,
+            },
+            Some(jwt_algorithm) => {
+                let private_key_path = settings.required(JWT_PRIVATE_KEY_PATH);
+                let public_key_path = settings.required(JWT_PUBLIC_KEY_PATH);
+
+                // Format: "kid=/path/to/public.pem,kid2=/path/to/other.pem"
+                let previous_keys = settings
+                    .get(JWT_PREVIOUS_PUBLIC_KEYS)
+                    .unwrap_or_default()
+                    .split(',')
+                    .filter(|entry| !entry.trim().is_empty())
+                    .map(|entry| {
+                        entry
+                            .split_once('=')
+                            .map(|(kid, path)| (kid.trim().to_string(), path.trim().to_string()))
+                            .ok_or_else(|| {
+                                format!("{} entries must be kid=path", JWT_PREVIOUS_PUBLIC_KEYS)
+                            })
+                    })
+                    .collect::<Result<Vec<_>, _>>();
+                let previous_keys = settings.ok(previous_keys);
+
+                match (private_key_path, public_key_path, previous_keys) {
+                    (Some(private_key_path), Some(public_key_path), Some(previous_keys)) => {
+                        settings.ok(JwtKeys::from_pem_files(
+                            jwt_algorithm,
+                            &private_key_path,
+                            &public_key_path,
+                            jwt_key_id,
+                            &previous_keys,
+                        ))
+                    }
+                    _ => None,
+                }
+            }
+        };
+
+        let jwt_issuer = settings.get_or(JWT_ISSUER, DEFAULT_JWT_ISSUER);
+        let jwt_audience = settings.get_or(JWT_AUDIENCE, DEFAULT_JWT_AUDIENCE);
+
+        #[cfg(not(all(feature = "postgres", feature = "sqlite")))]
+        let missing_feature = |backend: &str| {
+            format!(
+                "{}={} requires a build with the {} feature",
+                DATABASE_BACKEND, backend, backend
+            )
+        };
+
+        let backend = settings
+            .get_or(DATABASE_BACKEND, "mongodb")
+            .to_ascii_lowercase();
+        let database = match backend.as_str() {
+            "mongodb" => match (settings.required(MONGO_URI), settings.required(MONGO_DB)) {
+                (Some(uri), Some(database)) => Some(DatabaseConfig::MongoDb { uri, database }),
+                _ => None,
+            },
+            #[cfg(feature = "postgres")]
+            "postgres" => {
+                let max_connections =
+                    settings.number(DATABASE_MAX_CONNECTIONS, DEFAULT_DATABASE_MAX_CONNECTIONS);
+                settings
+                    .required(DATABASE_URL)
+                    .map(|url| DatabaseConfig::Postgres {
+                        url,
+                        max_connections,
+                    })
+            }
+            #[cfg(feature = "sqlite")]
+            "sqlite" => {
+                let max_connections =
+                    settings.number(DATABASE_MAX_CONNECTIONS, DEFAULT_DATABASE_MAX_CONNECTIONS);
+                settings
+                    .required(DATABASE_URL)
+                    .map(|url| DatabaseConfig::Sqlite {
+                        url,
+                        max_connections,
+                    })
+            }
+            #[cfg(not(feature = "postgres"))]
+            "postgres" => settings.ok(Err(missing_feature("postgres"))),
+            #[cfg(not(feature = "sqlite"))]
+            "sqlite" => settings.ok(Err(missing_feature("sqlite"))),
+            _ => settings.ok(Err(format!(
+                "{} must be one of mongodb, postgres, sqlite",
+                DATABASE_BACKEND
+            ))),
+        };
+
+        // The embedded database also holds the short-lived state that otherwise lives
+        // in Redis.
+        let redis_uri = settings.get(REDIS_URI);
+        if let Some(ref database) = database {
+            settings.check(redis_uri.is_some() || database.has_state_store(), || {
+                format!("{} is required", REDIS_URI)
+            });
         }
 
-        let mongo_uri = env::var(MONGO_URI).map_err(|_| format!("{} is required", MONGO_URI))?;
+        let jwt_exp_hours = settings.number(JWT_EXP_HOURS, DEFAULT_JWT_EXP_HOURS);
+        settings.check(jwt_exp_hours > 0, || {
+            format!("{} must be positive", JWT_EXP_HOURS)
+        });
+
+        let argon2_memory_kib = settings.number(ARGON2_MEMORY_KIB, DEFAULT_ARGON2_MEMORY_KIB);
+        let argon2_iterations = settings.number(ARGON2_ITERATIONS, DEFAULT_ARGON2_ITERATIONS);
+        let argon2_parallelism = settings.number(ARGON2_PARALLELISM, DEFAULT_ARGON2_PARALLELISM);
 
-        let mongo_db = env::var(MONGO_DB).map_err(|_| format!("{} is required", MONGO_DB))?;
+        let argon2_params =
+            argon2::Params::new(argon2_memory_kib, argon2_iterations, argon2_parallelism, None)
+                .map_err(|e| format!("invalid Argon2 parameters: {}", e));
+        let argon2_params = settings.ok(argon2_params).unwrap_or_default();
 
-        let redis_uri = env::var(REDIS_URI).map_err(|_| format!("{} is required", REDIS_URI))?;
+        let password_min_length: usize =
+            settings.number(PASSWORD_MIN_LENGTH, DEFAULT_PASSWORD_MIN_LENGTH);
+        let password_max_length: usize =
+            settings.number(PASSWORD_MAX_LENGTH, DEFAULT_PASSWORD_MAX_LENGTH);
 
-        let jwt_exp_hours = env::var(JWT_EXP_HOURS)
-            .unwrap_or_else(|_| DEFAULT_JWT_EXP_HOURS.to_string())
-            .parse()
-            .map_err(|_| format!("{} must be a valid number", JWT_EXP_HOURS))?;
+        settings.check(
+            password_min_length > 0 && password_min_length <= password_max_length,
+            || {
+                format!(
+                    "{} must be positive and not exceed {}",
+                    PASSWORD_MIN_LENGTH, PASSWORD_MAX_LENGTH
+                )
+            },
+        );
 
-        if jwt_exp_hours <= 0 {
-            return Err(format!("{} must be positive", JWT_EXP_HOURS));
+        let password_history_size =
+            settings.number(PASSWORD_HISTORY_SIZE, DEFAULT_PASSWORD_HISTORY_SIZE);
+
+        let password_max_age_days =
+            settings.number(PASSWORD_MAX_AGE_DAYS, DEFAULT_PASSWORD_MAX_AGE_DAYS);
+        settings.check(password_max_age_days >= 0, || {
+            format!("{} must not be negative", PASSWORD_MAX_AGE_DAYS)
+        });
+
+        let breached_passwords_dir = settings.get(BREACHED_PASSWORDS_DIR);
+        if let Some(ref dir) = breached_passwords_dir {
+            settings.check(Path::new(dir).is_dir(), || {
+                format!("breached password directory not found: {}", dir)
+            });
         }
 
-        let host = env::var(APP_HOST).unwrap_or_else(|_| DEFAULT_HOST.into());
+        let password_policy = PasswordPolicy {
+            min_length: password_min_length,
+            max_length: password_max_length,
+            require_lowercase: settings.flag(PASSWORD_REQUIRE_LOWERCASE, false),
+            require_uppercase: settings.flag(PASSWORD_REQUIRE_UPPERCASE, false),
+            require_digit: settings.flag(PASSWORD_REQUIRE_DIGIT, false),
+            require_symbol: settings.flag(PASSWORD_REQUIRE_SYMBOL, false),
+            disallow_personal_info: settings.flag(PASSWORD_DISALLOW_PERSONAL_INFO, true),
+            history_size: password_history_size,
+            max_age_days: password_max_age_days,
+            breached_passwords_dir,
+        };
+
+        let migrate_on_startup = settings.flag(MIGRATE_ON_STARTUP, true);
+
+        let session_cookie = if settings.flag(SESSION_COOKIES, false) {
+            let same_site = match settings
+                .get_or(SESSION_COOKIE_SAME_SITE, "lax")
+                .to_ascii_lowercase()
+                .as_str()
+            {
+                "strict" => SameSite::Strict,
+                "lax" => SameSite::Lax,
+                _ => {
+                    settings.errors.push(format!(
+                        "{} must be strict or lax",
+                        SESSION_COOKIE_SAME_SITE
+                    ));
+                    SameSite::Lax
+                }
+            };
 
-        let port = env::var(APP_PORT)
-            .unwrap_or_else(|_| DEFAULT_PORT.into())
-            .parse()
-            .map_err(|_| format!("{} must be a valid port number", APP_PORT))?;
+            Some(SessionCookieConfig {
+                name: settings.get_or(SESSION_COOKIE_NAME, DEFAULT_SESSION_COOKIE_NAME),
+                csrf_name: settings.get_or(CSRF_COOKIE_NAME, DEFAULT_CSRF_COOKIE_NAME),
+                secure: settings.flag(SESSION_COOKIE_SECURE, true),
+                same_site,
+                domain: settings.get(SESSION_COOKIE_DOMAIN),
+            })
+        } else {
+            None
+        };
 
-        let ssl_cert_path = env::var(SSL_CERT_PATH).ok();
-        let ssl_key_path = env::var(SSL_KEY_PATH).ok();
+        let host = settings.get_or(APP_HOST, DEFAULT_HOST);
+        let port = settings.parse(APP_PORT, DEFAULT_PORT, "a valid port number");
+
+        let ssl_cert_path = settings.get(SSL_CERT_PATH);
+        let ssl_key_path = settings.get(SSL_KEY_PATH);
 
         // Validate SSL certificate files exist
         if let Some(ref cert_path) = ssl_cert_path {
-            if !Path::new(cert_path).exists() {
-                return Err(format!("SSL certificate file not found: {}", cert_path));
-            }
+            settings.check(Path::new(cert_path).exists(), || {
+                format!("SSL certificate file not found: {}", cert_path)
+            });
         }
 
         if let Some(ref key_path) = ssl_key_path {
-            if !Path::new(key_path).exists() {
-                return Err(format!("SSL key file not found: {}", key_path));
+            settings.check(Path::new(key_path).exists(), || {
+                format!("SSL key file not found: {}", key_path)
+            });
+        }
+
+        let smtp_host = settings.get(SMTP_HOST);
+        let smtp_port = settings.parse(SMTP_PORT, DEFAULT_SMTP_PORT, "a valid port number");
+        let smtp_username = settings.get(SMTP_USERNAME);
+        let smtp_password = settings.get(SMTP_PASSWORD);
+
+        let smtp_tls = settings.get_or(SMTP_TLS, "starttls").parse();
+        let smtp_tls = settings.ok(smtp_tls).unwrap_or(SmtpTls::StartTls);
+
+        let mail_from = settings.get_or(MAIL_FROM, DEFAULT_MAIL_FROM);
+
+        let public_url = settings
+            .get(PUBLIC_URL)
+            .map(|url| url.trim_end_matches('/').to_string());
+
+        let password_reset_ttl_minutes = settings.number(
+            PASSWORD_RESET_TTL_MINUTES,
+            DEFAULT_PASSWORD_RESET_TTL_MINUTES,
+        );
+        settings.check(password_reset_ttl_minutes > 0, || {
+            format!("{} must be positive", PASSWORD_RESET_TTL_MINUTES)
+        });
+
+        let invite_ttl_hours = settings.number(INVITE_TTL_HOURS, DEFAULT_INVITE_TTL_HOURS);
+        settings.check(invite_ttl_hours > 0, || {
+            format!("{} must be positive", INVITE_TTL_HOURS)
+        });
+
+        let impersonation_ttl_minutes =
+            settings.number(IMPERSONATION_TTL_MINUTES, DEFAULT_IMPERSONATION_TTL_MINUTES);
+        settings.check(impersonation_ttl_minutes > 0, || {
+            format!("{} must be positive", IMPERSONATION_TTL_MINUTES)
+        });
+
+        let deleted_user_retention_days = settings.number(
+            DELETED_USER_RETENTION_DAYS,
+            DEFAULT_DELETED_USER_RETENTION_DAYS,
+        );
+        settings.check(deleted_user_retention_days > 0, || {
+            format!("{} must be positive", DELETED_USER_RETENTION_DAYS)
+        });
+
+        let membership_duration_days =
+            settings.number(MEMBERSHIP_DURATION_DAYS, DEFAULT_MEMBERSHIP_DURATION_DAYS);
+        settings.check(membership_duration_days >= 0, || {
+            format!("{} must not be negative", MEMBERSHIP_DURATION_DAYS)
+        });
+
+        let health_check_timeout_ms =
+            settings.number(HEALTH_CHECK_TIMEOUT_MS, DEFAULT_HEALTH_CHECK_TIMEOUT_MS);
+        settings.check(health_check_timeout_ms > 0, || {
+            format!("{} must be positive", HEALTH_CHECK_TIMEOUT_MS)
+        });
+
+        let metrics_token = settings
+            .get(METRICS_TOKEN)
+            .filter(|token| !token.is_empty());
+
+        let mut loan_limits: HashMap<PatronCategory, usize> = PatronCategory::ALL
+            .into_iter()
+            .map(|category| (category, DEFAULT_LOAN_LIMIT))
+            .collect();
+        for entry in settings
+            .get(PATRON_LOAN_LIMITS)
+            .unwrap_or_default()
+            .split(',')
+            .filter(|entry| !entry.trim().is_empty())
+        {
+            let limit = entry
+                .split_once('=')
+                .ok_or_else(|| format!("{} entries must be category=limit", PATRON_LOAN_LIMITS))
+                .and_then(|(category, limit)| {
+                    let category = category
+                        .parse()
+                        .map_err(|e| format!("{}: {}", PATRON_LOAN_LIMITS, e))?;
+                    let limit = limit.trim().parse().map_err(|_| {
+                        format!("{} limits must be valid numbers", PATRON_LOAN_LIMITS)
+                    })?;
+                    Ok((category, limit))
+                });
+            if let Some((category, limit)) = settings.ok(limit) {
+                loan_limits.insert(category, limit);
             }
         }
 
+        let email_verification_ttl_hours = settings.number(
+            EMAIL_VERIFICATION_TTL_HOURS,
+            DEFAULT_EMAIL_VERIFICATION_TTL_HOURS,
+        );
+        settings.check(email_verification_ttl_hours > 0, || {
+            format!("{} must be positive", EMAIL_VERIFICATION_TTL_HOURS)
+        });
+
+        let email_verification_resend_cooldown_seconds = settings.number(
+            EMAIL_VERIFICATION_RESEND_COOLDOWN_SECONDS,
+            DEFAULT_EMAIL_VERIFICATION_RESEND_COOLDOWN_SECONDS,
+        );
+        settings.check(email_verification_resend_cooldown_seconds > 0, || {
+            format!(
+                "{} must be positive",
+                EMAIL_VERIFICATION_RESEND_COOLDOWN_SECONDS
+            )
+        });
+
+        let require_verified_email_to_borrow =
+            settings.flag(REQUIRE_VERIFIED_EMAIL_TO_BORROW, false);
+
+        let allowlist = settings
+            .get(RATE_LIMIT_ALLOWLIST)
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .map(|entry| {
+                entry
+                    .parse()
+                    .map_err(|e| format!("{}: {}", RATE_LIMIT_ALLOWLIST, e))
+            })
+            .collect::<Result<_, _>>();
+        let rate_limits = RateLimitConfig {
+            auth: settings.rate_limit(RATE_LIMIT_AUTH, DEFAULT_RATE_LIMIT_AUTH),
+            book: settings.rate_limit(RATE_LIMIT_BOOK, DEFAULT_RATE_LIMIT_BOOK),
+            admin: settings.rate_limit(RATE_LIMIT_ADMIN, DEFAULT_RATE_LIMIT_ADMIN),
+            allowlist: settings.ok(allowlist).unwrap_or_default(),
+            trust_forwarded_for: settings.flag(RATE_LIMIT_TRUST_FORWARDED_FOR, false),
+        };
+
+        let cors_allowed_origins: Vec<String> = settings
+            .get(CORS_ALLOWED_ORIGINS)
+            .unwrap_or_default()
+            .split(',')
+            .map(|origin| origin.trim().trim_end_matches('/'))
+            .filter(|origin| !origin.is_empty())
+            .map(str::to_string)
+            .collect();
+        for origin in &cors_allowed_origins {
+            settings.check(
+                origin.starts_with("http://") || origin.starts_with("https://"),
+                || {
+                    format!(
+                        "{}: {} is not an http(s) origin",
+                        CORS_ALLOWED_ORIGINS, origin
+                    )
+                },
+            );
+        }
+        // CORS allows credentials, so session cookies must not be sent from any origin.
+        settings.check(
+            session_cookie.is_none() || !cors_allowed_origins.is_empty(),
+            || {
+                format!(
+                    "{} must list the allowed origins when {} is enabled",
+                    CORS_ALLOWED_ORIGINS, SESSION_COOKIES
+                )
+            },
+        );
+
+        // `RUST_LOG` is still honoured when no level is configured.
+        let default_log_level = settings
+            .get(RUST_LOG)
+            .unwrap_or_else(|| DEFAULT_LOG_LEVEL.into());
+        let log_level = settings.get_or(LOG_LEVEL, default_log_level);
+        let log_filter = EnvFilter::try_new(&log_level)
+            .map_err(|e| format!("{} is not a valid log filter: {}", LOG_LEVEL, e));
+        settings.ok(log_filter);
+
+        let oidc = match settings.get(OIDC_ISSUER_URL) {
+            Some(issuer_url) => {
+                let client_id = settings.required(OIDC_CLIENT_ID);
+                let redirect_url = settings.required(OIDC_REDIRECT_URL);
+                let scopes = settings
+                    .get_or(OIDC_SCOPES, DEFAULT_OIDC_SCOPES)
+                    .split_whitespace()
+                    .filter(|scope| *scope != "openid")
+                    .map(str::to_string)
+                    .collect();
+                let auto_provision = settings.flag(OIDC_AUTO_PROVISION, false);
+                let client_secret = settings.get(OIDC_CLIENT_SECRET);
+
+                match (client_id, redirect_url) {
+                    (Some(client_id), Some(redirect_url)) => Some(OidcConfig {
+                        issuer_url,
+                        client_id,
+                        client_secret,
+                        redirect_url,
+                        scopes,
+                        auto_provision,
+                    }),
+                    _ => None,
+                }
+            }
+            None => None,
+        };
+
+        let ldap = match settings.get(LDAP_URL) {
+            Some(url) => {
+                settings.check(
+                    url.starts_with("ldap://") || url.starts_with("ldaps://"),
+                    || format!("{} must start with ldap:// or ldaps://", LDAP_URL),
+                );
+
+                let starttls = settings.flag(LDAP_STARTTLS, false);
+                settings.check(!(starttls && url.starts_with("ldaps://")), || {
+                    format!("{} cannot be used with ldaps://", LDAP_STARTTLS)
+                });
+
+                let ca_cert_path = settings.get(LDAP_CA_CERT_PATH);
+                if let Some(ref path) = ca_cert_path {
+                    settings.check(Path::new(path).exists(), || {
+                        format!("LDAP CA certificate not found: {}", path)
+                    });
+                }
+
+                let bind_dn = settings.get(LDAP_BIND_DN);
+                let bind_password = settings.get(LDAP_BIND_PASSWORD);
+                settings.check(bind_dn.is_none() || bind_password.is_some(), || {
+                    format!("{} is required with {}", LDAP_BIND_PASSWORD, LDAP_BIND_DN)
+                });
+
+                let base_dn = settings.required(LDAP_BASE_DN);
+
+                let user_filter = settings.get_or(LDAP_USER_FILTER, DEFAULT_LDAP_USER_FILTER);
+                settings.check(user_filter.contains("{email}"), || {
+                    format!("{} must contain {{email}}", LDAP_USER_FILTER)
+                });
+
+                let admin_groups = settings
+                    .get(LDAP_ADMIN_GROUPS)
+                    .unwrap_or_default()
+                    .split(';')
+                    .map(str::trim)
+                    .filter(|dn| !dn.is_empty())
+                    .map(str::to_string)
+                    .collect();
+
+                let sync_interval_seconds = settings.number(
+                    LDAP_SYNC_INTERVAL_SECONDS,
+                    DEFAULT_LDAP_SYNC_INTERVAL_SECONDS,
+                );
+
+                base_dn.map(|base_dn| LdapConfig {
+                    url,
+                    starttls,
+                    ca_cert_path,
+                    bind_dn,
+                    bind_password,
+                    base_dn,
+                    user_filter,
+                    username_attribute: settings
+                        .get_or(LDAP_USERNAME_ATTRIBUTE, DEFAULT_LDAP_USERNAME_ATTRIBUTE),
+                    email_attribute: settings
+                        .get_or(LDAP_EMAIL_ATTRIBUTE, DEFAULT_LDAP_EMAIL_ATTRIBUTE),
+                    group_attribute: settings
+                        .get_or(LDAP_GROUP_ATTRIBUTE, DEFAULT_LDAP_GROUP_ATTRIBUTE),
+                    admin_groups,
+                    sync_interval_seconds,
+                })
+            }
+            None => None,
+        };
+
+        // Problems reading the sources come first, then the invalid settings.
+        let mut errors = source.take_errors();
+        errors.append(&mut settings.errors);
+        let (Some(jwt_keys), Some(database), true) = (jwt_keys, database, errors.is_empty()) else {
+            return Err(format!(
+                "invalid configuration:\n  - {}",
+                errors.join("\n  - ")
+            ));
+        };
+
         Ok(Self {
-            mongo_uri,
-            mongo_db,
+            database,
+            migrate_on_startup,
             redis_uri,
-            jwt_secret,
+            jwt_keys: Arc::new(jwt_keys),
+            jwt_issuer,
+            jwt_audience,
+            argon2_params,
+            password_policy,
+            session_cookie,
             jwt_exp_hours,
             host,
             port,
             ssl_cert_path,
             ssl_key_path,
+            smtp_host,
+            smtp_port,
+            smtp_username,
+            smtp_password,
+            smtp_tls,
+            mail_from,
+            public_url,
+            password_reset_ttl_minutes,
+            invite_ttl_hours,
+            impersonation_ttl_minutes,
+            deleted_user_retention_days,
+            membership_duration_days,
+            loan_limits,
+            email_verification_ttl_hours,
+            email_verification_resend_cooldown_seconds,
+            require_verified_email_to_borrow,
+            health_check_timeout_ms,
+            metrics_token,
+            rate_limits,
+            cors_allowed_origins,
+            log_level,
+            oidc,
+            ldap,
         })
     }
+
+    pub fn loan_limit(&self, category: PatronCategory) -> usize {
+        self.loan_limits
+            .get(&category)
+            .copied()
+            .unwrap_or(DEFAULT_LOAN_LIMIT)
+    }
 }
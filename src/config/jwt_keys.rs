@@ -0,0 +1,181 @@
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use ed25519_dalek::pkcs8::DecodePublicKey;
+use jsonwebtoken::jwk::{
+    AlgorithmParameters, CommonParameters, EllipticCurve, Jwk, JwkSet, KeyAlgorithm,
+    OctetKeyPairParameters, OctetKeyPairType, PublicKeyUse, RSAKeyParameters, RSAKeyType,
+};
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use rsa::traits::PublicKeyParts;
+use sha2::{Digest, Sha256};
+use std::fs;
+
+/// A key that tokens can be verified with, looked up by the `kid` header.
+pub struct VerificationKey {
+    pub kid: String,
+    pub algorithm: Algorithm,
+    pub decoding_key: DecodingKey,
+    /// Public JWK form, absent for shared HMAC secrets which must never be published
+    pub jwk: Option<Jwk>,
+}
+
+/// The signing key for new tokens plus every key still accepted for verification.
+pub struct JwtKeys {
+    pub algorithm: Algorithm,
+    pub signing_kid: String,
+    pub encoding_key: EncodingKey,
+    pub verification_keys: Vec<VerificationKey>,
+}
+
+impl JwtKeys {
+    pub fn from_secret(secret: &str, kid: Option<String>) -> Self {
+        let kid = kid.unwrap_or_else(|| "default".into());
+        Self {
+            algorithm: Algorithm::HS256,
+            signing_kid: kid.clone(),
+            encoding_key: EncodingKey::from_secret(secret.as_bytes()),
+            verification_keys: vec![VerificationKey {
+                kid,
+                algorithm: Algorithm::HS256,
+                decoding_key: DecodingKey::from_secret(secret.as_bytes()),
+                jwk: None,
+            }],
+        }
+    }
+
+    /// Loads an RS256 or EdDSA key pair from PEM files. Keys retired by a rotation
+    /// are passed as `(kid, public key path)` pairs and stay valid for verification.
+    pub fn from_pem_files(
+        algorithm: Algorithm,
+        private_key_path: &str,
+        public_key_path: &str,
+        kid: Option<String>,
+        previous_keys: &[(String, String)],
+    ) -> Result<Self, String> {
+        let private_pem = fs::read(private_key_path)
+            .map_err(|e| format!("failed to read {}: {}", private_key_path, e))?;
+
+        let encoding_key = match algorithm {
+            Algorithm::RS256 => EncodingKey::from_rsa_pem(&private_pem),
+            Algorithm::EdDSA => EncodingKey::from_ed_pem(&private_pem),
+            _ => return Err(format!("unsupported signing algorithm {:?}", algorithm)),
+        }
+        .map_err(|e| format!("invalid private key {}: {}", private_key_path, e))?;
+
+        let current = load_public_key(public_key_path, kid)?;
+        if current.algorithm != algorithm {
+            return Err(format!(
+                "public key {} does not match algorithm {:?}",
+                public_key_path, algorithm
+            ));
+        }
+
+        ensure_key_pair_matches(&encoding_key, &current)
+            .map_err(|_| format!("{} does not match {}", private_key_path, public_key_path))?;
+
+        let mut verification_keys = vec![current];
+        for (kid, path) in previous_keys {
+            let key = load_public_key(path, Some(kid.clone()))?;
+            if verification_keys.iter().any(|k| k.kid == key.kid) {
+                return Err(format!("duplicate JWT key id {}", key.kid));
+            }
+            verification_keys.push(key);
+        }
+
+        Ok(Self {
+            algorithm,
+            signing_kid: verification_keys[0].kid.clone(),
+            encoding_key,
+            verification_keys,
+        })
+    }
+
+    pub fn find(&self, kid: &str) -> Option<&VerificationKey> {
+        self.verification_keys.iter().find(|k| k.kid == kid)
+    }
+
+    pub fn jwk_set(&self) -> JwkSet {
+        JwkSet {
+            keys: self
+                .verification_keys
+                .iter()
+                .filter_map(|k| k.jwk.clone())
+                .collect(),
+        }
+    }
+}
+
+/// Reads an RSA or Ed25519 public key in SPKI PEM format. Without an explicit
+/// key id, one is derived from the key itself so it stays stable across restarts.
+fn load_public_key(path: &str, kid: Option<String>) -> Result<VerificationKey, String> {
+    let pem = fs::read_to_string(path).map_err(|e| format!("failed to read {}: {}", path, e))?;
+    let kid = kid.unwrap_or_else(|| hex::encode(&Sha256::digest(pem.trim().as_bytes())[..8]));
+
+    if let Ok(rsa_key) = rsa::RsaPublicKey::from_public_key_pem(&pem) {
+        let n = URL_SAFE_NO_PAD.encode(rsa_key.n().to_bytes_be());
+        let e = URL_SAFE_NO_PAD.encode(rsa_key.e().to_bytes_be());
+        let decoding_key = DecodingKey::from_rsa_components(&n, &e)
+            .map_err(|e| format!("invalid RSA public key {}: {}", path, e))?;
+
+        return Ok(VerificationKey {
+            jwk: Some(Jwk {
+                common: public_jwk_common(&kid, KeyAlgorithm::RS256),
+                algorithm: AlgorithmParameters::RSA(RSAKeyParameters {
+                    key_type: RSAKeyType::RSA,
+                    n,
+                    e,
+                }),
+            }),
+            kid,
+            algorithm: Algorithm::RS256,
+            decoding_key,
+        });
+    }
+
+    if let Ok(ed_key) = ed25519_dalek::VerifyingKey::from_public_key_pem(&pem) {
+        let x = URL_SAFE_NO_PAD.encode(ed_key.to_bytes());
+        let decoding_key = DecodingKey::from_ed_components(&x)
+            .map_err(|e| format!("invalid Ed25519 public key {}: {}", path, e))?;
+
+        return Ok(VerificationKey {
+            jwk: Some(Jwk {
+                common: public_jwk_common(&kid, KeyAlgorithm::EdDSA),
+                algorithm: AlgorithmParameters::OctetKeyPair(OctetKeyPairParameters {
+                    key_type: OctetKeyPairType::OctetKeyPair,
+                    curve: EllipticCurve::Ed25519,
+                    x,
+                }),
+            }),
+            kid,
+            algorithm: Algorithm::EdDSA,
+            decoding_key,
+        });
+    }
+
+    Err(format!("{} is not an RSA or Ed25519 public key", path))
+}
+
+/// Signs a throwaway token and verifies it, so a mismatched key pair fails at startup
+/// instead of rejecting every token at runtime.
+fn ensure_key_pair_matches(
+    encoding_key: &EncodingKey,
+    key: &VerificationKey,
+) -> jsonwebtoken::errors::Result<()> {
+    let claims = serde_json::json!({ "sub": "key-check", "exp": u32::MAX });
+    let token = jsonwebtoken::encode(&Header::new(key.algorithm), &claims, encoding_key)?;
+    jsonwebtoken::decode::<serde_json::Value>(
+        &token,
+        &key.decoding_key,
+        &Validation::new(key.algorithm),
+    )?;
+    Ok(())
+}
+
+fn public_jwk_common(kid: &str, algorithm: KeyAlgorithm) -> CommonParameters {
+    CommonParameters {
+        public_key_use: Some(PublicKeyUse::Signature),
+        key_algorithm: Some(algorithm),
+        key_id: Some(kid.into()),
+        ..Default::default()
+    }
+}
@@ -0,0 +1,65 @@
+//! Settings that take effect without a restart when the configuration is reloaded.
+
+use crate::config::app_config::{AppConfig, RateLimitConfig};
+use std::sync::{Arc, RwLock};
+
+#[derive(Clone, PartialEq)]
+pub struct LiveSettings {
+    pub cors_allowed_origins: Vec<String>,
+    pub rate_limits: RateLimitConfig,
+    pub log_level: String,
+}
+
+impl From<&AppConfig> for LiveSettings {
+    fn from(cfg: &AppConfig) -> Self {
+        LiveSettings {
+            cors_allowed_origins: cfg.cors_allowed_origins.clone(),
+            rate_limits: cfg.rate_limits.clone(),
+            log_level: cfg.log_level.clone(),
+        }
+    }
+}
+
+/// The live settings shared by every worker; a reload replaces them as a whole.
+#[derive(Clone)]
+pub struct LiveConfig(Arc<RwLock<Arc<LiveSettings>>>);
+
+impl LiveConfig {
+    pub fn new(cfg: &AppConfig) -> Self {
+        LiveConfig(Arc::new(RwLock::new(Arc::new(LiveSettings::from(cfg)))))
+    }
+
+    pub fn current(&self) -> Arc<LiveSettings> {
+        self.0.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Swaps in the settings of a reloaded configuration and names those that changed.
+    pub fn apply(&self, cfg: &AppConfig) -> Vec<&'static str> {
+        let new = LiveSettings::from(cfg);
+        let mut current = self.0.write().unwrap_or_else(|e| e.into_inner());
+
+        let mut changed = Vec::new();
+        if new.cors_allowed_origins != current.cors_allowed_origins {
+            changed.push("CORS origins");
+        }
+        if new.rate_limits != current.rate_limits {
+            changed.push("rate limits");
+        }
+        if new.log_level != current.log_level {
+            changed.push("log level");
+        }
+        *current = Arc::new(new);
+        changed
+    }
+
+    /// Whether cross-origin requests from `origin` are allowed; any origin is when
+    /// no list is configured.
+    pub fn allows_origin(&self, origin: &[u8]) -> bool {
+        let settings = self.current();
+        settings.cors_allowed_origins.is_empty()
+            || settings
+                .cors_allowed_origins
+                .iter()
+                .any(|allowed| allowed.as_bytes() == origin)
+    }
+}
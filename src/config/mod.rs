@@ -1,2 +1,5 @@
 pub mod app_config;
+pub mod jwt_keys;
+pub mod live;
 pub mod rustls_config;
+pub mod source;
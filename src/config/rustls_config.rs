@@ -1,21 +1,67 @@
+use rustls::crypto::CryptoProvider;
+use rustls::server::{ClientHello, ResolvesServerCert};
+use rustls::sign::CertifiedKey;
 use rustls::ServerConfig;
 use rustls_pemfile::{certs, pkcs8_private_keys};
 use std::fs::File;
 use std::io::BufReader;
+use std::sync::{Arc, RwLock};
 
-pub fn load_rustls_config(
+/// Serves the most recently loaded certificate, so that a renewed certificate can
+/// be swapped in without a restart. Connections already open keep the old one.
+#[derive(Debug)]
+pub struct CertResolver {
+    cert_path: String,
+    key_path: String,
+    key: RwLock<Arc<CertifiedKey>>,
+}
+
+impl CertResolver {
+    pub fn load(cert_path: &str, key_path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(CertResolver {
+            cert_path: cert_path.to_string(),
+            key_path: key_path.to_string(),
+            key: RwLock::new(Arc::new(load_certified_key(cert_path, key_path)?)),
+        })
+    }
+
+    pub fn paths(&self) -> [&str; 2] {
+        [&self.cert_path, &self.key_path]
+    }
+
+    /// Reads the certificate and key again. On error the current pair stays in use.
+    pub fn reload(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let key = load_certified_key(&self.cert_path, &self.key_path)?;
+        *self.key.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(key);
+        Ok(())
+    }
+}
+
+impl ResolvesServerCert for CertResolver {
+    fn resolve(&self, _client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        Some(self.key.read().unwrap_or_else(|e| e.into_inner()).clone())
+    }
+}
+
+fn load_certified_key(
     cert_path: &str,
     key_path: &str,
-) -> Result<ServerConfig, Box<dyn std::error::Error>> {
+) -> Result<CertifiedKey, Box<dyn std::error::Error>> {
     let cert_file = &mut BufReader::new(File::open(cert_path)?);
     let key_file = &mut BufReader::new(File::open(key_path)?);
 
     let cert_chain = certs(cert_file).collect::<Result<Vec<_>, _>>()?;
-    let mut keys = pkcs8_private_keys(key_file).collect::<Result<Vec<_>, _>>()?;
+    let key = pkcs8_private_keys(key_file)
+        .next()
+        .ok_or_else(|| format!("no PKCS#8 private key found in {}", key_path))??;
 
-    let config = ServerConfig::builder()
-        .with_no_client_auth()
-        .with_single_cert(cert_chain, keys.remove(0).into())?;
+    let provider = CryptoProvider::get_default().ok_or("no rustls crypto provider installed")?;
+    let key = CertifiedKey::from_der(cert_chain, key.into(), provider)?;
+    Ok(key)
+}
 
-    Ok(config)
+pub fn load_rustls_config(resolver: Arc<CertResolver>) -> ServerConfig {
+    ServerConfig::builder()
+        .with_no_client_auth()
+        .with_cert_resolver(resolver)
 }
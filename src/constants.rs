@@ -1,18 +1,118 @@
 pub const COLLECTION_USERS: &str = "users";
 pub const COLLECTION_BOOKS: &str = "books";
+pub const COLLECTION_API_KEYS: &str = "api_keys";
+pub const COLLECTION_SCHEMA_MIGRATIONS: &str = "schema_migrations";
+
+pub const DEFAULT_OIDC_SCOPES: &str = "openid email profile";
+pub const OIDC_STATE_TTL_SECONDS: i64 = 600;
+pub const OIDC_STATE_KEY_PREFIX: &str = "oidc_state:";
+pub const OIDC_STATE_COOKIE_NAME: &str = "lms_oidc_state";
+pub const RATE_LIMIT_KEY_PREFIX: &str = "rate_limit:";
+
+pub const DEFAULT_LDAP_USER_FILTER: &str = "(mail={email})";
+pub const DEFAULT_LDAP_USERNAME_ATTRIBUTE: &str = "uid";
+pub const DEFAULT_LDAP_EMAIL_ATTRIBUTE: &str = "mail";
+pub const DEFAULT_LDAP_GROUP_ATTRIBUTE: &str = "memberOf";
+pub const DEFAULT_LDAP_SYNC_INTERVAL_SECONDS: u64 = 3600;
+pub const LDAP_TIMEOUT_SECONDS: u64 = 5;
+
+pub const API_KEY_HEADER: &str = "X-API-Key";
+pub const API_KEY_PREFIX: &str = "lms_";
+pub const API_KEY_DISPLAY_PREFIX_LENGTH: usize = 12;
+
+pub const SCOPE_PROFILE_READ: &str = "profile:read";
+pub const SCOPE_PROFILE_WRITE: &str = "profile:write";
+pub const SCOPE_CIRCULATION_WRITE: &str = "circulation:write";
+pub const SCOPE_CATALOG_READ: &str = "catalog:read";
+pub const SCOPE_CATALOG_WRITE: &str = "catalog:write";
+pub const SCOPE_USERS_READ: &str = "users:read";
+pub const SCOPE_USERS_WRITE: &str = "users:write";
+pub const API_KEY_SCOPES: &[&str] = &[
+    SCOPE_PROFILE_READ,
+    SCOPE_PROFILE_WRITE,
+    SCOPE_CIRCULATION_WRITE,
+    SCOPE_CATALOG_READ,
+    SCOPE_CATALOG_WRITE,
+    SCOPE_USERS_READ,
+    SCOPE_USERS_WRITE,
+];
 
 pub const DEFAULT_JWT_EXP_HOURS: i64 = 24;
 pub const MIN_JWT_SECRET_LENGTH: usize = 32;
+pub const DEFAULT_JWT_ALGORITHM: &str = "HS256";
+pub const DEFAULT_JWT_ISSUER: &str = "lib-management-sys";
+pub const DEFAULT_JWT_AUDIENCE: &str = "lib-management-sys";
+
+#[cfg(any(feature = "postgres", feature = "sqlite"))]
+pub const DEFAULT_DATABASE_MAX_CONNECTIONS: u32 = 10;
+
+// OWASP recommended minimum for Argon2id: 19 MiB memory, 2 iterations, 1 lane
+pub const DEFAULT_ARGON2_MEMORY_KIB: u32 = 19456;
+pub const DEFAULT_ARGON2_ITERATIONS: u32 = 2;
+pub const DEFAULT_ARGON2_PARALLELISM: u32 = 1;
+
+pub const DEFAULT_PASSWORD_MIN_LENGTH: usize = 8;
+pub const DEFAULT_PASSWORD_MAX_LENGTH: usize = 128;
+pub const DEFAULT_PASSWORD_HISTORY_SIZE: usize = 0;
+pub const DEFAULT_PASSWORD_MAX_AGE_DAYS: i64 = 0;
+// Shorter email local parts or usernames are too common to be rejected as substrings.
+pub const MIN_PERSONAL_INFO_LENGTH: usize = 3;
+
+pub const DEFAULT_SESSION_COOKIE_NAME: &str = "lms_session";
+pub const DEFAULT_CSRF_COOKIE_NAME: &str = "lms_csrf";
+pub const CSRF_HEADER: &str = "X-CSRF-Token";
 
 pub const DEFAULT_HOST: &str = "0.0.0.0";
 pub const DEFAULT_PORT: &str = "8080";
 
+pub const DEFAULT_SMTP_PORT: &str = "587";
+pub const DEFAULT_MAIL_FROM: &str = "Library <no-reply@localhost>";
+pub const DEFAULT_PASSWORD_RESET_TTL_MINUTES: i64 = 30;
+
+pub const PASSWORD_RESET_KEY_PREFIX: &str = "password_reset:";
+pub const PASSWORD_RESET_USER_KEY_PREFIX: &str = "password_reset_user:";
+pub const PASSWORD_RESET_EMAIL_SUBJECT: &str = "Reset your library password";
+
+pub const DEFAULT_IMPERSONATION_TTL_MINUTES: i64 = 15;
+
+pub const DEFAULT_DELETED_USER_RETENTION_DAYS: i64 = 30;
+pub const USER_PURGE_INTERVAL_SECONDS: u64 = 3600;
+/// How recent a sign-in must be to delete an account that has no password
+pub const RECENT_LOGIN_MAX_AGE_SECONDS: i64 = 600;
+
+pub const DEFAULT_MEMBERSHIP_DURATION_DAYS: i64 = 365;
+pub const DEFAULT_LOAN_LIMIT: usize = 8;
+
+pub const DEFAULT_HEALTH_CHECK_TIMEOUT_MS: u64 = 2000;
+/// How long the database-backed gauges on `/metrics` are reused between scrapes
+pub const METRICS_GAUGE_REFRESH_SECONDS: u64 = 15;
+
+pub const DEFAULT_RATE_LIMIT_AUTH: &str = "20/60";
+pub const DEFAULT_RATE_LIMIT_BOOK: &str = "120/60";
+pub const DEFAULT_RATE_LIMIT_ADMIN: &str = "300/60";
+// Lowercase, as required by `HeaderName::from_static`
+pub const RATE_LIMIT_LIMIT_HEADER: &str = "x-ratelimit-limit";
+pub const RATE_LIMIT_REMAINING_HEADER: &str = "x-ratelimit-remaining";
+pub const RATE_LIMIT_RESET_HEADER: &str = "x-ratelimit-reset";
+
+pub const BULK_IMPORT_MAX_BYTES: usize = 10 * 1024 * 1024;
+pub const BULK_IMPORT_MAX_ROWS: usize = 10_000;
+pub const GENERATED_PASSWORD_LENGTH: usize = 16;
+pub const DEFAULT_INVITE_TTL_HOURS: i64 = 72;
+pub const INVITE_EMAIL_SUBJECT: &str = "Your library account is ready";
+
+pub const DEFAULT_EMAIL_VERIFICATION_TTL_HOURS: i64 = 24;
+pub const DEFAULT_EMAIL_VERIFICATION_RESEND_COOLDOWN_SECONDS: i64 = 60;
+
+pub const EMAIL_VERIFICATION_KEY_PREFIX: &str = "email_verification:";
+pub const EMAIL_VERIFICATION_COOLDOWN_KEY_PREFIX: &str = "email_verification_cooldown:";
+pub const EMAIL_VERIFICATION_EMAIL_SUBJECT: &str = "Confirm your library email address";
+
 pub const REGISTER_SUCCESS: &str = "successfully registered";
 pub const LOGIN_SUCCESS: &str = "successfully logged in";
 pub const LOGOUT_SUCCESS: &str = "successfully logged out";
 pub const TOKEN_BLACKLISTED: &str = "token has been blacklisted";
 pub const PROFILE_FETCHED: &str = "successfully fetched user profile";
-pub const EMAIL_UPDATED: &str = "successfully updated email";
 pub const USERNAME_UPDATED: &str = "successfully updated username";
 pub const PASSWORD_UPDATED: &str = "successfully updated password";
 pub const USER_INFO_FETCHED: &str = "successfully fetched user info";
@@ -22,6 +122,30 @@ pub const USER_UPDATED: &str = "successfully updated user";
 pub const USER_DELETED: &str = "successfully deleted user";
 pub const USER_SET_AS_ADMIN: &str = "successfully set user as admin";
 pub const ADMIN_SET_AS_USER: &str = "successfully set admin as user";
+pub const ACCOUNT_EXPORTED: &str = "successfully exported account data";
+pub const ACCOUNT_DELETED: &str = "successfully deleted account";
+pub const IMPERSONATION_STARTED: &str = "successfully issued impersonation token";
+pub const USER_SUSPENDED: &str = "successfully suspended user";
+pub const USER_REACTIVATED: &str = "successfully reactivated user";
+pub const USER_RESTORED: &str = "successfully restored user";
+pub const MEMBERSHIP_RENEWED: &str = "successfully renewed membership";
+pub const MEMBERSHIPS_EXTENDED: &str = "successfully extended memberships";
+pub const USERS_IMPORTED: &str = "successfully processed user import";
+pub const USERS_EXPORTED: &str = "successfully exported users";
+pub const FAMILY_FETCHED: &str = "successfully fetched family";
+pub const FAMILY_UPDATED: &str = "successfully updated family";
+pub const DEPENDENT_CREATED: &str = "successfully created dependent";
+pub const DEPENDENT_UPDATED: &str = "successfully updated dependent";
+pub const GUARDIAN_LINKED: &str = "successfully linked guardian";
+pub const GUARDIAN_UNLINKED: &str = "successfully unlinked guardian";
+pub const PASSWORD_RESET_REQUESTED: &str =
+    "if the email is registered, a password reset link has been sent";
+pub const PASSWORD_RESET_SUCCESS: &str = "successfully reset password";
+pub const API_KEY_CREATED: &str = "successfully created api key";
+pub const API_KEYS_FETCHED: &str = "successfully fetched api keys";
+pub const API_KEY_REVOKED: &str = "successfully revoked api key";
+pub const EMAIL_VERIFICATION_SENT: &str = "verification email sent";
+pub const EMAIL_VERIFIED: &str = "successfully verified email";
 
 pub const BOOK_CREATED: &str = "successfully created book";
 pub const BOOK_UPDATED: &str = "successfully updated book";
@@ -36,19 +160,173 @@ pub const EMAIL_ALREADY_EXISTS: &str = "email already registered";
 pub const BOOK_ALREADY_EXISTS: &str = "book already exists";
 pub const INVALID_CREDENTIALS: &str = "invalid username or password";
 pub const INVALID_OLD_PASSWORD: &str = "invalid old password";
+pub const INVALID_PASSWORD: &str = "invalid password";
+pub const RECENT_LOGIN_REQUIRED: &str = "sign in again to confirm this action";
+pub const ACCOUNT_HAS_BORROWED_BOOKS: &str =
+    "return all borrowed books before deleting the account";
+pub const INVALID_RESET_TOKEN: &str = "invalid or expired password reset token";
+pub const INVALID_VERIFICATION_TOKEN: &str = "invalid or expired email verification token";
+pub const EMAIL_ALREADY_VERIFIED: &str = "email already verified";
+pub const EMAIL_NOT_VERIFIED: &str = "email address must be verified first";
+pub const VERIFICATION_RESEND_TOO_SOON: &str =
+    "verification email was sent recently, please wait before retrying";
+pub const RATE_LIMIT_EXCEEDED: &str = "too many requests, please slow down";
+pub const PASSWORD_TOO_SHORT: &str = "password must be at least";
+pub const PASSWORD_TOO_LONG: &str = "password must be at most";
+pub const PASSWORD_NEEDS_LOWERCASE: &str = "password must contain a lowercase letter";
+pub const PASSWORD_NEEDS_UPPERCASE: &str = "password must contain an uppercase letter";
+pub const PASSWORD_NEEDS_DIGIT: &str = "password must contain a digit";
+pub const PASSWORD_NEEDS_SYMBOL: &str = "password must contain a symbol";
+pub const PASSWORD_CONTAINS_PERSONAL_INFO: &str =
+    "password must not contain your email address or username";
+pub const PASSWORD_BREACHED: &str =
+    "password appears in a known data breach, please choose a different one";
+pub const PASSWORD_REUSED: &str = "password was used recently, please choose a different one";
+pub const PASSWORD_EXPIRED: &str = "password has expired, please reset it";
 pub const USER_NOT_FOUND: &str = "user not found";
 pub const AUTH_REQUIRED: &str = "authentication required";
 pub const INVALID_USER_ID: &str = "invalid user id";
 pub const INVALID_BOOK_ID: &str = "invalid book id";
+pub const CSRF_TOKEN_INVALID: &str = "missing or invalid csrf token";
 pub const PERMISSION_DENIED: &str = "permission denied";
+pub const OIDC_NOT_CONFIGURED: &str = "single sign-on is not configured";
+pub const OIDC_LOGIN_FAILED: &str = "single sign-on failed";
+pub const OIDC_INVALID_STATE: &str = "invalid or expired single sign-on state";
+pub const OIDC_EMAIL_NOT_VERIFIED: &str = "identity provider did not assert a verified email";
+pub const OIDC_ACCOUNT_NOT_FOUND: &str = "no account is linked to this identity";
+pub const OIDC_ACCOUNT_NOT_LINKABLE: &str =
+    "this account cannot be linked to single sign-on, sign in as usual";
+pub const LDAP_ACCOUNT_NOT_LINKABLE: &str =
+    "an account with this email exists and cannot be linked to the directory";
+pub const CANNOT_IMPERSONATE_ADMIN: &str = "administrators cannot be impersonated";
+pub const NOT_ALLOWED_WHILE_IMPERSONATING: &str = "this action is not allowed while impersonating";
+pub const ACCOUNT_DISABLED: &str = "account is disabled";
+pub const ACCOUNT_SUSPENDED: &str = "account is suspended";
+pub const USER_HAS_OPEN_LOANS: &str =
+    "user still has borrowed books, return them or delete with force_return=true";
+pub const USER_ALREADY_DELETED: &str = "user is already deleted";
+pub const USER_NOT_DELETED: &str = "user is not deleted";
+pub const RESTORE_WINDOW_EXPIRED: &str = "the restore window for this user has passed";
+pub const CANNOT_MODERATE_SELF: &str = "administrators cannot suspend or delete themselves";
+pub const MEMBERSHIP_EXPIRED: &str = "membership has expired, please renew it";
+pub const BORROW_LIMIT_REACHED: &str = "borrow limit reached";
+pub const BOOK_ALREADY_BORROWED: &str = "book already borrowed";
+pub const BOOK_NOT_BORROWED: &str = "book not borrowed by user";
+pub const NO_STOCK_AVAILABLE: &str = "no stock available";
+pub const FAMILY_LIMIT_REACHED: &str = "family borrow limit reached";
+pub const DEPENDENT_NOT_FOUND: &str = "dependent not found";
+pub const INVALID_GUARDIAN: &str =
+    "a guardian must be another account that is not itself a dependent";
+pub const GUARDIAN_HAS_DEPENDENTS: &str =
+    "remove or hand over managed dependents before deleting this account";
+pub const MANAGED_DEPENDENT_NEEDS_GUARDIAN: &str =
+    "dependents without their own email must stay linked to a guardian";
+pub const INVALID_EXTENSION: &str = "provide exactly one of days or until";
+pub const UNSUPPORTED_IMPORT_FORMAT: &str = "import must be sent as text/csv or application/json";
+pub const TOO_MANY_IMPORT_ROWS: &str = "import has too many rows";
+pub const DUPLICATE_IMPORT_ROW: &str = "email appears more than once in the import";
+pub const INVALID_API_KEY: &str = "invalid or expired api key";
+pub const INVALID_API_KEY_ID: &str = "invalid api key id";
+pub const INVALID_API_KEY_SCOPE: &str = "unknown api key scope";
+pub const API_KEY_NOT_FOUND: &str = "api key not found";
+pub const API_KEY_SCOPE_MISSING: &str = "api key lacks the scope required for this request";
+pub const API_KEY_NOT_ALLOWED: &str = "this request requires a session token, not an api key";
 pub const INTERNAL_SERVER_ERROR: &str = "internal server error";
 
+pub const OTEL_EXPORTER_OTLP_ENDPOINT: &str = "OTEL_EXPORTER_OTLP_ENDPOINT";
+pub const OTEL_EXPORTER_OTLP_TRACES_ENDPOINT: &str = "OTEL_EXPORTER_OTLP_TRACES_ENDPOINT";
+pub const OTEL_SERVICE_NAME: &str = "OTEL_SERVICE_NAME";
+pub const LOG_LEVEL: &str = "LOG_LEVEL";
+pub const RUST_LOG: &str = "RUST_LOG";
+pub const DEFAULT_LOG_LEVEL: &str = "info";
+pub const DEFAULT_OTEL_SERVICE_NAME: &str = env!("CARGO_PKG_NAME");
+
+pub const CONFIG_FILE: &str = "CONFIG_FILE";
+/// How often the configuration file and TLS certificate are checked for changes
+pub const CONFIG_WATCH_INTERVAL_SECONDS: u64 = 5;
+/// Suffix of the variables naming a file that holds a secret, e.g. `JWT_SECRET_FILE`
+pub const SECRET_FILE_SUFFIX: &str = "_FILE";
+pub const REDACTED: &str = "<redacted>";
+
+pub const DATABASE_BACKEND: &str = "DATABASE_BACKEND";
 pub const MONGO_URI: &str = "MONGO_URI";
 pub const MONGO_DB: &str = "MONGO_DB";
+pub const DATABASE_URL: &str = "DATABASE_URL";
+pub const DATABASE_MAX_CONNECTIONS: &str = "DATABASE_MAX_CONNECTIONS";
+pub const MIGRATE_ON_STARTUP: &str = "MIGRATE_ON_STARTUP";
 pub const JWT_SECRET: &str = "JWT_SECRET";
 pub const JWT_EXP_HOURS: &str = "JWT_EXP_HOURS";
+pub const JWT_ALGORITHM: &str = "JWT_ALGORITHM";
+pub const JWT_PRIVATE_KEY_PATH: &str = "JWT_PRIVATE_KEY_PATH";
+pub const JWT_PUBLIC_KEY_PATH: &str = "JWT_PUBLIC_KEY_PATH";
+pub const JWT_KEY_ID: &str = "JWT_KEY_ID";
+pub const JWT_PREVIOUS_PUBLIC_KEYS: &str = "JWT_PREVIOUS_PUBLIC_KEYS";
+pub const JWT_ISSUER: &str = "JWT_ISSUER";
+pub const JWT_AUDIENCE: &str = "JWT_AUDIENCE";
+pub const ARGON2_MEMORY_KIB: &str = "ARGON2_MEMORY_KIB";
+pub const ARGON2_ITERATIONS: &str = "ARGON2_ITERATIONS";
+pub const ARGON2_PARALLELISM: &str = "ARGON2_PARALLELISM";
+pub const PASSWORD_MIN_LENGTH: &str = "PASSWORD_MIN_LENGTH";
+pub const PASSWORD_MAX_LENGTH: &str = "PASSWORD_MAX_LENGTH";
+pub const PASSWORD_REQUIRE_LOWERCASE: &str = "PASSWORD_REQUIRE_LOWERCASE";
+pub const PASSWORD_REQUIRE_UPPERCASE: &str = "PASSWORD_REQUIRE_UPPERCASE";
+pub const PASSWORD_REQUIRE_DIGIT: &str = "PASSWORD_REQUIRE_DIGIT";
+pub const PASSWORD_REQUIRE_SYMBOL: &str = "PASSWORD_REQUIRE_SYMBOL";
+pub const PASSWORD_DISALLOW_PERSONAL_INFO: &str = "PASSWORD_DISALLOW_PERSONAL_INFO";
+pub const PASSWORD_HISTORY_SIZE: &str = "PASSWORD_HISTORY_SIZE";
+pub const PASSWORD_MAX_AGE_DAYS: &str = "PASSWORD_MAX_AGE_DAYS";
+pub const BREACHED_PASSWORDS_DIR: &str = "BREACHED_PASSWORDS_DIR";
+pub const SESSION_COOKIES: &str = "SESSION_COOKIES";
+pub const SESSION_COOKIE_NAME: &str = "SESSION_COOKIE_NAME";
+pub const CSRF_COOKIE_NAME: &str = "CSRF_COOKIE_NAME";
+pub const SESSION_COOKIE_SECURE: &str = "SESSION_COOKIE_SECURE";
+pub const SESSION_COOKIE_SAME_SITE: &str = "SESSION_COOKIE_SAME_SITE";
+pub const SESSION_COOKIE_DOMAIN: &str = "SESSION_COOKIE_DOMAIN";
 pub const APP_HOST: &str = "APP_HOST";
 pub const APP_PORT: &str = "APP_PORT";
 pub const REDIS_URI: &str = "REDIS_URI";
 pub const SSL_CERT_PATH: &str = "SSL_CERT_PATH";
 pub const SSL_KEY_PATH: &str = "SSL_KEY_PATH";
+pub const SMTP_HOST: &str = "SMTP_HOST";
+pub const SMTP_PORT: &str = "SMTP_PORT";
+pub const SMTP_USERNAME: &str = "SMTP_USERNAME";
+pub const SMTP_PASSWORD: &str = "SMTP_PASSWORD";
+pub const SMTP_TLS: &str = "SMTP_TLS";
+pub const MAIL_FROM: &str = "MAIL_FROM";
+pub const PUBLIC_URL: &str = "PUBLIC_URL";
+pub const PASSWORD_RESET_TTL_MINUTES: &str = "PASSWORD_RESET_TTL_MINUTES";
+pub const IMPERSONATION_TTL_MINUTES: &str = "IMPERSONATION_TTL_MINUTES";
+pub const DELETED_USER_RETENTION_DAYS: &str = "DELETED_USER_RETENTION_DAYS";
+pub const MEMBERSHIP_DURATION_DAYS: &str = "MEMBERSHIP_DURATION_DAYS";
+pub const PATRON_LOAN_LIMITS: &str = "PATRON_LOAN_LIMITS";
+pub const INVITE_TTL_HOURS: &str = "INVITE_TTL_HOURS";
+pub const EMAIL_VERIFICATION_TTL_HOURS: &str = "EMAIL_VERIFICATION_TTL_HOURS";
+pub const EMAIL_VERIFICATION_RESEND_COOLDOWN_SECONDS: &str =
+    "EMAIL_VERIFICATION_RESEND_COOLDOWN_SECONDS";
+pub const REQUIRE_VERIFIED_EMAIL_TO_BORROW: &str = "REQUIRE_VERIFIED_EMAIL_TO_BORROW";
+pub const OIDC_ISSUER_URL: &str = "OIDC_ISSUER_URL";
+pub const OIDC_CLIENT_ID: &str = "OIDC_CLIENT_ID";
+pub const OIDC_CLIENT_SECRET: &str = "OIDC_CLIENT_SECRET";
+pub const OIDC_REDIRECT_URL: &str = "OIDC_REDIRECT_URL";
+pub const OIDC_SCOPES: &str = "OIDC_SCOPES";
+pub const OIDC_AUTO_PROVISION: &str = "OIDC_AUTO_PROVISION";
+pub const LDAP_URL: &str = "LDAP_URL";
+pub const LDAP_STARTTLS: &str = "LDAP_STARTTLS";
+pub const LDAP_CA_CERT_PATH: &str = "LDAP_CA_CERT_PATH";
+pub const LDAP_BIND_DN: &str = "LDAP_BIND_DN";
+pub const LDAP_BIND_PASSWORD: &str = "LDAP_BIND_PASSWORD";
+pub const LDAP_BASE_DN: &str = "LDAP_BASE_DN";
+pub const LDAP_USER_FILTER: &str = "LDAP_USER_FILTER";
+pub const LDAP_USERNAME_ATTRIBUTE: &str = "LDAP_USERNAME_ATTRIBUTE";
+pub const LDAP_EMAIL_ATTRIBUTE: &str = "LDAP_EMAIL_ATTRIBUTE";
+pub const LDAP_GROUP_ATTRIBUTE: &str = "LDAP_GROUP_ATTRIBUTE";
+pub const LDAP_ADMIN_GROUPS: &str = "LDAP_ADMIN_GROUPS";
+pub const LDAP_SYNC_INTERVAL_SECONDS: &str = "LDAP_SYNC_INTERVAL_SECONDS";
+pub const HEALTH_CHECK_TIMEOUT_MS: &str = "HEALTH_CHECK_TIMEOUT_MS";
+pub const METRICS_TOKEN: &str = "METRICS_TOKEN";
+pub const RATE_LIMIT_AUTH: &str = "RATE_LIMIT_AUTH";
+pub const RATE_LIMIT_BOOK: &str = "RATE_LIMIT_BOOK";
+pub const RATE_LIMIT_ADMIN: &str = "RATE_LIMIT_ADMIN";
+pub const RATE_LIMIT_ALLOWLIST: &str = "RATE_LIMIT_ALLOWLIST";
+pub const RATE_LIMIT_TRUST_FORWARDED_FOR: &str = "RATE_LIMIT_TRUST_FORWARDED_FOR";
+pub const CORS_ALLOWED_ORIGINS: &str = "CORS_ALLOWED_ORIGINS";
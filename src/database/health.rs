@@ -0,0 +1,17 @@
+use crate::errors::AppError;
+use async_trait::async_trait;
+use std::sync::Arc;
+
+/// A dependency the service cannot answer requests without.
+#[async_trait]
+pub trait HealthCheck: Send + Sync {
+    /// Name reported by the readiness probe, e.g. `mongodb`.
+    fn name(&self) -> &'static str;
+
+    /// Sends the dependency the cheapest request it answers.
+    async fn ping(&self) -> Result<(), AppError>;
+}
+
+/// Every dependency checked by the readiness probe.
+#[derive(Clone, Default)]
+pub struct HealthChecks(pub Vec<Arc<dyn HealthCheck>>);
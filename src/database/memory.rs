@@ -0,0 +1,884 @@
+//! In-process implementations of every repository and store, used to run the HTTP
+//! API in tests without MongoDB or Redis. They follow the semantics of the Mongo
+//! and Redis implementations, including the atomic stock guard on borrowing.
+
+use crate::constants::{
+    BOOK_ALREADY_BORROWED, BOOK_ALREADY_EXISTS, BOOK_NOT_BORROWED, BORROW_LIMIT_REACHED,
+    EMAIL_ALREADY_EXISTS, NO_STOCK_AVAILABLE, USER_NOT_FOUND,
+};
+use crate::database::repository::{ApiKeyRepository, BookRepository, UserRepository};
+use crate::database::store::{
+    EmailVerificationStore, OidcStateStore, PasswordResetStore, RateLimitDecision, RateLimitStore,
+    TokenBlacklist,
+};
+use crate::errors::AppError;
+use crate::models::api_key::ApiKey;
+use crate::models::book::Book;
+use crate::models::user::{PatronCategory, Suspension, User};
+use async_trait::async_trait;
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::DateTime;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Applies `f` to the record with the given id, if there is one. Updating a missing
+/// record is a no-op, as with `update_one`.
+fn update<T>(records: &Mutex<Vec<T>>, matches: impl Fn(&T) -> bool, f: impl FnOnce(&mut T)) {
+    if let Some(record) = lock(records).iter_mut().find(|r| matches(r)) {
+        f(record);
+    }
+}
+
+#[derive(Default)]
+pub struct MemoryUserRepository {
+    users: Mutex<Vec<User>>,
+}
+
+impl MemoryUserRepository {
+    fn find(&self, matches: impl Fn(&User) -> bool) -> Option<User> {
+        lock(&self.users).iter().find(|u| matches(u)).cloned()
+    }
+
+    fn filter(&self, matches: impl Fn(&User) -> bool) -> Vec<User> {
+        lock(&self.users)
+            .iter()
+            .filter(|u| matches(u))
+            .cloned()
+            .collect()
+    }
+
+    fn update(&self, id: &ObjectId, f: impl FnOnce(&mut User)) {
+        update(&self.users, |u| u.id == *id, f)
+    }
+
+    /// Moves a user to `email`, refusing an address another account already has.
+    fn update_email_to(
+        &self,
+        id: &ObjectId,
+        email: &str,
+        f: impl FnOnce(&mut User),
+    ) -> Result<(), AppError> {
+        let mut users = lock(&self.users);
+        if email_taken(&users, id, email) {
+            return Err(AppError::Conflict(EMAIL_ALREADY_EXISTS.into()));
+        }
+        if let Some(user) = users.iter_mut().find(|u| u.id == *id) {
+            f(user);
+        }
+        Ok(())
+    }
+}
+
+/// Mirrors the unique email index, which leaves out the empty email of dependents.
+fn email_taken(users: &[User], id: &ObjectId, email: &str) -> bool {
+    !email.is_empty() && users.iter().any(|u| u.id != *id && u.email == email)
+}
+
+#[async_trait]
+impl UserRepository for MemoryUserRepository {
+    async fn find_by_email(&self, email: &str) -> Result<Option<User>, AppError> {
+        Ok(self.find(|u| u.email == email))
+    }
+
+    async fn find_by_id(&self, id: &ObjectId) -> Result<Option<User>, AppError> {
+        Ok(self.find(|u| u.id == *id))
+    }
+
+    async fn find_by_oidc_subject(&self, subject: &str) -> Result<Option<User>, AppError> {
+        Ok(self.find(|u| u.oidc_subject.as_deref() == Some(subject)))
+    }
+
+    async fn set_oidc_subject(&self, id: &ObjectId, subject: &str) -> Result<(), AppError> {
+        self.update(id, |u| u.oidc_subject = Some(subject.to_string()));
+        Ok(())
+    }
+
+    async fn find_by_ldap_dn(&self, dn: &str) -> Result<Option<User>, AppError> {
+        Ok(self.find(|u| u.ldap_dn.as_deref() == Some(dn)))
+    }
+
+    async fn find_ldap_users(&self) -> Result<Vec<User>, AppError> {
+        Ok(self.filter(|u| u.ldap_dn.is_some() && !u.disabled))
+    }
+
+    async fn sync_ldap_account(
+        &self,
+        id: &ObjectId,
+        dn: &str,
+        username: &str,
+        email: &str,
+        is_admin: bool,
+    ) -> Result<(), AppError> {
+        self.update(id, |u| {
+            u.ldap_dn = Some(dn.to_string());
+            u.username = username.to_string();
+            u.email = email.to_string();
+            u.email_verified = true;
+            u.is_admin = is_admin;
+            u.pending_email = None;
+        });
+        Ok(())
+    }
+
+    async fn disable(&self, id: &ObjectId) -> Result<(), AppError> {
+        self.update(id, |u| {
+            u.disabled = true;
+            u.token_version += 1;
+        });
+        Ok(())
+    }
+
+    async fn enable(&self, id: &ObjectId) -> Result<(), AppError> {
+        self.update(id, |u| u.disabled = false);
+        Ok(())
+    }
+
+    async fn anonymize(&self, id: &ObjectId) -> Result<(), AppError> {
+        self.update(id, |u| {
+            *u = User {
+                id: u.id,
+                email: String::new(),
+                username: User::anonymized_username(&u.id),
+                password_hash: String::new(),
+                password_history: Vec::new(),
+                password_changed_at: None,
+                email_verified: false,
+                pending_email: None,
+                oidc_subject: None,
+                ldap_dn: None,
+                disabled: true,
+                suspension: None,
+                deleted_at: None,
+                category: u.category,
+                membership_started_at: u.membership_started_at,
+                membership_expires_at: u.membership_expires_at,
+                guardian_id: None,
+                loan_limit: None,
+                family_loan_cap: None,
+                is_admin: false,
+                token_version: u.token_version + 1,
+                borrowed_books: Vec::new(),
+            }
+        });
+        Ok(())
+    }
+
+    async fn suspend(&self, id: &ObjectId, suspension: &Suspension) -> Result<(), AppError> {
+        self.update(id, |u| u.suspension = Some(suspension.clone()));
+        Ok(())
+    }
+
+    async fn reactivate(&self, id: &ObjectId) -> Result<(), AppError> {
+        self.update(id, |u| u.suspension = None);
+        Ok(())
+    }
+
+    async fn soft_delete(&self, id: &ObjectId) -> Result<(), AppError> {
+        self.update(id, |u| {
+            u.deleted_at = Some(DateTime::now());
+            u.token_version += 1;
+        });
+        Ok(())
+    }
+
+    async fn restore(&self, id: &ObjectId) -> Result<(), AppError> {
+        self.update(id, |u| u.deleted_at = None);
+        Ok(())
+    }
+
+    async fn purge_deleted(&self, cutoff: DateTime) -> Result<Vec<ObjectId>, AppError> {
+        let mut users = lock(&self.users);
+        let expired = |u: &User| u.deleted_at.is_some_and(|at| at < cutoff);
+        let ids = users.iter().filter(|u| expired(u)).map(|u| u.id).collect();
+        users.retain(|u| !expired(u));
+        Ok(ids)
+    }
+
+    async fn set_category(&self, id: &ObjectId, category: PatronCategory) -> Result<(), AppError> {
+        self.update(id, |u| u.category = category);
+        Ok(())
+    }
+
+    async fn set_membership_expiry(
+        &self,
+        id: &ObjectId,
+        expires_at: Option<DateTime>,
+    ) -> Result<(), AppError> {
+        self.update(id, |u| u.membership_expires_at = expires_at);
+        Ok(())
+    }
+
+    async fn extend_memberships_by_days(
+        &self,
+        category: PatronCategory,
+        days: i64,
+    ) -> Result<u64, AppError> {
+        let now = DateTime::now().timestamp_millis();
+        let mut updated = 0;
+        for user in lock(&self.users).iter_mut() {
+            if user.category != category {
+                continue;
+            }
+            if let Some(expires_at) = user.membership_expires_at {
+                let from = expires_at.timestamp_millis().max(now);
+                user.membership_expires_at =
+                    Some(DateTime::from_millis(from + days * 24 * 60 * 60 * 1000));
+                updated += 1;
+            }
+        }
+        Ok(updated)
+    }
+
+    async fn extend_memberships_until(
+        &self,
+        category: PatronCategory,
+        until: DateTime,
+    ) -> Result<u64, AppError> {
+        let mut updated = 0;
+        for user in lock(&self.users).iter_mut() {
+            if user.category == category && user.membership_expires_at.is_some_and(|e| e < until) {
+                user.membership_expires_at = Some(until);
+                updated += 1;
+            }
+        }
+        Ok(updated)
+    }
+
+    async fn find_dependents(&self, guardian_id: &ObjectId) -> Result<Vec<User>, AppError> {
+        Ok(self.filter(|u| u.guardian_id == Some(*guardian_id) && u.deleted_at.is_none()))
+    }
+
+    async fn set_guardian(
+        &self,
+        id: &ObjectId,
+        guardian_id: Option<&ObjectId>,
+    ) -> Result<(), AppError> {
+        self.update(id, |u| {
+            u.guardian_id = guardian_id.copied();
+            if guardian_id.is_none() {
+                u.loan_limit = None;
+            }
+        });
+        Ok(())
+    }
+
+    async fn set_loan_limit(&self, id: &ObjectId, limit: Option<u32>) -> Result<(), AppError> {
+        self.update(id, |u| u.loan_limit = limit);
+        Ok(())
+    }
+
+    async fn set_family_loan_cap(&self, id: &ObjectId, cap: Option<u32>) -> Result<(), AppError> {
+        self.update(id, |u| u.family_loan_cap = cap);
+        Ok(())
+    }
+
+    async fn find_all(&self) -> Result<Vec<User>, AppError> {
+        Ok(lock(&self.users).clone())
+    }
+
+    async fn set_admin(&self, id: &ObjectId, is_admin: bool) -> Result<(), AppError> {
+        self.update(id, |u| u.is_admin = is_admin);
+        Ok(())
+    }
+
+    async fn create(&self, user: &User) -> Result<(), AppError> {
+        let mut users = lock(&self.users);
+        if users.iter().any(|u| u.id == user.id) {
+            return Err(AppError::Internal);
+        }
+        if email_taken(&users, &user.id, &user.email) {
+            return Err(AppError::Conflict(EMAIL_ALREADY_EXISTS.into()));
+        }
+        users.push(user.clone());
+        Ok(())
+    }
+
+    async fn update_email(&self, id: &ObjectId, new_email: &str) -> Result<(), AppError> {
+        self.update_email_to(id, new_email, |u| {
+            u.email = new_email.to_string();
+            u.email_verified = false;
+            u.pending_email = None;
+        })
+    }
+
+    async fn set_pending_email(&self, id: &ObjectId, email: &str) -> Result<(), AppError> {
+        self.update(id, |u| u.pending_email = Some(email.to_string()));
+        Ok(())
+    }
+
+    async fn confirm_email(&self, id: &ObjectId, email: &str) -> Result<(), AppError> {
+        self.update_email_to(id, email, |u| {
+            u.email = email.to_string();
+            u.email_verified = true;
+            u.pending_email = None;
+        })
+    }
+
+    async fn mark_email_verified(&self, id: &ObjectId) -> Result<(), AppError> {
+        self.update(id, |u| u.email_verified = true);
+        Ok(())
+    }
+
+    async fn update_username(&self, id: &ObjectId, new_username: &str) -> Result<(), AppError> {
+        self.update(id, |u| u.username = new_username.to_string());
+        Ok(())
+    }
+
+    async fn update_password(&self, id: &ObjectId, password_hash: &str) -> Result<(), AppError> {
+        self.update(id, |u| u.password_hash = password_hash.to_string());
+        Ok(())
+    }
+
+    async fn change_password(
+        &self,
+        id: &ObjectId,
+        password_hash: &str,
+        password_history: &[String],
+    ) -> Result<(), AppError> {
+        self.update(id, |u| {
+            u.password_hash = password_hash.to_string();
+            u.password_history = password_history.to_vec();
+            u.password_changed_at = Some(DateTime::now());
+        });
+        Ok(())
+    }
+
+    async fn update_token_version(
+        &self,
+        id: &ObjectId,
+        token_version: i32,
+    ) -> Result<(), AppError> {
+        self.update(id, |u| u.token_version = token_version);
+        Ok(())
+    }
+
+    async fn add_borrowed_book(
+        &self,
+        user_id: &ObjectId,
+        book_id: &ObjectId,
+        limit: usize,
+    ) -> Result<(), AppError> {
+        let mut users = lock(&self.users);
+        let user = users
+            .iter_mut()
+            .find(|u| u.id == *user_id)
+            .ok_or_else(|| AppError::NotFound(USER_NOT_FOUND.into()))?;
+
+        if user.borrowed_books.contains(book_id) {
+            return Err(AppError::BadRequest(BOOK_ALREADY_BORROWED.into()));
+        }
+
+        if user.borrowed_books.len() >= limit {
+            return Err(AppError::BadRequest(BORROW_LIMIT_REACHED.into()));
+        }
+
+        user.borrowed_books.push(*book_id);
+        Ok(())
+    }
+
+    async fn remove_borrowed_book(
+        &self,
+        user_id: &ObjectId,
+        book_id: &ObjectId,
+    ) -> Result<(), AppError> {
+        let mut users = lock(&self.users);
+        match users
+            .iter_mut()
+            .find(|u| u.id == *user_id && u.borrowed_books.contains(book_id))
+        {
+            Some(user) => {
+                user.borrowed_books.retain(|b| b != book_id);
+                Ok(())
+            }
+            None => Err(AppError::BadRequest(BOOK_NOT_BORROWED.into())),
+        }
+    }
+
+    async fn count_active_loans(&self) -> Result<u64, AppError> {
+        let users = lock(&self.users);
+        Ok(users.iter().map(|u| u.borrowed_books.len() as u64).sum())
+    }
+}
+
+#[derive(Default)]
+pub struct MemoryBookRepository {
+    books: Mutex<Vec<Book>>,
+}
+
+impl MemoryBookRepository {
+    fn filter(&self, matches: impl Fn(&Book) -> bool) -> Vec<Book> {
+        lock(&self.books)
+            .iter()
+            .filter(|b| matches(b))
+            .cloned()
+            .collect()
+    }
+
+    fn update(&self, id: &ObjectId, f: impl FnOnce(&mut Book)) {
+        update(&self.books, |b| b.id == *id, f)
+    }
+}
+
+#[async_trait]
+impl BookRepository for MemoryBookRepository {
+    async fn create(&self, title: &str, author: &str) -> Result<Book, AppError> {
+        let mut books = lock(&self.books);
+        if books.iter().any(|b| b.title == title && b.author == author) {
+            return Err(AppError::Conflict(BOOK_ALREADY_EXISTS.into()));
+        }
+
+        let book = Book {
+            id: ObjectId::new(),
+            title: title.to_string(),
+            author: author.to_string(),
+            stock: 0,
+        };
+        books.push(book.clone());
+        Ok(book)
+    }
+
+    async fn find_by_id(&self, id: &ObjectId) -> Result<Option<Book>, AppError> {
+        Ok(self.filter(|b| b.id == *id).into_iter().next())
+    }
+
+    async fn find_all(&self) -> Result<Vec<Book>, AppError> {
+        Ok(lock(&self.books).clone())
+    }
+
+    async fn find_by_title(&self, title: &str) -> Result<Vec<Book>, AppError> {
+        Ok(self.filter(|b| b.title == title))
+    }
+
+    async fn find_by_author(&self, author: &str) -> Result<Vec<Book>, AppError> {
+        Ok(self.filter(|b| b.author == author))
+    }
+
+    async fn delete_by_id(&self, id: &ObjectId) -> Result<(), AppError> {
+        lock(&self.books).retain(|b| b.id != *id);
+        Ok(())
+    }
+
+    async fn update_title(&self, id: &ObjectId, title: &str) -> Result<(), AppError> {
+        self.update(id, |b| b.title = title.to_string());
+        Ok(())
+    }
+
+    async fn update_author(&self, id: &ObjectId, author: &str) -> Result<(), AppError> {
+        self.update(id, |b| b.author = author.to_string());
+        Ok(())
+    }
+
+    async fn update_stock(&self, id: &ObjectId, stock: i32) -> Result<(), AppError> {
+        self.update(id, |b| b.stock = stock);
+        Ok(())
+    }
+
+    async fn borrow_book(&self, id: &ObjectId) -> Result<(), AppError> {
+        let mut books = lock(&self.books);
+        match books.iter_mut().find(|b| b.id == *id && b.stock > 0) {
+            Some(book) => {
+                book.stock -= 1;
+                Ok(())
+            }
+            None => Err(AppError::BadRequest(NO_STOCK_AVAILABLE.into())),
+        }
+    }
+
+    async fn return_book(&self, id: &ObjectId) -> Result<(), AppError> {
+        self.update(id, |b| b.stock += 1);
+        Ok(())
+    }
+
+    async fn count_out_of_stock(&self) -> Result<u64, AppError> {
+        Ok(self.filter(|b| b.stock <= 0).len() as u64)
+    }
+}
+
+#[derive(Default)]
+pub struct MemoryApiKeyRepository {
+    keys: Mutex<Vec<ApiKey>>,
+}
+
+impl MemoryApiKeyRepository {
+    fn find(&self, matches: impl Fn(&ApiKey) -> bool) -> Option<ApiKey> {
+        lock(&self.keys).iter().find(|k| matches(k)).cloned()
+    }
+}
+
+#[async_trait]
+impl ApiKeyRepository for MemoryApiKeyRepository {
+    async fn create(&self, api_key: &ApiKey) -> Result<(), AppError> {
+        lock(&self.keys).push(api_key.clone());
+        Ok(())
+    }
+
+    async fn find_by_id(&self, id: &ObjectId) -> Result<Option<ApiKey>, AppError> {
+        Ok(self.find(|k| k.id == *id))
+    }
+
+    async fn find_by_hash(&self, key_hash: &str) -> Result<Option<ApiKey>, AppError> {
+        Ok(self.find(|k| k.key_hash == key_hash))
+    }
+
+    async fn find_by_user(&self, user_id: &ObjectId) -> Result<Vec<ApiKey>, AppError> {
+        Ok(lock(&self.keys)
+            .iter()
+            .filter(|k| k.user_id == *user_id)
+            .cloned()
+            .collect())
+    }
+
+    async fn delete_by_id(&self, id: &ObjectId) -> Result<(), AppError> {
+        lock(&self.keys).retain(|k| k.id != *id);
+        Ok(())
+    }
+
+    async fn delete_by_user(&self, user_id: &ObjectId) -> Result<(), AppError> {
+        lock(&self.keys).retain(|k| k.user_id != *user_id);
+        Ok(())
+    }
+
+    async fn touch_last_used(&self, id: &ObjectId) -> Result<(), AppError> {
+        update(
+            &self.keys,
+            |k| k.id == *id,
+            |k| k.last_used_at = Some(DateTime::now()),
+        );
+        Ok(())
+    }
+}
+
+/// A map whose entries disappear after their TTL, like keys set with `SETEX`.
+struct ExpiringMap<V> {
+    entries: Mutex<HashMap<String, (V, Instant)>>,
+}
+
+impl<V: Clone> ExpiringMap<V> {
+    fn insert(&self, key: String, value: V, ttl_seconds: i64) {
+        let expires_at = Instant::now() + Duration::from_secs(ttl_seconds.max(0) as u64);
+        lock(&self.entries).insert(key, (value, expires_at));
+    }
+
+    /// Inserts only if there is no live entry, like `SET NX`.
+    fn insert_new(&self, key: String, value: V, ttl_seconds: i64) -> bool {
+        if self.get(&key).is_some() {
+            return false;
+        }
+        self.insert(key, value, ttl_seconds);
+        true
+    }
+
+    fn get(&self, key: &str) -> Option<V> {
+        lock(&self.entries)
+            .get(key)
+            .filter(|(_, expires_at)| *expires_at > Instant::now())
+            .map(|(value, _)| value.clone())
+    }
+
+    fn take(&self, key: &str) -> Option<V> {
+        lock(&self.entries)
+            .remove(key)
+            .filter(|(_, expires_at)| *expires_at > Instant::now())
+            .map(|(value, _)| value)
+    }
+}
+
+impl<V> Default for ExpiringMap<V> {
+    fn default() -> Self {
+        Self {
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct MemoryTokenBlacklist {
+    tokens: ExpiringMap<()>,
+}
+
+#[async_trait]
+impl TokenBlacklist for MemoryTokenBlacklist {
+    async fn add_token(&self, token: &str, exp_seconds: i64) -> Result<(), AppError> {
+        self.tokens.insert(token.to_string(), (), exp_seconds);
+        Ok(())
+    }
+
+    async fn is_blacklisted(&self, token: &str) -> Result<bool, AppError> {
+        Ok(self.tokens.get(token).is_some())
+    }
+}
+
+#[derive(Default)]
+pub struct MemoryPasswordResetStore {
+    tokens: ExpiringMap<String>,
+    latest_by_user: ExpiringMap<String>,
+}
+
+#[async_trait]
+impl PasswordResetStore for MemoryPasswordResetStore {
+    async fn store(
+        &self,
+        token_hash: &str,
+        user_id: &str,
+        ttl_seconds: i64,
+    ) -> Result<(), AppError> {
+        if let Some(previous) = self.latest_by_user.take(user_id) {
+            self.tokens.take(&previous);
+        }
+        self.tokens
+            .insert(token_hash.to_string(), user_id.to_string(), ttl_seconds);
+        self.latest_by_user
+            .insert(user_id.to_string(), token_hash.to_string(), ttl_seconds);
+        Ok(())
+    }
+
+    async fn peek(&self, token_hash: &str) -> Result<Option<String>, AppError> {
+        Ok(self.tokens.get(token_hash))
+    }
+
+    async fn consume(&self, token_hash: &str) -> Result<Option<String>, AppError> {
+        let user_id = self.tokens.take(token_hash);
+        if let Some(ref user_id) = user_id {
+            self.latest_by_user.take(user_id);
+        }
+        Ok(user_id)
+    }
+}
+
+#[derive(Default)]
+pub struct MemoryEmailVerificationStore {
+    tokens: ExpiringMap<(String, String)>,
+    cooldowns: ExpiringMap<()>,
+}
+
+#[async_trait]
+impl EmailVerificationStore for MemoryEmailVerificationStore {
+    async fn store(
+        &self,
+        token_hash: &str,
+        user_id: &str,
+        email: &str,
+        ttl_seconds: i64,
+    ) -> Result<(), AppError> {
+        let value = (user_id.to_string(), email.to_string());
+        self.tokens
+            .insert(token_hash.to_string(), value, ttl_seconds);
+        Ok(())
+    }
+
+    async fn consume(&self, token_hash: &str) -> Result<Option<(String, String)>, AppError> {
+        Ok(self.tokens.take(token_hash))
+    }
+
+    async fn try_start_cooldown(
+        &self,
+        user_id: &str,
+        cooldown_seconds: i64,
+    ) -> Result<bool, AppError> {
+        Ok(self
+            .cooldowns
+            .insert_new(user_id.to_string(), (), cooldown_seconds))
+    }
+}
+
+#[derive(Default)]
+pub struct MemoryOidcStateStore {
+    states: ExpiringMap<(String, String)>,
+}
+
+#[async_trait]
+impl OidcStateStore for MemoryOidcStateStore {
+    async fn store(
+        &self,
+        state: &str,
+        pkce_verifier: &str,
+        nonce: &str,
+        ttl_seconds: i64,
+    ) -> Result<(), AppError> {
+        let value = (pkce_verifier.to_string(), nonce.to_string());
+        self.states.insert(state.to_string(), value, ttl_seconds);
+        Ok(())
+    }
+
+    async fn consume(&self, state: &str) -> Result<Option<(String, String)>, AppError> {
+        Ok(self.states.take(state))
+    }
+}
+
+#[derive(Default)]
+pub struct MemoryRateLimitStore {
+    hits: Mutex<HashMap<String, VecDeque<Instant>>>,
+}
+
+#[async_trait]
+impl RateLimitStore for MemoryRateLimitStore {
+    async fn hit(
+        &self,
+        key: &str,
+        limit: u32,
+        window_ms: u64,
+    ) -> Result<RateLimitDecision, AppError> {
+        let now = Instant::now();
+        let window = Duration::from_millis(window_ms);
+        let mut hits = lock(&self.hits);
+        let times = hits.entry(key.to_string()).or_default();
+        while times.front().is_some_and(|hit| now - *hit >= window) {
+            times.pop_front();
+        }
+
+        let allowed = times.len() < limit as usize;
+        if allowed {
+            times.push_back(now);
+        }
+        let oldest = times.front().copied().unwrap_or(now);
+        Ok(RateLimitDecision {
+            allowed,
+            remaining: limit.saturating_sub(times.len() as u32),
+            reset_after_ms: (oldest + window - now).as_millis() as u64,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[actix_web::test]
+    async fn concurrent_borrows_never_drive_stock_negative() {
+        let repo = Arc::new(MemoryBookRepository::default());
+        let book = repo.create("Dune", "Frank Herbert").await.unwrap();
+        repo.update_stock(&book.id, 5).await.unwrap();
+
+        let handles: Vec<_> = (0..50)
+            .map(|_| {
+                let repo = repo.clone();
+                std::thread::spawn(move || {
+                    futures::executor::block_on(repo.borrow_book(&book.id)).is_ok()
+                })
+            })
+            .collect();
+        let borrowed = handles
+            .into_iter()
+            .map(|h| h.join().unwrap())
+            .filter(|ok| *ok)
+            .count();
+
+        assert_eq!(borrowed, 5);
+        let stock = repo.find_by_id(&book.id).await.unwrap().unwrap().stock;
+        assert_eq!(stock, 0);
+    }
+
+    #[actix_web::test]
+    async fn borrow_limit_and_duplicates_are_rejected() {
+        let repo = MemoryUserRepository::default();
+        let user = user();
+        repo.create(&user).await.unwrap();
+        let (first, second) = (ObjectId::new(), ObjectId::new());
+
+        repo.add_borrowed_book(&user.id, &first, 1).await.unwrap();
+        assert!(matches!(
+            repo.add_borrowed_book(&user.id, &first, 2).await,
+            Err(AppError::BadRequest(msg)) if msg == BOOK_ALREADY_BORROWED
+        ));
+        assert!(matches!(
+            repo.add_borrowed_book(&user.id, &second, 1).await,
+            Err(AppError::BadRequest(msg)) if msg == BORROW_LIMIT_REACHED
+        ));
+
+        repo.remove_borrowed_book(&user.id, &first).await.unwrap();
+        assert!(repo.remove_borrowed_book(&user.id, &first).await.is_err());
+    }
+
+    #[actix_web::test]
+    async fn emails_are_unique_except_for_dependents() {
+        let repo = MemoryUserRepository::default();
+        let first = user();
+        repo.create(&first).await.unwrap();
+
+        let duplicate = User {
+            id: ObjectId::new(),
+            ..user()
+        };
+        assert!(matches!(
+            repo.create(&duplicate).await,
+            Err(AppError::Conflict(msg)) if msg == EMAIL_ALREADY_EXISTS
+        ));
+
+        let other = User {
+            id: ObjectId::new(),
+            email: "other@example.com".into(),
+            ..user()
+        };
+        repo.create(&other).await.unwrap();
+        assert!(matches!(
+            repo.update_email(&other.id, &first.email).await,
+            Err(AppError::Conflict(msg)) if msg == EMAIL_ALREADY_EXISTS
+        ));
+
+        for _ in 0..2 {
+            let dependent = User {
+                id: ObjectId::new(),
+                email: String::new(),
+                ..user()
+            };
+            repo.create(&dependent).await.unwrap();
+        }
+    }
+
+    #[actix_web::test]
+    async fn expired_entries_are_gone() {
+        let blacklist = MemoryTokenBlacklist::default();
+        blacklist.add_token("live", 60).await.unwrap();
+        blacklist.add_token("dead", 0).await.unwrap();
+
+        assert!(blacklist.is_blacklisted("live").await.unwrap());
+        assert!(!blacklist.is_blacklisted("dead").await.unwrap());
+    }
+
+    #[actix_web::test]
+    async fn a_new_reset_token_replaces_the_previous_one() {
+        let store = MemoryPasswordResetStore::default();
+        store.store("first", "user", 60).await.unwrap();
+        store.store("second", "user", 60).await.unwrap();
+
+        assert_eq!(store.consume("first").await.unwrap(), None);
+        assert_eq!(
+            store.consume("second").await.unwrap().as_deref(),
+            Some("user")
+        );
+        assert_eq!(store.consume("second").await.unwrap(), None);
+    }
+
+    fn user() -> User {
+        User {
+            id: ObjectId::new(),
+            email: "reader@example.com".into(),
+            username: "reader".into(),
+            password_hash: String::new(),
+            password_history: Vec::new(),
+            password_changed_at: None,
+            email_verified: true,
+            pending_email: None,
+            oidc_subject: None,
+            ldap_dn: None,
+            disabled: false,
+            suspension: None,
+            deleted_at: None,
+            category: PatronCategory::Public,
+            membership_started_at: None,
+            membership_expires_at: None,
+            guardian_id: None,
+            loan_limit: None,
+            family_loan_cap: None,
+            is_admin: false,
+            token_version: 0,
+            borrowed_books: Vec::new(),
+        }
+    }
+}
@@ -0,0 +1,247 @@
+//! Versioned schema changes for MongoDB: indexes and collection validators.
+//!
+//! Applied versions are recorded in the `schema_migrations` collection. Every migration
+//! is idempotent, so two instances starting at once can both run it safely. The SQL
+//! backends use the sqlx migrations under `migrations/` instead.
+
+use crate::constants::{
+    COLLECTION_API_KEYS, COLLECTION_BOOKS, COLLECTION_SCHEMA_MIGRATIONS, COLLECTION_USERS,
+};
+use crate::database::mongodb::is_duplicate_key;
+use futures::future::BoxFuture;
+use futures::stream::TryStreamExt;
+use mongodb::bson::{doc, DateTime, Document};
+use mongodb::error::{ErrorKind, Result};
+use mongodb::options::IndexOptions;
+use mongodb::{Database, IndexModel};
+
+/// MongoDB's `NamespaceExists` error code.
+const NAMESPACE_EXISTS: i32 = 48;
+
+struct Migration {
+    version: i32,
+    description: &'static str,
+    apply: for<'a> fn(&'a Database) -> BoxFuture<'a, Result<()>>,
+}
+
+/// Every migration, oldest first. Versions are never reused or reordered.
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "user indexes: unique email, external identities, guardians, loans",
+        apply: user_indexes,
+    },
+    Migration {
+        version: 2,
+        description: "book indexes: unique title and author, author lookups",
+        apply: book_indexes,
+    },
+    Migration {
+        version: 3,
+        description: "api key indexes: unique key hash, keys by user",
+        apply: api_key_indexes,
+    },
+    Migration {
+        version: 4,
+        description: "$jsonSchema validators for users, books and api keys",
+        apply: validators,
+    },
+    Migration {
+        version: 5,
+        description: "mark accounts created before email verification as verified",
+        apply: verify_existing_emails,
+    },
+];
+
+/// Applies the migrations not yet recorded and returns their versions.
+pub async fn run_migrations(db: &Database) -> Result<Vec<i32>> {
+    let applied = applied_versions(db).await?;
+    let records = db.collection::<Document>(COLLECTION_SCHEMA_MIGRATIONS);
+    let mut ran = Vec::new();
+
+    for migration in MIGRATIONS.iter().filter(|m| !applied.contains(&m.version)) {
+        tracing::info!(
+            "Applying migration {}: {}",
+            migration.version,
+            migration.description
+        );
+        (migration.apply)(db).await?;
+
+        let record = doc! {
+            "_id": migration.version,
+            "description": migration.description,
+            "applied_at": DateTime::now(),
+        };
+        match records.insert_one(record).await {
+            // Another instance recorded it first; the migration itself is idempotent.
+            Err(e) if !is_duplicate_key(&e) => return Err(e),
+            _ => ran.push(migration.version),
+        }
+    }
+
+    Ok(ran)
+}
+
+/// Versions known to this build that the database has not recorded yet.
+pub async fn pending_migrations(db: &Database) -> Result<Vec<i32>> {
+    let applied = applied_versions(db).await?;
+    Ok(MIGRATIONS
+        .iter()
+        .map(|m| m.version)
+        .filter(|version| !applied.contains(version))
+        .collect())
+}
+
+async fn applied_versions(db: &Database) -> Result<Vec<i32>> {
+    db.collection::<Document>(COLLECTION_SCHEMA_MIGRATIONS)
+        .find(doc! {})
+        .await?
+        .try_filter_map(|record| async move { Ok(record.get_i32("_id").ok()) })
+        .try_collect()
+        .await
+}
+
+fn index(keys: Document, name: &str, unique: bool) -> IndexModel {
+    IndexModel::builder()
+        .keys(keys)
+        .options(
+            IndexOptions::builder()
+                .name(name.to_string())
+                .unique(unique)
+                .build(),
+        )
+        .build()
+}
+
+fn user_indexes(db: &Database) -> BoxFuture<'_, Result<()>> {
+    Box::pin(async move {
+        // Managed dependents have no email of their own, so only non-empty addresses
+        // must be unique.
+        let email = IndexModel::builder()
+            .keys(doc! { "email": 1 })
+            .options(
+                IndexOptions::builder()
+                    .name("email_unique".to_string())
+                    .unique(true)
+                    .partial_filter_expression(doc! { "email": { "$gt": "" } })
+                    .build(),
+            )
+            .build();
+
+        db.collection::<Document>(COLLECTION_USERS)
+            .create_indexes([
+                email,
+                index(doc! { "oidc_subject": 1 }, "oidc_subject", false),
+                index(doc! { "ldap_dn": 1 }, "ldap_dn", false),
+                index(doc! { "guardian_id": 1 }, "guardian_id", false),
+                index(doc! { "borrowed_books": 1 }, "borrowed_books", false),
+                index(doc! { "deleted_at": 1 }, "deleted_at", false),
+            ])
+            .await?;
+        Ok(())
+    })
+}
+
+fn book_indexes(db: &Database) -> BoxFuture<'_, Result<()>> {
+    Box::pin(async move {
+        // The compound index also serves lookups by title alone.
+        db.collection::<Document>(COLLECTION_BOOKS)
+            .create_indexes([
+                index(
+                    doc! { "title": 1, "author": 1 },
+                    "title_author_unique",
+                    true,
+                ),
+                index(doc! { "author": 1 }, "author", false),
+            ])
+            .await?;
+        Ok(())
+    })
+}
+
+fn api_key_indexes(db: &Database) -> BoxFuture<'_, Result<()>> {
+    Box::pin(async move {
+        db.collection::<Document>(COLLECTION_API_KEYS)
+            .create_indexes([
+                index(doc! { "key_hash": 1 }, "key_hash_unique", true),
+                index(doc! { "user_id": 1 }, "user_id", false),
+            ])
+            .await?;
+        Ok(())
+    })
+}
+
+fn validators(db: &Database) -> BoxFuture<'_, Result<()>> {
+    Box::pin(async move {
+        let users = doc! {
+            "bsonType": "object",
+            "required": ["email", "username", "password_hash", "is_admin", "token_version"],
+            "properties": {
+                "email": { "bsonType": "string" },
+                "username": { "bsonType": "string" },
+                "password_hash": { "bsonType": "string" },
+                "is_admin": { "bsonType": "bool" },
+                "token_version": { "bsonType": ["int", "long"] },
+                "category": { "enum": ["student", "staff", "public", "child"] },
+                "borrowed_books": { "bsonType": "array", "items": { "bsonType": "objectId" } },
+            },
+        };
+        let books = doc! {
+            "bsonType": "object",
+            "required": ["title", "author", "stock"],
+            "properties": {
+                "title": { "bsonType": "string" },
+                "author": { "bsonType": "string" },
+                "stock": { "bsonType": ["int", "long"], "minimum": 0 },
+            },
+        };
+        let api_keys = doc! {
+            "bsonType": "object",
+            "required": ["user_id", "key_hash", "scopes", "created_at"],
+            "properties": {
+                "user_id": { "bsonType": "objectId" },
+                "key_hash": { "bsonType": "string" },
+                "scopes": { "bsonType": "array", "items": { "bsonType": "string" } },
+                "created_at": { "bsonType": "date" },
+            },
+        };
+
+        install_validator(db, COLLECTION_USERS, users).await?;
+        install_validator(db, COLLECTION_BOOKS, books).await?;
+        install_validator(db, COLLECTION_API_KEYS, api_keys).await
+    })
+}
+
+/// Sets a collection's `$jsonSchema`, creating the collection first on a fresh
+/// database. The `moderate` level leaves documents that were already invalid
+/// updatable.
+async fn install_validator(db: &Database, collection: &str, schema: Document) -> Result<()> {
+    match db.create_collection(collection).await {
+        Err(e) if !matches!(*e.kind, ErrorKind::Command(ref c) if c.code == NAMESPACE_EXISTS) => {
+            return Err(e)
+        }
+        _ => {}
+    }
+
+    db.run_command(doc! {
+        "collMod": collection,
+        "validator": { "$jsonSchema": schema },
+        "validationLevel": "moderate",
+    })
+    .await?;
+    Ok(())
+}
+
+fn verify_existing_emails(db: &Database) -> BoxFuture<'_, Result<()>> {
+    Box::pin(async move {
+        // Accounts stored before verification existed have no flag at all. They were
+        // trusted until now and must not lose the right to borrow on upgrade.
+        db.collection::<Document>(COLLECTION_USERS)
+            .update_many(
+                doc! { "email_verified": { "$exists": false } },
+                doc! { "$set": { "email_verified": true } },
+            )
+            .await?;
+        Ok(())
+    })
+}
@@ -1,2 +1,135 @@
+#[cfg(test)]
+pub mod memory;
+pub mod health;
+pub mod migrations;
 pub mod mongodb;
+#[cfg(feature = "postgres")]
+pub mod postgres;
 pub mod redis;
+pub mod repository;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+pub mod store;
+
+use crate::config::app_config::DatabaseConfig;
+use crate::database::health::HealthCheck;
+use crate::database::repository::{ApiKeyRepository, BookRepository, UserRepository};
+use crate::database::store::{
+    EmailVerificationStore, OidcStateStore, PasswordResetStore, RateLimitStore, TokenBlacklist,
+};
+use std::sync::Arc;
+
+/// The repositories backing the API, all on the same database.
+pub struct Repositories {
+    pub users: Arc<dyn UserRepository>,
+    pub books: Arc<dyn BookRepository>,
+    pub api_keys: Arc<dyn ApiKeyRepository>,
+    pub health: Arc<dyn HealthCheck>,
+    /// Set when the database can also hold the short-lived state
+    pub stores: Option<Stores>,
+}
+
+/// Short-lived state: revoked tokens, one-time tokens awaiting use and request counts.
+pub struct Stores {
+    pub blacklist: Arc<dyn TokenBlacklist>,
+    pub password_resets: Arc<dyn PasswordResetStore>,
+    pub email_verifications: Arc<dyn EmailVerificationStore>,
+    pub oidc_states: Arc<dyn OidcStateStore>,
+    pub rate_limits: Arc<dyn RateLimitStore>,
+    /// Unset when the stores live in the database, which is checked already
+    pub health: Option<Arc<dyn HealthCheck>>,
+}
+
+/// Connects to the configured database. With `migrate` set, pending schema migrations
+/// are applied first.
+pub async fn init_repositories(
+    cfg: &DatabaseConfig,
+    migrate: bool,
+) -> Result<Repositories, String> {
+    match cfg {
+        DatabaseConfig::MongoDb { uri, database } => {
+            tracing::info!("Connecting to MongoDB at {}...", uri);
+            let db = mongodb::init_mongodb(uri, database)
+                .await
+                .map_err(|e| e.to_string())?;
+            if migrate {
+                let ran = migrations::run_migrations(&db)
+                    .await
+                    .map_err(|e| format!("migration failed: {}", e))?;
+                tracing::info!("Applied {} schema migration(s)", ran.len());
+            } else {
+                let pending = migrations::pending_migrations(&db)
+                    .await
+                    .map_err(|e| e.to_string())?;
+                if !pending.is_empty() {
+                    tracing::warn!("Schema migrations {:?} have not been applied", pending);
+                }
+            }
+            Ok(Repositories {
+                users: Arc::new(mongodb::MongoUserRepository::new(&db)),
+                books: Arc::new(mongodb::MongoBookRepository::new(&db)),
+                api_keys: Arc::new(mongodb::MongoApiKeyRepository::new(&db)),
+                health: Arc::new(mongodb::MongoHealthCheck::new(&db)),
+                stores: None,
+            })
+        }
+        #[cfg(feature = "postgres")]
+        DatabaseConfig::Postgres { url, max_connections } => {
+            tracing::info!("Connecting to PostgreSQL...");
+            let pool = postgres::init_postgres(url, *max_connections, migrate)
+                .await
+                .map_err(|e| e.to_string())?;
+            Ok(Repositories {
+                users: Arc::new(postgres::PostgresUserRepository::new(pool.clone())),
+                books: Arc::new(postgres::PostgresBookRepository::new(pool.clone())),
+                api_keys: Arc::new(postgres::PostgresApiKeyRepository::new(pool.clone())),
+                health: Arc::new(postgres::PostgresHealthCheck::new(pool)),
+                stores: None,
+            })
+        }
+        #[cfg(feature = "sqlite")]
+        DatabaseConfig::Sqlite { url, max_connections } => {
+            tracing::info!("Opening SQLite database {}...", url);
+            let pool = sqlite::init_sqlite(url, *max_connections, migrate)
+                .await
+                .map_err(|e| e.to_string())?;
+            Ok(Repositories {
+                users: Arc::new(sqlite::SqliteUserRepository::new(pool.clone())),
+                books: Arc::new(sqlite::SqliteBookRepository::new(pool.clone())),
+                api_keys: Arc::new(sqlite::SqliteApiKeyRepository::new(pool.clone())),
+                health: Arc::new(sqlite::SqliteHealthCheck::new(pool.clone())),
+                stores: Some(Stores {
+                    blacklist: Arc::new(sqlite::SqliteTokenBlacklist::new(pool.clone())),
+                    password_resets: Arc::new(sqlite::SqlitePasswordResetStore::new(pool.clone())),
+                    email_verifications: Arc::new(sqlite::SqliteEmailVerificationStore::new(
+                        pool.clone(),
+                    )),
+                    oidc_states: Arc::new(sqlite::SqliteOidcStateStore::new(pool.clone())),
+                    rate_limits: Arc::new(sqlite::SqliteRateLimitStore::new(pool)),
+                    health: None,
+                }),
+            })
+        }
+    }
+}
+
+/// Uses Redis for the short-lived state when configured, otherwise the database.
+pub async fn init_stores(
+    redis_uri: Option<&str>,
+    embedded: Option<Stores>,
+) -> Result<Stores, String> {
+    let Some(uri) = redis_uri else {
+        return embedded.ok_or_else(|| "the database cannot hold tokens without Redis".into());
+    };
+
+    tracing::info!("Connecting to Redis at {}...", uri);
+    let conn = redis::init_redis(uri).await.map_err(|e| e.to_string())?;
+    Ok(Stores {
+        blacklist: Arc::new(redis::RedisTokenBlacklist::new(conn.clone())),
+        password_resets: Arc::new(redis::RedisPasswordResetStore::new(conn.clone())),
+        email_verifications: Arc::new(redis::RedisEmailVerificationStore::new(conn.clone())),
+        oidc_states: Arc::new(redis::RedisOidcStateStore::new(conn.clone())),
+        rate_limits: Arc::new(redis::RedisRateLimitStore::new(conn.clone())),
+        health: Some(Arc::new(redis::RedisHealthCheck::new(conn))),
+    })
+}
@@ -1,40 +1,434 @@
-use crate::constants::{BOOK_ALREADY_EXISTS, COLLECTION_BOOKS, COLLECTION_USERS, USER_NOT_FOUND};
+use crate::constants::{
+    BOOK_ALREADY_BORROWED, BOOK_ALREADY_EXISTS, BOOK_NOT_BORROWED, BORROW_LIMIT_REACHED,
+    COLLECTION_API_KEYS, COLLECTION_BOOKS, COLLECTION_USERS, EMAIL_ALREADY_EXISTS,
+    NO_STOCK_AVAILABLE, USER_NOT_FOUND,
+};
+use crate::database::health::HealthCheck;
+use crate::database::repository::{ApiKeyRepository, BookRepository, UserRepository};
 use crate::errors::AppError;
+use crate::models::api_key::ApiKey;
 use crate::models::book::Book;
-use crate::models::user::User;
-use mongodb::bson::doc;
+use crate::models::user::{PatronCategory, Suspension, User};
+use crate::utils::metrics::METRICS;
+use async_trait::async_trait;
 use mongodb::bson::oid::ObjectId;
+use mongodb::bson::DateTime;
+use mongodb::bson::{doc, to_bson, Bson, Document};
+use mongodb::error::{ErrorKind, WriteFailure};
+use mongodb::event::command::CommandEvent;
+use mongodb::event::EventHandler;
 use mongodb::options::ClientOptions;
 use mongodb::{Client, Collection, Database};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use tracing::{instrument, Span};
 
 pub async fn init_mongodb(uri: &str, db_name: &str) -> mongodb::error::Result<Database> {
     let mut client_options = ClientOptions::parse(uri).await?;
     client_options.app_name = Some("ActixAuth".into());
+    client_options.command_event_handler = Some(command_monitor());
     let client = Client::with_options(client_options)?;
     Ok(client.database(db_name))
 }
 
+/// Times every command the driver sends and describes it on the repository span
+/// that issued it. The collection only appears in the started event, so it is kept
+/// by request id until the command finishes.
+fn command_monitor() -> EventHandler<CommandEvent> {
+    let in_flight = Mutex::new(HashMap::<i32, String>::new());
+    EventHandler::callback(move |event| {
+        // Events are emitted from the task running the operation, so the current
+        // span is the repository call.
+        let span = Span::current();
+        let mut in_flight = in_flight.lock().unwrap_or_else(|e| e.into_inner());
+        let (request_id, name, duration, succeeded) = match event {
+            CommandEvent::Started(started) => {
+                let target = started
+                    .command
+                    .get_str(&started.command_name)
+                    .unwrap_or_default();
+                span.record("db.system", "mongodb");
+                span.record("db.collection", target);
+                span.record("db.operation", started.command_name.as_str());
+                in_flight.insert(started.request_id, target.to_string());
+                return;
+            }
+            CommandEvent::Succeeded(e) => {
+                // Writes report how many documents they matched and changed.
+                if let Some(matched) = count(&e.reply, "n") {
+                    span.record("db.matched", matched);
+                }
+                if let Some(modified) = count(&e.reply, "nModified") {
+                    span.record("db.modified", modified);
+                }
+                (e.request_id, e.command_name, e.duration, true)
+            }
+            CommandEvent::Failed(e) => (e.request_id, e.command_name, e.duration, false),
+            _ => return,
+        };
+        let target = in_flight.remove(&request_id).unwrap_or_default();
+        METRICS.observe_database("mongodb", &name, &target, duration, succeeded);
+    })
+}
+
+fn count(reply: &Document, key: &str) -> Option<i64> {
+    match reply.get(key)? {
+        Bson::Int32(n) => Some(i64::from(*n)),
+        Bson::Int64(n) => Some(*n),
+        _ => None,
+    }
+}
+
+/// MongoDB's `DuplicateKey` error code.
+const DUPLICATE_KEY: i32 = 11000;
+
+/// Whether a write was rejected by a unique index.
+pub fn is_duplicate_key(e: &mongodb::error::Error) -> bool {
+    match e.kind.as_ref() {
+        ErrorKind::Write(WriteFailure::WriteError(we)) => we.code == DUPLICATE_KEY,
+        ErrorKind::Command(ce) => ce.code == DUPLICATE_KEY,
+        _ => false,
+    }
+}
+
+/// Turns a unique index violation into a conflict with the given message.
+fn conflict_on_duplicate(msg: &'static str) -> impl Fn(mongodb::error::Error) -> AppError {
+    move |e| {
+        if is_duplicate_key(&e) {
+            AppError::Conflict(msg.into())
+        } else {
+            AppError::Database(e)
+        }
+    }
+}
+
+/// Matches users in a category. Accounts created before categories existed have no
+/// field and count as public.
+fn category_filter(category: PatronCategory) -> Document {
+    match category {
+        PatronCategory::Public => doc! { "category": { "$in": [category.as_str(), Bson::Null] } },
+        _ => doc! { "category": category.as_str() },
+    }
+}
+
 #[derive(Clone)]
-pub struct UserRepository {
+pub struct MongoUserRepository {
     collection: Collection<User>,
 }
 
-impl UserRepository {
+impl MongoUserRepository {
     pub fn new(db: &Database) -> Self {
         Self {
             collection: db.collection::<User>(COLLECTION_USERS),
         }
     }
+}
 
-    pub async fn find_by_email(&self, email: &str) -> Result<Option<User>, AppError> {
+#[async_trait]
+impl UserRepository for MongoUserRepository {
+    #[instrument(skip_all, fields(db.system, db.collection, db.operation, db.matched, db.modified))]
+    async fn find_by_email(&self, email: &str) -> Result<Option<User>, AppError> {
         Ok(self.collection.find_one(doc! { "email": email }).await?)
     }
 
-    pub async fn find_by_id(&self, id: &ObjectId) -> Result<Option<User>, AppError> {
+    #[instrument(skip_all, fields(db.system, db.collection, db.operation, db.matched, db.modified))]
+    async fn find_by_id(&self, id: &ObjectId) -> Result<Option<User>, AppError> {
         Ok(self.collection.find_one(doc! { "_id": id }).await?)
     }
 
-    pub async fn find_all(&self) -> Result<Vec<User>, AppError> {
+    #[instrument(skip_all, fields(db.system, db.collection, db.operation, db.matched, db.modified))]
+    async fn find_by_oidc_subject(&self, subject: &str) -> Result<Option<User>, AppError> {
+        Ok(self.collection.find_one(doc! { "oidc_subject": subject }).await?)
+    }
+
+    #[instrument(skip_all, fields(db.system, db.collection, db.operation, db.matched, db.modified))]
+    async fn set_oidc_subject(&self, id: &ObjectId, subject: &str) -> Result<(), AppError> {
+        self.collection
+            .update_one(
+                doc! { "_id": id },
+                doc! { "$set": { "oidc_subject": subject } },
+            )
+            .await?;
+        Ok(())
+    }
+
+    #[instrument(skip_all, fields(db.system, db.collection, db.operation, db.matched, db.modified))]
+    async fn find_by_ldap_dn(&self, dn: &str) -> Result<Option<User>, AppError> {
+        Ok(self.collection.find_one(doc! { "ldap_dn": dn }).await?)
+    }
+
+    #[instrument(skip_all, fields(db.system, db.collection, db.operation, db.matched, db.modified))]
+    async fn find_ldap_users(&self) -> Result<Vec<User>, AppError> {
+        use futures::stream::TryStreamExt;
+        let mut cursor = self
+            .collection
+            .find(doc! { "ldap_dn": { "$exists": true }, "disabled": { "$ne": true } })
+            .await?;
+        let mut users = Vec::new();
+        while let Some(user) = cursor.try_next().await? {
+            users.push(user);
+        }
+        Ok(users)
+    }
+
+    #[instrument(skip_all, fields(db.system, db.collection, db.operation, db.matched, db.modified))]
+    async fn sync_ldap_account(
+        &self,
+        id: &ObjectId,
+        dn: &str,
+        username: &str,
+        email: &str,
+        is_admin: bool,
+    ) -> Result<(), AppError> {
+        self.collection
+            .update_one(
+                doc! { "_id": id },
+                doc! {
+                    "$set": {
+                        "ldap_dn": dn,
+                        "username": username,
+                        "email": email,
+                        "email_verified": true,
+                        "is_admin": is_admin,
+                    },
+                    "$unset": { "pending_email": "" },
+                },
+            )
+            .await?;
+        Ok(())
+    }
+
+    #[instrument(skip_all, fields(db.system, db.collection, db.operation, db.matched, db.modified))]
+    async fn disable(&self, id: &ObjectId) -> Result<(), AppError> {
+        self.collection
+            .update_one(
+                doc! { "_id": id },
+                doc! { "$set": { "disabled": true }, "$inc": { "token_version": 1 } },
+            )
+            .await?;
+        Ok(())
+    }
+
+    #[instrument(skip_all, fields(db.system, db.collection, db.operation, db.matched, db.modified))]
+    async fn enable(&self, id: &ObjectId) -> Result<(), AppError> {
+        self.collection
+            .update_one(doc! { "_id": id }, doc! { "$set": { "disabled": false } })
+            .await?;
+        Ok(())
+    }
+
+    #[instrument(skip_all, fields(db.system, db.collection, db.operation, db.matched, db.modified))]
+    async fn anonymize(&self, id: &ObjectId) -> Result<(), AppError> {
+        self.collection
+            .update_one(
+                doc! { "_id": id },
+                doc! {
+                    "$set": {
+                        "email": "",
+                        "username": User::anonymized_username(id),
+                        "password_hash": "",
+                        "email_verified": false,
+                        "disabled": true,
+                        "is_admin": false,
+                    },
+                    "$unset": {
+                        "password_history": "",
+                        "password_changed_at": "",
+                        "pending_email": "",
+                        "oidc_subject": "",
+                        "ldap_dn": "",
+                        "suspension": "",
+                        "guardian_id": "",
+                        "loan_limit": "",
+                        "family_loan_cap": "",
+                    },
+                    "$inc": { "token_version": 1 },
+                },
+            )
+            .await?;
+        Ok(())
+    }
+
+    #[instrument(skip_all, fields(db.system, db.collection, db.operation, db.matched, db.modified))]
+    async fn suspend(&self, id: &ObjectId, suspension: &Suspension) -> Result<(), AppError> {
+        let suspension = to_bson(suspension).map_err(|e| {
+            tracing::error!("failed to serialize suspension: {}", e);
+            AppError::Internal
+        })?;
+        self.collection
+            .update_one(
+                doc! { "_id": id },
+                doc! { "$set": { "suspension": suspension } },
+            )
+            .await?;
+        Ok(())
+    }
+
+    #[instrument(skip_all, fields(db.system, db.collection, db.operation, db.matched, db.modified))]
+    async fn reactivate(&self, id: &ObjectId) -> Result<(), AppError> {
+        self.collection
+            .update_one(doc! { "_id": id }, doc! { "$unset": { "suspension": "" } })
+            .await?;
+        Ok(())
+    }
+
+    #[instrument(skip_all, fields(db.system, db.collection, db.operation, db.matched, db.modified))]
+    async fn soft_delete(&self, id: &ObjectId) -> Result<(), AppError> {
+        self.collection
+            .update_one(
+                doc! { "_id": id },
+                doc! { "$set": { "deleted_at": DateTime::now() }, "$inc": { "token_version": 1 } },
+            )
+            .await?;
+        Ok(())
+    }
+
+    #[instrument(skip_all, fields(db.system, db.collection, db.operation, db.matched, db.modified))]
+    async fn restore(&self, id: &ObjectId) -> Result<(), AppError> {
+        self.collection
+            .update_one(doc! { "_id": id }, doc! { "$unset": { "deleted_at": "" } })
+            .await?;
+        Ok(())
+    }
+
+    #[instrument(skip_all, fields(db.system, db.collection, db.operation, db.matched, db.modified))]
+    async fn purge_deleted(&self, cutoff: DateTime) -> Result<Vec<ObjectId>, AppError> {
+        use futures::stream::TryStreamExt;
+        let filter = doc! { "deleted_at": { "$lt": cutoff } };
+        let mut cursor = self.collection.find(filter).await?;
+        let mut ids = Vec::new();
+        while let Some(user) = cursor.try_next().await? {
+            ids.push(user.id);
+        }
+
+        if !ids.is_empty() {
+            self.collection
+                .delete_many(doc! { "_id": { "$in": &ids }, "deleted_at": { "$lt": cutoff } })
+                .await?;
+        }
+        Ok(ids)
+    }
+
+    #[instrument(skip_all, fields(db.system, db.collection, db.operation, db.matched, db.modified))]
+    async fn set_category(&self, id: &ObjectId, category: PatronCategory) -> Result<(), AppError> {
+        self.collection
+            .update_one(
+                doc! { "_id": id },
+                doc! { "$set": { "category": category.as_str() } },
+            )
+            .await?;
+        Ok(())
+    }
+
+    #[instrument(skip_all, fields(db.system, db.collection, db.operation, db.matched, db.modified))]
+    async fn set_membership_expiry(
+        &self,
+        id: &ObjectId,
+        expires_at: Option<DateTime>,
+    ) -> Result<(), AppError> {
+        let update = match expires_at {
+            Some(expires_at) => doc! { "$set": { "membership_expires_at": expires_at } },
+            None => doc! { "$unset": { "membership_expires_at": "" } },
+        };
+        self.collection
+            .update_one(doc! { "_id": id }, update)
+            .await?;
+        Ok(())
+    }
+
+    #[instrument(skip_all, fields(db.system, db.collection, db.operation, db.matched, db.modified))]
+    async fn extend_memberships_by_days(
+        &self,
+        category: PatronCategory,
+        days: i64,
+    ) -> Result<u64, AppError> {
+        let mut filter = category_filter(category);
+        filter.insert("membership_expires_at", doc! { "$type": "date" });
+        let update = vec![doc! {
+            "$set": {
+                "membership_expires_at": {
+                    "$add": [
+                        { "$max": ["$membership_expires_at", "$$NOW"] },
+                        days * 24 * 60 * 60 * 1000,
+                    ]
+                }
+            }
+        }];
+        let result = self.collection.update_many(filter, update).await?;
+        Ok(result.modified_count)
+    }
+
+    #[instrument(skip_all, fields(db.system, db.collection, db.operation, db.matched, db.modified))]
+    async fn extend_memberships_until(
+        &self,
+        category: PatronCategory,
+        until: DateTime,
+    ) -> Result<u64, AppError> {
+        let mut filter = category_filter(category);
+        filter.insert("membership_expires_at", doc! { "$lt": until });
+        let result = self
+            .collection
+            .update_many(filter, doc! { "$set": { "membership_expires_at": until } })
+            .await?;
+        Ok(result.modified_count)
+    }
+
+    #[instrument(skip_all, fields(db.system, db.collection, db.operation, db.matched, db.modified))]
+    async fn find_dependents(&self, guardian_id: &ObjectId) -> Result<Vec<User>, AppError> {
+        use futures::stream::TryStreamExt;
+        let mut cursor = self
+            .collection
+            .find(doc! { "guardian_id": guardian_id, "deleted_at": { "$exists": false } })
+            .await?;
+        let mut users = Vec::new();
+        while let Some(user) = cursor.try_next().await? {
+            users.push(user);
+        }
+        Ok(users)
+    }
+
+    #[instrument(skip_all, fields(db.system, db.collection, db.operation, db.matched, db.modified))]
+    async fn set_guardian(
+        &self,
+        id: &ObjectId,
+        guardian_id: Option<&ObjectId>,
+    ) -> Result<(), AppError> {
+        let update = match guardian_id {
+            Some(guardian_id) => doc! { "$set": { "guardian_id": guardian_id } },
+            None => doc! { "$unset": { "guardian_id": "", "loan_limit": "" } },
+        };
+        self.collection
+            .update_one(doc! { "_id": id }, update)
+            .await?;
+        Ok(())
+    }
+
+    #[instrument(skip_all, fields(db.system, db.collection, db.operation, db.matched, db.modified))]
+    async fn set_loan_limit(&self, id: &ObjectId, limit: Option<u32>) -> Result<(), AppError> {
+        let update = match limit {
+            Some(limit) => doc! { "$set": { "loan_limit": limit } },
+            None => doc! { "$unset": { "loan_limit": "" } },
+        };
+        self.collection
+            .update_one(doc! { "_id": id }, update)
+            .await?;
+        Ok(())
+    }
+
+    #[instrument(skip_all, fields(db.system, db.collection, db.operation, db.matched, db.modified))]
+    async fn set_family_loan_cap(&self, id: &ObjectId, cap: Option<u32>) -> Result<(), AppError> {
+        let update = match cap {
+            Some(cap) => doc! { "$set": { "family_loan_cap": cap } },
+            None => doc! { "$unset": { "family_loan_cap": "" } },
+        };
+        self.collection
+            .update_one(doc! { "_id": id }, update)
+            .await?;
+        Ok(())
+    }
+
+    #[instrument(skip_all, fields(db.system, db.collection, db.operation, db.matched, db.modified))]
+    async fn find_all(&self) -> Result<Vec<User>, AppError> {
         use mongodb::bson::doc;
         let mut cursor = self.collection.find(doc! {}).await?;
         let mut users = Vec::new();
@@ -45,34 +439,80 @@ impl UserRepository {
         Ok(users)
     }
 
-    pub async fn delete_by_id(&self, id: &ObjectId) -> Result<(), AppError> {
-        self.collection.delete_one(doc! { "_id": id }).await?;
+    #[instrument(skip_all, fields(db.system, db.collection, db.operation, db.matched, db.modified))]
+    async fn set_admin(&self, id: &ObjectId, is_admin: bool) -> Result<(), AppError> {
+        self.collection
+            .update_one(
+                doc! { "_id": id },
+                doc! { "$set": { "is_admin": is_admin } },
+            )
+            .await?;
+        Ok(())
+    }
+
+    #[instrument(skip_all, fields(db.system, db.collection, db.operation, db.matched, db.modified))]
+    async fn create(&self, user: &User) -> Result<(), AppError> {
+        self.collection
+            .insert_one(user)
+            .await
+            .map_err(conflict_on_duplicate(EMAIL_ALREADY_EXISTS))?;
         Ok(())
     }
 
-    pub async fn set_admin(&self, id: &ObjectId, is_admin: bool) -> Result<(), AppError> {
+    #[instrument(skip_all, fields(db.system, db.collection, db.operation, db.matched, db.modified))]
+    async fn update_email(&self, id: &ObjectId, new_email: &str) -> Result<(), AppError> {
         self.collection
             .update_one(
                 doc! { "_id": id },
-                doc! { "$set": { "is_admin": is_admin } },
+                doc! {
+                    "$set": { "email": new_email, "email_verified": false },
+                    "$unset": { "pending_email": "" },
+                },
+            )
+            .await
+            .map_err(conflict_on_duplicate(EMAIL_ALREADY_EXISTS))?;
+        Ok(())
+    }
+
+    #[instrument(skip_all, fields(db.system, db.collection, db.operation, db.matched, db.modified))]
+    async fn set_pending_email(&self, id: &ObjectId, email: &str) -> Result<(), AppError> {
+        self.collection
+            .update_one(
+                doc! { "_id": id },
+                doc! { "$set": { "pending_email": email } },
             )
             .await?;
         Ok(())
     }
 
-    pub async fn create(&self, user: &User) -> Result<(), AppError> {
-        self.collection.insert_one(user).await?;
+    #[instrument(skip_all, fields(db.system, db.collection, db.operation, db.matched, db.modified))]
+    async fn confirm_email(&self, id: &ObjectId, email: &str) -> Result<(), AppError> {
+        self.collection
+            .update_one(
+                doc! { "_id": id },
+                doc! {
+                    "$set": { "email": email, "email_verified": true },
+                    "$unset": { "pending_email": "" },
+                },
+            )
+            .await
+            .map_err(conflict_on_duplicate(EMAIL_ALREADY_EXISTS))?;
         Ok(())
     }
 
-    pub async fn update_email(&self, id: &ObjectId, new_email: &str) -> Result<(), AppError> {
+    #[instrument(skip_all, fields(db.system, db.collection, db.operation, db.matched, db.modified))]
+    async fn mark_email_verified(&self, id: &ObjectId) -> Result<(), AppError> {
         self.collection
-            .update_one(doc! { "_id": id }, doc! { "$set": { "email": new_email } })
+            .update_one(
+                doc! { "_id": id },
+                doc! { "$set": { "email_verified": true } },
+            )
             .await?;
         Ok(())
     }
 
-    pub async fn update_username(&self, id: &ObjectId, new_username: &str) -> Result<(), AppError> {
+    #[instrument(skip_all, fields(db.system, db.collection, db.operation, db.matched, db.modified))]
+    async fn update_username(&self, id: &ObjectId, new_username: &str) -> Result<(), AppError> {
         self.collection
             .update_one(
                 doc! { "_id": id },
@@ -82,21 +522,41 @@ impl UserRepository {
         Ok(())
     }
 
-    pub async fn update_password(
+    #[instrument(skip_all, fields(db.system, db.collection, db.operation, db.matched, db.modified))]
+    async fn update_password(&self, id: &ObjectId, password_hash: &str) -> Result<(), AppError> {
+        self.collection
+            .update_one(
+                doc! { "_id": id },
+                doc! { "$set": { "password_hash": password_hash } },
+            )
+            .await?;
+        Ok(())
+    }
+
+    #[instrument(skip_all, fields(db.system, db.collection, db.operation, db.matched, db.modified))]
+    async fn change_password(
         &self,
         id: &ObjectId,
         password_hash: &str,
+        password_history: &[String],
     ) -> Result<(), AppError> {
         self.collection
             .update_one(
                 doc! { "_id": id },
-                doc! { "$set": { "password_hash": password_hash } },
+                doc! {
+                    "$set": {
+                        "password_hash": password_hash,
+                        "password_history": password_history,
+                        "password_changed_at": DateTime::now(),
+                    }
+                },
             )
             .await?;
         Ok(())
     }
 
-    pub async fn update_token_version(
+    #[instrument(skip_all, fields(db.system, db.collection, db.operation, db.matched, db.modified))]
+    async fn update_token_version(
         &self,
         id: &ObjectId,
         token_version: i32,
@@ -110,10 +570,12 @@ impl UserRepository {
         Ok(())
     }
 
-    pub async fn add_borrowed_book(
+    #[instrument(skip_all, fields(db.system, db.collection, db.operation, db.matched, db.modified))]
+    async fn add_borrowed_book(
         &self,
         user_id: &ObjectId,
         book_id: &ObjectId,
+        limit: usize,
     ) -> Result<(), AppError> {
         let mut user = self
             .find_by_id(user_id)
@@ -121,14 +583,14 @@ impl UserRepository {
             .ok_or_else(|| AppError::NotFound(USER_NOT_FOUND.into()))?;
 
         if user.borrowed_books.contains(book_id) {
-            return Err(AppError::BadRequest("book already borrowed".into()));
+            return Err(AppError::BadRequest(BOOK_ALREADY_BORROWED.into()));
         }
 
-        if user.borrowed_books.len() >= 8 {
-            return Err(AppError::BadRequest("borrow limit reached".into()));
+        if user.borrowed_books.len() >= limit {
+            return Err(AppError::BadRequest(BORROW_LIMIT_REACHED.into()));
         }
 
-        user.borrowed_books.push(book_id.clone());
+        user.borrowed_books.push(*book_id);
 
         self.collection
             .update_one(
@@ -140,7 +602,8 @@ impl UserRepository {
         Ok(())
     }
 
-    pub async fn remove_borrowed_book(
+    #[instrument(skip_all, fields(db.system, db.collection, db.operation, db.matched, db.modified))]
+    async fn remove_borrowed_book(
         &self,
         user_id: &ObjectId,
         book_id: &ObjectId,
@@ -154,26 +617,54 @@ impl UserRepository {
             .await?;
 
         if result.modified_count == 0 {
-            return Err(AppError::BadRequest("book not borrowed by user".into()));
+            return Err(AppError::BadRequest(BOOK_NOT_BORROWED.into()));
         }
 
         Ok(())
     }
+
+    #[instrument(skip_all, fields(db.system, db.collection, db.operation, db.matched, db.modified))]
+    async fn count_active_loans(&self) -> Result<u64, AppError> {
+        use futures::stream::TryStreamExt;
+        let pipeline = [doc! {
+            "$group": {
+                "_id": Bson::Null,
+                "loans": { "$sum": { "$size": { "$ifNull": ["$borrowed_books", []] } } },
+            }
+        }];
+        let mut cursor = self.collection.aggregate(pipeline).await?;
+        // $sum yields an int or a long depending on the total.
+        let loans = match cursor
+            .try_next()
+            .await?
+            .as_ref()
+            .and_then(|t| t.get("loans"))
+        {
+            Some(Bson::Int32(n)) => *n as u64,
+            Some(Bson::Int64(n)) => *n as u64,
+            _ => 0,
+        };
+        Ok(loans)
+    }
 }
 
 #[derive(Clone)]
-pub struct BookRepository {
+pub struct MongoBookRepository {
     collection: Collection<Book>,
 }
 
-impl BookRepository {
+impl MongoBookRepository {
     pub fn new(db: &Database) -> Self {
         Self {
             collection: db.collection::<Book>(COLLECTION_BOOKS),
         }
     }
+}
 
-    pub async fn create(&self, title: &str, author: &str) -> Result<Book, AppError> {
+#[async_trait]
+impl BookRepository for MongoBookRepository {
+    #[instrument(skip_all, fields(db.system, db.collection, db.operation, db.matched, db.modified))]
+    async fn create(&self, title: &str, author: &str) -> Result<Book, AppError> {
         if self
             .collection
             .find_one(doc! { "title": title, "author": author })
@@ -190,15 +681,21 @@ impl BookRepository {
             stock: 0,
         };
 
-        self.collection.insert_one(&book).await?;
+        // The unique index catches a concurrent create of the same book.
+        self.collection
+            .insert_one(&book)
+            .await
+            .map_err(conflict_on_duplicate(BOOK_ALREADY_EXISTS))?;
         Ok(book)
     }
 
-    pub async fn find_by_id(&self, id: &ObjectId) -> Result<Option<Book>, AppError> {
+    #[instrument(skip_all, fields(db.system, db.collection, db.operation, db.matched, db.modified))]
+    async fn find_by_id(&self, id: &ObjectId) -> Result<Option<Book>, AppError> {
         Ok(self.collection.find_one(doc! { "_id": id }).await?)
     }
 
-    pub async fn find_all(&self) -> Result<Vec<Book>, AppError> {
+    #[instrument(skip_all, fields(db.system, db.collection, db.operation, db.matched, db.modified))]
+    async fn find_all(&self) -> Result<Vec<Book>, AppError> {
         use futures::stream::TryStreamExt;
         let mut cursor = self.collection.find(doc! {}).await?;
         let mut books = Vec::new();
@@ -208,7 +705,8 @@ impl BookRepository {
         Ok(books)
     }
 
-    pub async fn find_by_title(&self, title: &str) -> Result<Vec<Book>, AppError> {
+    #[instrument(skip_all, fields(db.system, db.collection, db.operation, db.matched, db.modified))]
+    async fn find_by_title(&self, title: &str) -> Result<Vec<Book>, AppError> {
         use futures::stream::TryStreamExt;
         let mut cursor = self.collection.find(doc! { "title": title }).await?;
         let mut books = Vec::new();
@@ -218,7 +716,8 @@ impl BookRepository {
         Ok(books)
     }
 
-    pub async fn find_by_author(&self, author: &str) -> Result<Vec<Book>, AppError> {
+    #[instrument(skip_all, fields(db.system, db.collection, db.operation, db.matched, db.modified))]
+    async fn find_by_author(&self, author: &str) -> Result<Vec<Book>, AppError> {
         use futures::stream::TryStreamExt;
         let mut cursor = self.collection.find(doc! { "author": author }).await?;
         let mut books = Vec::new();
@@ -228,33 +727,40 @@ impl BookRepository {
         Ok(books)
     }
 
-    pub async fn delete_by_id(&self, id: &ObjectId) -> Result<(), AppError> {
+    #[instrument(skip_all, fields(db.system, db.collection, db.operation, db.matched, db.modified))]
+    async fn delete_by_id(&self, id: &ObjectId) -> Result<(), AppError> {
         self.collection.delete_one(doc! { "_id": id }).await?;
         Ok(())
     }
 
-    pub async fn update_title(&self, id: &ObjectId, title: &str) -> Result<(), AppError> {
+    #[instrument(skip_all, fields(db.system, db.collection, db.operation, db.matched, db.modified))]
+    async fn update_title(&self, id: &ObjectId, title: &str) -> Result<(), AppError> {
         self.collection
             .update_one(doc! { "_id": id }, doc! { "$set": { "title": title } })
-            .await?;
+            .await
+            .map_err(conflict_on_duplicate(BOOK_ALREADY_EXISTS))?;
         Ok(())
     }
 
-    pub async fn update_author(&self, id: &ObjectId, author: &str) -> Result<(), AppError> {
+    #[instrument(skip_all, fields(db.system, db.collection, db.operation, db.matched, db.modified))]
+    async fn update_author(&self, id: &ObjectId, author: &str) -> Result<(), AppError> {
         self.collection
             .update_one(doc! { "_id": id }, doc! { "$set": { "author": author } })
-            .await?;
+            .await
+            .map_err(conflict_on_duplicate(BOOK_ALREADY_EXISTS))?;
         Ok(())
     }
 
-    pub async fn update_stock(&self, id: &ObjectId, stock: i32) -> Result<(), AppError> {
+    #[instrument(skip_all, fields(db.system, db.collection, db.operation, db.matched, db.modified))]
+    async fn update_stock(&self, id: &ObjectId, stock: i32) -> Result<(), AppError> {
         self.collection
             .update_one(doc! { "_id": id }, doc! { "$set": { "stock": stock } })
             .await?;
         Ok(())
     }
 
-    pub async fn borrow_book(&self, id: &ObjectId) -> Result<(), AppError> {
+    #[instrument(skip_all, fields(db.system, db.collection, db.operation, db.matched, db.modified))]
+    async fn borrow_book(&self, id: &ObjectId) -> Result<(), AppError> {
         let result = self
             .collection
             .update_one(
@@ -264,16 +770,114 @@ impl BookRepository {
             .await?;
 
         if result.modified_count == 0 {
-            return Err(AppError::BadRequest("no stock available".into()));
+            return Err(AppError::BadRequest(NO_STOCK_AVAILABLE.into()));
         }
 
         Ok(())
     }
 
-    pub async fn return_book(&self, id: &ObjectId) -> Result<(), AppError> {
+    #[instrument(skip_all, fields(db.system, db.collection, db.operation, db.matched, db.modified))]
+    async fn return_book(&self, id: &ObjectId) -> Result<(), AppError> {
         self.collection
             .update_one(doc! { "_id": id }, doc! { "$inc": { "stock": 1 } })
             .await?;
         Ok(())
     }
+
+    #[instrument(skip_all, fields(db.system, db.collection, db.operation, db.matched, db.modified))]
+    async fn count_out_of_stock(&self) -> Result<u64, AppError> {
+        Ok(self
+            .collection
+            .count_documents(doc! { "stock": { "$lte": 0 } })
+            .await?)
+    }
+}
+
+#[derive(Clone)]
+pub struct MongoApiKeyRepository {
+    collection: Collection<ApiKey>,
+}
+
+impl MongoApiKeyRepository {
+    pub fn new(db: &Database) -> Self {
+        Self {
+            collection: db.collection::<ApiKey>(COLLECTION_API_KEYS),
+        }
+    }
+}
+
+#[async_trait]
+impl ApiKeyRepository for MongoApiKeyRepository {
+    #[instrument(skip_all, fields(db.system, db.collection, db.operation, db.matched, db.modified))]
+    async fn create(&self, api_key: &ApiKey) -> Result<(), AppError> {
+        self.collection.insert_one(api_key).await?;
+        Ok(())
+    }
+
+    #[instrument(skip_all, fields(db.system, db.collection, db.operation, db.matched, db.modified))]
+    async fn find_by_id(&self, id: &ObjectId) -> Result<Option<ApiKey>, AppError> {
+        Ok(self.collection.find_one(doc! { "_id": id }).await?)
+    }
+
+    #[instrument(skip_all, fields(db.system, db.collection, db.operation, db.matched, db.modified))]
+    async fn find_by_hash(&self, key_hash: &str) -> Result<Option<ApiKey>, AppError> {
+        Ok(self.collection.find_one(doc! { "key_hash": key_hash }).await?)
+    }
+
+    #[instrument(skip_all, fields(db.system, db.collection, db.operation, db.matched, db.modified))]
+    async fn find_by_user(&self, user_id: &ObjectId) -> Result<Vec<ApiKey>, AppError> {
+        use futures::stream::TryStreamExt;
+        let mut cursor = self.collection.find(doc! { "user_id": user_id }).await?;
+        let mut keys = Vec::new();
+        while let Some(key) = cursor.try_next().await? {
+            keys.push(key);
+        }
+        Ok(keys)
+    }
+
+    #[instrument(skip_all, fields(db.system, db.collection, db.operation, db.matched, db.modified))]
+    async fn delete_by_id(&self, id: &ObjectId) -> Result<(), AppError> {
+        self.collection.delete_one(doc! { "_id": id }).await?;
+        Ok(())
+    }
+
+    #[instrument(skip_all, fields(db.system, db.collection, db.operation, db.matched, db.modified))]
+    async fn delete_by_user(&self, user_id: &ObjectId) -> Result<(), AppError> {
+        self.collection.delete_many(doc! { "user_id": user_id }).await?;
+        Ok(())
+    }
+
+    #[instrument(skip_all, fields(db.system, db.collection, db.operation, db.matched, db.modified))]
+    async fn touch_last_used(&self, id: &ObjectId) -> Result<(), AppError> {
+        self.collection
+            .update_one(
+                doc! { "_id": id },
+                doc! { "$set": { "last_used_at": DateTime::now() } },
+            )
+            .await?;
+        Ok(())
+    }
+}
+
+#[derive(Clone)]
+pub struct MongoHealthCheck {
+    db: Database,
+}
+
+impl MongoHealthCheck {
+    pub fn new(db: &Database) -> Self {
+        Self { db: db.clone() }
+    }
+}
+
+#[async_trait]
+impl HealthCheck for MongoHealthCheck {
+    fn name(&self) -> &'static str {
+        "mongodb"
+    }
+
+    async fn ping(&self) -> Result<(), AppError> {
+        self.db.run_command(doc! { "ping": 1 }).await?;
+        Ok(())
+    }
 }
@@ -0,0 +1,1168 @@
+use crate::constants::{
+    BOOK_ALREADY_BORROWED, BOOK_ALREADY_EXISTS, BOOK_NOT_BORROWED, BORROW_LIMIT_REACHED,
+    EMAIL_ALREADY_EXISTS, NO_STOCK_AVAILABLE, USER_NOT_FOUND,
+};
+use crate::database::health::HealthCheck;
+use crate::database::repository::{ApiKeyRepository, BookRepository, UserRepository};
+use crate::errors::AppError;
+use crate::models::api_key::ApiKey;
+use crate::models::book::Book;
+use crate::models::user::{PatronCategory, Suspension, User};
+use async_trait::async_trait;
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::DateTime;
+use sqlx::postgres::{PgArguments, PgPool, PgPoolOptions};
+use sqlx::query::Query;
+use sqlx::{FromRow, Postgres};
+use time::OffsetDateTime;
+
+type PgQuery<'q> = Query<'q, Postgres, PgArguments>;
+
+/// Connects to PostgreSQL and applies any pending schema migrations when `migrate` is
+/// set.
+pub async fn init_postgres(
+    url: &str,
+    max_connections: u32,
+    migrate: bool,
+) -> Result<PgPool, sqlx::Error> {
+    let pool = PgPoolOptions::new()
+        .max_connections(max_connections)
+        .connect(url)
+        .await?;
+    if migrate {
+        sqlx::migrate!("migrations/postgres").run(&pool).await?;
+    }
+    Ok(pool)
+}
+
+/// Runs a statement and returns the number of rows it affected.
+async fn execute(pool: &PgPool, query: PgQuery<'_>) -> Result<u64, AppError> {
+    Ok(query.execute(pool).await?.rows_affected())
+}
+
+/// Turns a unique constraint violation into a conflict with the given message.
+fn conflict_on_unique<E: Into<AppError>>(msg: &'static str) -> impl Fn(E) -> AppError {
+    move |e| match e.into() {
+        AppError::Sql(sqlx::Error::Database(ref db)) if db.is_unique_violation() => {
+            AppError::Conflict(msg.into())
+        }
+        e => e,
+    }
+}
+
+fn parse_id(id: &str) -> Result<ObjectId, AppError> {
+    ObjectId::parse_str(id).map_err(|e| {
+        tracing::error!("invalid id {:?} in database: {}", id, e);
+        AppError::Internal
+    })
+}
+
+fn to_timestamp(dt: DateTime) -> OffsetDateTime {
+    OffsetDateTime::from_unix_timestamp_nanos(dt.timestamp_millis() as i128 * 1_000_000)
+        .unwrap_or(OffsetDateTime::UNIX_EPOCH)
+}
+
+fn from_timestamp(ts: OffsetDateTime) -> DateTime {
+    DateTime::from_millis((ts.unix_timestamp_nanos() / 1_000_000) as i64)
+}
+
+/// Columns of a user row, with the user's loans aggregated in borrowing order.
+const USER_COLUMNS: &str = "id, email, username, password_hash, password_history, \
+    password_changed_at, email_verified, pending_email, oidc_subject, ldap_dn, disabled, \
+    suspension_reason, suspended_at, suspension_expires_at, suspended_by, deleted_at, category, \
+    membership_started_at, membership_expires_at, guardian_id, loan_limit, family_loan_cap, \
+    is_admin, token_version, \
+    ARRAY(SELECT book_id FROM loans WHERE loans.user_id = users.id ORDER BY seq) \
+    AS borrowed_books";
+
+#[derive(FromRow)]
+struct UserRow {
+    id: String,
+    email: String,
+    username: String,
+    password_hash: String,
+    password_history: Vec<String>,
+    password_changed_at: Option<OffsetDateTime>,
+    email_verified: bool,
+    pending_email: Option<String>,
+    oidc_subject: Option<String>,
+    ldap_dn: Option<String>,
+    disabled: bool,
+    suspension_reason: Option<String>,
+    suspended_at: Option<OffsetDateTime>,
+    suspension_expires_at: Option<OffsetDateTime>,
+    suspended_by: Option<String>,
+    deleted_at: Option<OffsetDateTime>,
+    category: String,
+    membership_started_at: Option<OffsetDateTime>,
+    membership_expires_at: Option<OffsetDateTime>,
+    guardian_id: Option<String>,
+    loan_limit: Option<i64>,
+    family_loan_cap: Option<i64>,
+    is_admin: bool,
+    token_version: i32,
+    borrowed_books: Vec<String>,
+}
+
+impl TryFrom<UserRow> for User {
+    type Error = AppError;
+
+    fn try_from(row: UserRow) -> Result<Self, AppError> {
+        let suspension = match (row.suspended_at, row.suspended_by) {
+            (Some(suspended_at), Some(suspended_by)) => Some(Suspension {
+                reason: row.suspension_reason.unwrap_or_default(),
+                suspended_at: from_timestamp(suspended_at),
+                expires_at: row.suspension_expires_at.map(from_timestamp),
+                suspended_by: parse_id(&suspended_by)?,
+            }),
+            _ => None,
+        };
+
+        let category = row.category.parse().map_err(|e| {
+            tracing::error!("invalid user row {}: {}", row.id, e);
+            AppError::Internal
+        })?;
+
+        Ok(User {
+            id: parse_id(&row.id)?,
+            email: row.email,
+            username: row.username,
+            password_hash: row.password_hash,
+            password_history: row.password_history,
+            password_changed_at: row.password_changed_at.map(from_timestamp),
+            email_verified: row.email_verified,
+            pending_email: row.pending_email,
+            oidc_subject: row.oidc_subject,
+            ldap_dn: row.ldap_dn,
+            disabled: row.disabled,
+            suspension,
+            deleted_at: row.deleted_at.map(from_timestamp),
+            category,
+            membership_started_at: row.membership_started_at.map(from_timestamp),
+            membership_expires_at: row.membership_expires_at.map(from_timestamp),
+            guardian_id: row.guardian_id.as_deref().map(parse_id).transpose()?,
+            loan_limit: row.loan_limit.map(|limit| limit as u32),
+            family_loan_cap: row.family_loan_cap.map(|cap| cap as u32),
+            is_admin: row.is_admin,
+            token_version: row.token_version,
+            borrowed_books: row
+                .borrowed_books
+                .iter()
+                .map(|id| parse_id(id))
+                .collect::<Result<_, _>>()?,
+        })
+    }
+}
+
+#[derive(Clone)]
+pub struct PostgresUserRepository {
+    pool: PgPool,
+}
+
+impl PostgresUserRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    async fn find_one(&self, column: &str, value: &str) -> Result<Option<User>, AppError> {
+        let sql = format!(
+            "SELECT {} FROM users WHERE {} = $1 ORDER BY id LIMIT 1",
+            USER_COLUMNS, column
+        );
+        sqlx::query_as::<_, UserRow>(&sql)
+            .bind(value)
+            .fetch_optional(&self.pool)
+            .await?
+            .map(User::try_from)
+            .transpose()
+    }
+
+    async fn find_many(&self, filter: &str, value: Option<&str>) -> Result<Vec<User>, AppError> {
+        let sql = format!(
+            "SELECT {} FROM users WHERE {} ORDER BY id",
+            USER_COLUMNS, filter
+        );
+        let mut query = sqlx::query_as::<_, UserRow>(&sql);
+        if let Some(value) = value {
+            query = query.bind(value);
+        }
+        query
+            .fetch_all(&self.pool)
+            .await?
+            .into_iter()
+            .map(User::try_from)
+            .collect()
+    }
+}
+
+#[async_trait]
+impl UserRepository for PostgresUserRepository {
+    async fn find_by_email(&self, email: &str) -> Result<Option<User>, AppError> {
+        self.find_one("email", email).await
+    }
+
+    async fn find_by_id(&self, id: &ObjectId) -> Result<Option<User>, AppError> {
+        self.find_one("id", &id.to_hex()).await
+    }
+
+    async fn find_by_oidc_subject(&self, subject: &str) -> Result<Option<User>, AppError> {
+        self.find_one("oidc_subject", subject).await
+    }
+
+    async fn set_oidc_subject(&self, id: &ObjectId, subject: &str) -> Result<(), AppError> {
+        execute(
+            &self.pool,
+            sqlx::query("UPDATE users SET oidc_subject = $2 WHERE id = $1")
+                .bind(id.to_hex())
+                .bind(subject),
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn find_by_ldap_dn(&self, dn: &str) -> Result<Option<User>, AppError> {
+        self.find_one("ldap_dn", dn).await
+    }
+
+    async fn find_ldap_users(&self) -> Result<Vec<User>, AppError> {
+        self.find_many("ldap_dn IS NOT NULL AND NOT disabled", None)
+            .await
+    }
+
+    async fn sync_ldap_account(
+        &self,
+        id: &ObjectId,
+        dn: &str,
+        username: &str,
+        email: &str,
+        is_admin: bool,
+    ) -> Result<(), AppError> {
+        execute(
+            &self.pool,
+            sqlx::query(
+                "UPDATE users SET ldap_dn = $2, username = $3, email = $4, email_verified = TRUE, \
+                 is_admin = $5, pending_email = NULL WHERE id = $1",
+            )
+            .bind(id.to_hex())
+            .bind(dn)
+            .bind(username)
+            .bind(email)
+            .bind(is_admin),
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn disable(&self, id: &ObjectId) -> Result<(), AppError> {
+        execute(
+            &self.pool,
+            sqlx::query(
+                "UPDATE users SET disabled = TRUE, token_version = token_version + 1 \
+                 WHERE id = $1",
+            )
+            .bind(id.to_hex()),
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn enable(&self, id: &ObjectId) -> Result<(), AppError> {
+        execute(
+            &self.pool,
+            sqlx::query("UPDATE users SET disabled = FALSE WHERE id = $1").bind(id.to_hex()),
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn anonymize(&self, id: &ObjectId) -> Result<(), AppError> {
+        execute(
+            &self.pool,
+            sqlx::query(
+                "UPDATE users SET email = '', username = $2, password_hash = '', \
+                 password_history = '{}', password_changed_at = NULL, email_verified = FALSE, \
+                 pending_email = NULL, oidc_subject = NULL, ldap_dn = NULL, disabled = TRUE, \
+                 suspension_reason = NULL, suspended_at = NULL, suspension_expires_at = NULL, \
+                 suspended_by = NULL, guardian_id = NULL, loan_limit = NULL, \
+                 family_loan_cap = NULL, is_admin = FALSE, token_version = token_version + 1 \
+                 WHERE id = $1",
+            )
+            .bind(id.to_hex())
+            .bind(User::anonymized_username(id)),
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn suspend(&self, id: &ObjectId, suspension: &Suspension) -> Result<(), AppError> {
+        execute(
+            &self.pool,
+            sqlx::query(
+                "UPDATE users SET suspension_reason = $2, suspended_at = $3, \
+                 suspension_expires_at = $4, suspended_by = $5 WHERE id = $1",
+            )
+            .bind(id.to_hex())
+            .bind(&suspension.reason)
+            .bind(to_timestamp(suspension.suspended_at))
+            .bind(suspension.expires_at.map(to_timestamp))
+            .bind(suspension.suspended_by.to_hex()),
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn reactivate(&self, id: &ObjectId) -> Result<(), AppError> {
+        execute(
+            &self.pool,
+            sqlx::query(
+                "UPDATE users SET suspension_reason = NULL, suspended_at = NULL, \
+                 suspension_expires_at = NULL, suspended_by = NULL WHERE id = $1",
+            )
+            .bind(id.to_hex()),
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn soft_delete(&self, id: &ObjectId) -> Result<(), AppError> {
+        execute(
+            &self.pool,
+            sqlx::query(
+                "UPDATE users SET deleted_at = now(), token_version = token_version + 1 \
+                 WHERE id = $1",
+            )
+            .bind(id.to_hex()),
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn restore(&self, id: &ObjectId) -> Result<(), AppError> {
+        execute(
+            &self.pool,
+            sqlx::query("UPDATE users SET deleted_at = NULL WHERE id = $1").bind(id.to_hex()),
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn purge_deleted(&self, cutoff: DateTime) -> Result<Vec<ObjectId>, AppError> {
+        let ids: Vec<String> =
+            sqlx::query_scalar("DELETE FROM users WHERE deleted_at < $1 RETURNING id")
+                .bind(to_timestamp(cutoff))
+                .fetch_all(&self.pool)
+                .await?;
+        ids.iter().map(|id| parse_id(id)).collect()
+    }
+
+    async fn set_category(&self, id: &ObjectId, category: PatronCategory) -> Result<(), AppError> {
+        execute(
+            &self.pool,
+            sqlx::query("UPDATE users SET category = $2 WHERE id = $1")
+                .bind(id.to_hex())
+                .bind(category.as_str()),
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn set_membership_expiry(
+        &self,
+        id: &ObjectId,
+        expires_at: Option<DateTime>,
+    ) -> Result<(), AppError> {
+        execute(
+            &self.pool,
+            sqlx::query("UPDATE users SET membership_expires_at = $2 WHERE id = $1")
+                .bind(id.to_hex())
+                .bind(expires_at.map(to_timestamp)),
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn extend_memberships_by_days(
+        &self,
+        category: PatronCategory,
+        days: i64,
+    ) -> Result<u64, AppError> {
+        execute(
+            &self.pool,
+            sqlx::query(
+                "UPDATE users SET membership_expires_at = \
+                 GREATEST(membership_expires_at, now()) + make_interval(days => $2::INTEGER) \
+                 WHERE category = $1 AND membership_expires_at IS NOT NULL",
+            )
+            .bind(category.as_str())
+            .bind(days),
+        )
+        .await
+    }
+
+    async fn extend_memberships_until(
+        &self,
+        category: PatronCategory,
+        until: DateTime,
+    ) -> Result<u64, AppError> {
+        execute(
+            &self.pool,
+            sqlx::query(
+                "UPDATE users SET membership_expires_at = $2 \
+                 WHERE category = $1 AND membership_expires_at < $2",
+            )
+            .bind(category.as_str())
+            .bind(to_timestamp(until)),
+        )
+        .await
+    }
+
+    async fn find_dependents(&self, guardian_id: &ObjectId) -> Result<Vec<User>, AppError> {
+        self.find_many(
+            "guardian_id = $1 AND deleted_at IS NULL",
+            Some(&guardian_id.to_hex()),
+        )
+        .await
+    }
+
+    async fn set_guardian(
+        &self,
+        id: &ObjectId,
+        guardian_id: Option<&ObjectId>,
+    ) -> Result<(), AppError> {
+        let query = match guardian_id {
+            Some(guardian_id) => sqlx::query("UPDATE users SET guardian_id = $2 WHERE id = $1")
+                .bind(id.to_hex())
+                .bind(guardian_id.to_hex()),
+            None => {
+                sqlx::query("UPDATE users SET guardian_id = NULL, loan_limit = NULL WHERE id = $1")
+                    .bind(id.to_hex())
+            }
+        };
+        execute(&self.pool, query).await?;
+        Ok(())
+    }
+
+    async fn set_loan_limit(&self, id: &ObjectId, limit: Option<u32>) -> Result<(), AppError> {
+        execute(
+            &self.pool,
+            sqlx::query("UPDATE users SET loan_limit = $2 WHERE id = $1")
+                .bind(id.to_hex())
+                .bind(limit.map(i64::from)),
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn set_family_loan_cap(&self, id: &ObjectId, cap: Option<u32>) -> Result<(), AppError> {
+        execute(
+            &self.pool,
+            sqlx::query("UPDATE users SET family_loan_cap = $2 WHERE id = $1")
+                .bind(id.to_hex())
+                .bind(cap.map(i64::from)),
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn find_all(&self) -> Result<Vec<User>, AppError> {
+        self.find_many("TRUE", None).await
+    }
+
+    async fn set_admin(&self, id: &ObjectId, is_admin: bool) -> Result<(), AppError> {
+        execute(
+            &self.pool,
+            sqlx::query("UPDATE users SET is_admin = $2 WHERE id = $1")
+                .bind(id.to_hex())
+                .bind(is_admin),
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn create(&self, user: &User) -> Result<(), AppError> {
+        let mut tx = self.pool.begin().await?;
+        let suspension = user.suspension.as_ref();
+
+        sqlx::query(
+            "INSERT INTO users (id, email, username, password_hash, password_history, \
+             password_changed_at, email_verified, pending_email, oidc_subject, ldap_dn, disabled, \
+             suspension_reason, suspended_at, suspension_expires_at, suspended_by, deleted_at, \
+             category, membership_started_at, membership_expires_at, guardian_id, loan_limit, \
+             family_loan_cap, is_admin, token_version) \
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, \
+             $18, $19, $20, $21, $22, $23, $24)",
+        )
+        .bind(user.id.to_hex())
+        .bind(&user.email)
+        .bind(&user.username)
+        .bind(&user.password_hash)
+        .bind(&user.password_history)
+        .bind(user.password_changed_at.map(to_timestamp))
+        .bind(user.email_verified)
+        .bind(&user.pending_email)
+        .bind(&user.oidc_subject)
+        .bind(&user.ldap_dn)
+        .bind(user.disabled)
+        .bind(suspension.map(|s| s.reason.clone()))
+        .bind(suspension.map(|s| to_timestamp(s.suspended_at)))
+        .bind(suspension.and_then(|s| s.expires_at).map(to_timestamp))
+        .bind(suspension.map(|s| s.suspended_by.to_hex()))
+        .bind(user.deleted_at.map(to_timestamp))
+        .bind(user.category.as_str())
+        .bind(user.membership_started_at.map(to_timestamp))
+        .bind(user.membership_expires_at.map(to_timestamp))
+        .bind(user.guardian_id.map(|id| id.to_hex()))
+        .bind(user.loan_limit.map(i64::from))
+        .bind(user.family_loan_cap.map(i64::from))
+        .bind(user.is_admin)
+        .bind(user.token_version)
+        .execute(&mut *tx)
+        .await
+        .map_err(conflict_on_unique(EMAIL_ALREADY_EXISTS))?;
+
+        if !user.borrowed_books.is_empty() {
+            let book_ids: Vec<String> = user.borrowed_books.iter().map(|id| id.to_hex()).collect();
+            sqlx::query(
+                "INSERT INTO loans (user_id, book_id) \
+                 SELECT $1, book_id FROM UNNEST($2::CHAR(24)[]) WITH ORDINALITY AS b(book_id, n) \
+                 ORDER BY n",
+            )
+            .bind(user.id.to_hex())
+            .bind(book_ids)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn update_email(&self, id: &ObjectId, new_email: &str) -> Result<(), AppError> {
+        execute(
+            &self.pool,
+            sqlx::query(
+                "UPDATE users SET email = $2, email_verified = FALSE, pending_email = NULL \
+                 WHERE id = $1",
+            )
+            .bind(id.to_hex())
+            .bind(new_email),
+        )
+        .await
+        .map_err(conflict_on_unique(EMAIL_ALREADY_EXISTS))?;
+        Ok(())
+    }
+
+    async fn set_pending_email(&self, id: &ObjectId, email: &str) -> Result<(), AppError> {
+        execute(
+            &self.pool,
+            sqlx::query("UPDATE users SET pending_email = $2 WHERE id = $1")
+                .bind(id.to_hex())
+                .bind(email),
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn confirm_email(&self, id: &ObjectId, email: &str) -> Result<(), AppError> {
+        execute(
+            &self.pool,
+            sqlx::query(
+                "UPDATE users SET email = $2, email_verified = TRUE, pending_email = NULL \
+                 WHERE id = $1",
+            )
+            .bind(id.to_hex())
+            .bind(email),
+        )
+        .await
+        .map_err(conflict_on_unique(EMAIL_ALREADY_EXISTS))?;
+        Ok(())
+    }
+
+    async fn mark_email_verified(&self, id: &ObjectId) -> Result<(), AppError> {
+        execute(
+            &self.pool,
+            sqlx::query("UPDATE users SET email_verified = TRUE WHERE id = $1").bind(id.to_hex()),
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn update_username(&self, id: &ObjectId, new_username: &str) -> Result<(), AppError> {
+        execute(
+            &self.pool,
+            sqlx::query("UPDATE users SET username = $2 WHERE id = $1")
+                .bind(id.to_hex())
+                .bind(new_username),
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn update_password(&self, id: &ObjectId, password_hash: &str) -> Result<(), AppError> {
+        execute(
+            &self.pool,
+            sqlx::query("UPDATE users SET password_hash = $2 WHERE id = $1")
+                .bind(id.to_hex())
+                .bind(password_hash),
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn change_password(
+        &self,
+        id: &ObjectId,
+        password_hash: &str,
+        password_history: &[String],
+    ) -> Result<(), AppError> {
+        execute(
+            &self.pool,
+            sqlx::query(
+                "UPDATE users SET password_hash = $2, password_history = $3, \
+                 password_changed_at = now() WHERE id = $1",
+            )
+            .bind(id.to_hex())
+            .bind(password_hash)
+            .bind(password_history),
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn update_token_version(
+        &self,
+        id: &ObjectId,
+        token_version: i32,
+    ) -> Result<(), AppError> {
+        execute(
+            &self.pool,
+            sqlx::query("UPDATE users SET token_version = $2 WHERE id = $1")
+                .bind(id.to_hex())
+                .bind(token_version),
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn add_borrowed_book(
+        &self,
+        user_id: &ObjectId,
+        book_id: &ObjectId,
+        limit: usize,
+    ) -> Result<(), AppError> {
+        let mut tx = self.pool.begin().await?;
+
+        // Locking the user row serialises concurrent borrows by the same user, so the
+        // limit check and the insert cannot interleave.
+        let locked: Option<String> =
+            sqlx::query_scalar("SELECT id FROM users WHERE id = $1 FOR UPDATE")
+                .bind(user_id.to_hex())
+                .fetch_optional(&mut *tx)
+                .await?;
+        if locked.is_none() {
+            return Err(AppError::NotFound(USER_NOT_FOUND.into()));
+        }
+
+        let (loans, already_borrowed): (i64, bool) = sqlx::query_as(
+            "SELECT COUNT(*), COALESCE(BOOL_OR(book_id = $2), FALSE) FROM loans \
+             WHERE user_id = $1",
+        )
+        .bind(user_id.to_hex())
+        .bind(book_id.to_hex())
+        .fetch_one(&mut *tx)
+        .await?;
+
+        if already_borrowed {
+            return Err(AppError::BadRequest(BOOK_ALREADY_BORROWED.into()));
+        }
+
+        if loans as usize >= limit {
+            return Err(AppError::BadRequest(BORROW_LIMIT_REACHED.into()));
+        }
+
+        sqlx::query("INSERT INTO loans (user_id, book_id) VALUES ($1, $2)")
+            .bind(user_id.to_hex())
+            .bind(book_id.to_hex())
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn remove_borrowed_book(
+        &self,
+        user_id: &ObjectId,
+        book_id: &ObjectId,
+    ) -> Result<(), AppError> {
+        let removed = execute(
+            &self.pool,
+            sqlx::query("DELETE FROM loans WHERE user_id = $1 AND book_id = $2")
+                .bind(user_id.to_hex())
+                .bind(book_id.to_hex()),
+        )
+        .await?;
+
+        if removed == 0 {
+            return Err(AppError::BadRequest(BOOK_NOT_BORROWED.into()));
+        }
+
+        Ok(())
+    }
+
+    async fn count_active_loans(&self) -> Result<u64, AppError> {
+        let (count,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM loans")
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(count as u64)
+    }
+}
+
+#[derive(FromRow)]
+struct BookRow {
+    id: String,
+    title: String,
+    author: String,
+    stock: i32,
+}
+
+impl TryFrom<BookRow> for Book {
+    type Error = AppError;
+
+    fn try_from(row: BookRow) -> Result<Self, AppError> {
+        Ok(Book {
+            id: parse_id(&row.id)?,
+            title: row.title,
+            author: row.author,
+            stock: row.stock,
+        })
+    }
+}
+
+#[derive(Clone)]
+pub struct PostgresBookRepository {
+    pool: PgPool,
+}
+
+impl PostgresBookRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    async fn find_many(&self, filter: &str, value: Option<&str>) -> Result<Vec<Book>, AppError> {
+        let sql = format!(
+            "SELECT id, title, author, stock FROM books WHERE {} ORDER BY id",
+            filter
+        );
+        let mut query = sqlx::query_as::<_, BookRow>(&sql);
+        if let Some(value) = value {
+            query = query.bind(value);
+        }
+        query
+            .fetch_all(&self.pool)
+            .await?
+            .into_iter()
+            .map(Book::try_from)
+            .collect()
+    }
+}
+
+#[async_trait]
+impl BookRepository for PostgresBookRepository {
+    async fn create(&self, title: &str, author: &str) -> Result<Book, AppError> {
+        // The unique constraint on (title, author) makes the duplicate check atomic.
+        let row: Option<BookRow> = sqlx::query_as(
+            "INSERT INTO books (id, title, author, stock) VALUES ($1, $2, $3, 0) \
+             ON CONFLICT (title, author) DO NOTHING RETURNING id, title, author, stock",
+        )
+        .bind(ObjectId::new().to_hex())
+        .bind(title)
+        .bind(author)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        row.ok_or_else(|| AppError::Conflict(BOOK_ALREADY_EXISTS.into()))?
+            .try_into()
+    }
+
+    async fn find_by_id(&self, id: &ObjectId) -> Result<Option<Book>, AppError> {
+        Ok(self.find_many("id = $1", Some(&id.to_hex())).await?.pop())
+    }
+
+    async fn find_all(&self) -> Result<Vec<Book>, AppError> {
+        self.find_many("TRUE", None).await
+    }
+
+    async fn find_by_title(&self, title: &str) -> Result<Vec<Book>, AppError> {
+        self.find_many("title = $1", Some(title)).await
+    }
+
+    async fn find_by_author(&self, author: &str) -> Result<Vec<Book>, AppError> {
+        self.find_many("author = $1", Some(author)).await
+    }
+
+    async fn delete_by_id(&self, id: &ObjectId) -> Result<(), AppError> {
+        execute(
+            &self.pool,
+            sqlx::query("DELETE FROM books WHERE id = $1").bind(id.to_hex()),
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn update_title(&self, id: &ObjectId, title: &str) -> Result<(), AppError> {
+        execute(
+            &self.pool,
+            sqlx::query("UPDATE books SET title = $2 WHERE id = $1")
+                .bind(id.to_hex())
+                .bind(title),
+        )
+        .await
+        .map_err(conflict_on_unique(BOOK_ALREADY_EXISTS))?;
+        Ok(())
+    }
+
+    async fn update_author(&self, id: &ObjectId, author: &str) -> Result<(), AppError> {
+        execute(
+            &self.pool,
+            sqlx::query("UPDATE books SET author = $2 WHERE id = $1")
+                .bind(id.to_hex())
+                .bind(author),
+        )
+        .await
+        .map_err(conflict_on_unique(BOOK_ALREADY_EXISTS))?;
+        Ok(())
+    }
+
+    async fn update_stock(&self, id: &ObjectId, stock: i32) -> Result<(), AppError> {
+        execute(
+            &self.pool,
+            sqlx::query("UPDATE books SET stock = $2 WHERE id = $1")
+                .bind(id.to_hex())
+                .bind(stock),
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn borrow_book(&self, id: &ObjectId) -> Result<(), AppError> {
+        // The guard and the decrement are one statement, so concurrent borrows of the
+        // last copy cannot both succeed.
+        let taken = execute(
+            &self.pool,
+            sqlx::query("UPDATE books SET stock = stock - 1 WHERE id = $1 AND stock > 0")
+                .bind(id.to_hex()),
+        )
+        .await?;
+
+        if taken == 0 {
+            return Err(AppError::BadRequest(NO_STOCK_AVAILABLE.into()));
+        }
+
+        Ok(())
+    }
+
+    async fn return_book(&self, id: &ObjectId) -> Result<(), AppError> {
+        execute(
+            &self.pool,
+            sqlx::query("UPDATE books SET stock = stock + 1 WHERE id = $1").bind(id.to_hex()),
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn count_out_of_stock(&self) -> Result<u64, AppError> {
+        let (count,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM books WHERE stock <= 0")
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(count as u64)
+    }
+}
+
+#[derive(FromRow)]
+struct ApiKeyRow {
+    id: String,
+    user_id: String,
+    name: String,
+    key_hash: String,
+    prefix: String,
+    scopes: Vec<String>,
+    created_at: OffsetDateTime,
+    expires_at: Option<OffsetDateTime>,
+    last_used_at: Option<OffsetDateTime>,
+}
+
+impl TryFrom<ApiKeyRow> for ApiKey {
+    type Error = AppError;
+
+    fn try_from(row: ApiKeyRow) -> Result<Self, AppError> {
+        Ok(ApiKey {
+            id: parse_id(&row.id)?,
+            user_id: parse_id(&row.user_id)?,
+            name: row.name,
+            key_hash: row.key_hash,
+            prefix: row.prefix,
+            scopes: row.scopes,
+            created_at: from_timestamp(row.created_at),
+            expires_at: row.expires_at.map(from_timestamp),
+            last_used_at: row.last_used_at.map(from_timestamp),
+        })
+    }
+}
+
+#[derive(Clone)]
+pub struct PostgresApiKeyRepository {
+    pool: PgPool,
+}
+
+impl PostgresApiKeyRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    async fn find_many(&self, column: &str, value: &str) -> Result<Vec<ApiKey>, AppError> {
+        let sql = format!(
+            "SELECT id, user_id, name, key_hash, prefix, scopes, created_at, expires_at, \
+             last_used_at FROM api_keys WHERE {} = $1 ORDER BY id",
+            column
+        );
+        sqlx::query_as::<_, ApiKeyRow>(&sql)
+            .bind(value)
+            .fetch_all(&self.pool)
+            .await?
+            .into_iter()
+            .map(ApiKey::try_from)
+            .collect()
+    }
+}
+
+#[async_trait]
+impl ApiKeyRepository for PostgresApiKeyRepository {
+    async fn create(&self, api_key: &ApiKey) -> Result<(), AppError> {
+        execute(
+            &self.pool,
+            sqlx::query(
+                "INSERT INTO api_keys (id, user_id, name, key_hash, prefix, scopes, created_at, \
+                 expires_at, last_used_at) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)",
+            )
+            .bind(api_key.id.to_hex())
+            .bind(api_key.user_id.to_hex())
+            .bind(&api_key.name)
+            .bind(&api_key.key_hash)
+            .bind(&api_key.prefix)
+            .bind(&api_key.scopes)
+            .bind(to_timestamp(api_key.created_at))
+            .bind(api_key.expires_at.map(to_timestamp))
+            .bind(api_key.last_used_at.map(to_timestamp)),
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn find_by_id(&self, id: &ObjectId) -> Result<Option<ApiKey>, AppError> {
+        Ok(self.find_many("id", &id.to_hex()).await?.pop())
+    }
+
+    async fn find_by_hash(&self, key_hash: &str) -> Result<Option<ApiKey>, AppError> {
+        Ok(self.find_many("key_hash", key_hash).await?.pop())
+    }
+
+    async fn find_by_user(&self, user_id: &ObjectId) -> Result<Vec<ApiKey>, AppError> {
+        self.find_many("user_id", &user_id.to_hex()).await
+    }
+
+    async fn delete_by_id(&self, id: &ObjectId) -> Result<(), AppError> {
+        execute(
+            &self.pool,
+            sqlx::query("DELETE FROM api_keys WHERE id = $1").bind(id.to_hex()),
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn delete_by_user(&self, user_id: &ObjectId) -> Result<(), AppError> {
+        execute(
+            &self.pool,
+            sqlx::query("DELETE FROM api_keys WHERE user_id = $1").bind(user_id.to_hex()),
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn touch_last_used(&self, id: &ObjectId) -> Result<(), AppError> {
+        execute(
+            &self.pool,
+            sqlx::query("UPDATE api_keys SET last_used_at = now() WHERE id = $1").bind(id.to_hex()),
+        )
+        .await?;
+        Ok(())
+    }
+}
+
+/// These run against a real server: `TEST_DATABASE_URL=postgres://... cargo test
+/// --features postgres -- --ignored`.
+#[derive(Clone)]
+pub struct PostgresHealthCheck {
+    pool: PgPool,
+}
+
+impl PostgresHealthCheck {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl HealthCheck for PostgresHealthCheck {
+    fn name(&self) -> &'static str {
+        "postgres"
+    }
+
+    async fn ping(&self) -> Result<(), AppError> {
+        sqlx::query("SELECT 1").execute(&self.pool).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::future::join_all;
+
+    async fn pool() -> PgPool {
+        let url = std::env::var("TEST_DATABASE_URL").expect("TEST_DATABASE_URL is not set");
+        init_postgres(&url, 20, true).await.unwrap()
+    }
+
+    async fn book(repo: &PostgresBookRepository, stock: i32) -> Book {
+        let book = repo
+            .create(&ObjectId::new().to_hex(), "Frank Herbert")
+            .await
+            .unwrap();
+        repo.update_stock(&book.id, stock).await.unwrap();
+        book
+    }
+
+    #[actix_web::test]
+    #[ignore]
+    async fn concurrent_borrows_never_drive_stock_negative() {
+        let repo = PostgresBookRepository::new(pool().await);
+        let book = book(&repo, 5).await;
+
+        let results = join_all((0..50).map(|_| repo.borrow_book(&book.id))).await;
+
+        assert_eq!(results.iter().filter(|r| r.is_ok()).count(), 5);
+        let stock = repo.find_by_id(&book.id).await.unwrap().unwrap().stock;
+        assert_eq!(stock, 0);
+    }
+
+    #[actix_web::test]
+    #[ignore]
+    async fn concurrent_loans_respect_the_borrow_limit() {
+        let pool = pool().await;
+        let users = PostgresUserRepository::new(pool.clone());
+        let books = PostgresBookRepository::new(pool);
+        let user = user();
+        users.create(&user).await.unwrap();
+
+        let mut ids = Vec::new();
+        for _ in 0..10 {
+            ids.push(book(&books, 1).await.id);
+        }
+        let results = join_all(
+            ids.iter()
+                .map(|id| users.add_borrowed_book(&user.id, id, 3)),
+        )
+        .await;
+
+        assert_eq!(results.iter().filter(|r| r.is_ok()).count(), 3);
+        assert!(matches!(
+            users.add_borrowed_book(&user.id, &ids[0], 5).await,
+            Err(AppError::BadRequest(msg)) if msg == BOOK_ALREADY_BORROWED
+        ));
+        let stored = users.find_by_id(&user.id).await.unwrap().unwrap();
+        assert_eq!(stored.borrowed_books.len(), 3);
+    }
+
+    #[actix_web::test]
+    #[ignore]
+    async fn users_round_trip_and_loans_follow_their_user() {
+        let pool = pool().await;
+        let users = PostgresUserRepository::new(pool.clone());
+        let books = PostgresBookRepository::new(pool);
+        let book = book(&books, 1).await;
+
+        let mut user = user();
+        user.category = PatronCategory::Student;
+        user.loan_limit = Some(2);
+        user.membership_expires_at = Some(DateTime::from_millis(4_102_444_800_000));
+        user.borrowed_books = vec![book.id];
+        users.create(&user).await.unwrap();
+
+        let stored = users.find_by_id(&user.id).await.unwrap().unwrap();
+        assert_eq!(stored.category, PatronCategory::Student);
+        assert_eq!(stored.loan_limit, Some(2));
+        assert_eq!(stored.membership_expires_at, user.membership_expires_at);
+        assert_eq!(stored.borrowed_books, vec![book.id]);
+
+        users.soft_delete(&user.id).await.unwrap();
+        let cutoff = DateTime::from_millis(DateTime::now().timestamp_millis() + 60_000);
+        let purged = users.purge_deleted(cutoff).await.unwrap();
+        assert_eq!(purged, vec![user.id]);
+        assert!(matches!(
+            users.remove_borrowed_book(&user.id, &book.id).await,
+            Err(AppError::BadRequest(_))
+        ));
+        assert!(books.find_by_id(&book.id).await.unwrap().is_some());
+    }
+
+    #[actix_web::test]
+    #[ignore]
+    async fn anonymized_accounts_keep_only_statistics() {
+        let users = PostgresUserRepository::new(pool().await);
+        let mut user = user();
+        user.category = PatronCategory::Student;
+        user.password_hash = "hash".into();
+        user.password_history = vec!["old".into()];
+        user.oidc_subject = Some("subject".into());
+        users.create(&user).await.unwrap();
+
+        users.anonymize(&user.id).await.unwrap();
+        let stored = users.find_by_id(&user.id).await.unwrap().unwrap();
+        assert!(stored.email.is_empty() && stored.password_hash.is_empty());
+        assert!(stored.password_history.is_empty() && stored.oidc_subject.is_none());
+        assert_eq!(stored.username, User::anonymized_username(&user.id));
+        assert!(stored.disabled);
+        assert_eq!(stored.category, PatronCategory::Student);
+        assert_eq!(stored.token_version, user.token_version + 1);
+    }
+
+    fn user() -> User {
+        // Emails are unique and the database outlives a test run.
+        let id = ObjectId::new();
+        User {
+            id,
+            email: format!("{}@example.com", id),
+            username: "reader".into(),
+            password_hash: String::new(),
+            password_history: Vec::new(),
+            password_changed_at: None,
+            email_verified: false,
+            pending_email: None,
+            oidc_subject: None,
+            ldap_dn: None,
+            disabled: false,
+            suspension: None,
+            deleted_at: None,
+            category: PatronCategory::Public,
+            membership_started_at: None,
+            membership_expires_at: None,
+            guardian_id: None,
+            loan_limit: None,
+            family_loan_cap: None,
+            is_admin: false,
+            token_version: 0,
+            borrowed_books: Vec::new(),
+        }
+    }
+}
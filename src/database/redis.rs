@@ -1,5 +1,21 @@
+use crate::constants::{
+    EMAIL_VERIFICATION_COOLDOWN_KEY_PREFIX, EMAIL_VERIFICATION_KEY_PREFIX, OIDC_STATE_KEY_PREFIX,
+    PASSWORD_RESET_KEY_PREFIX, PASSWORD_RESET_USER_KEY_PREFIX, RATE_LIMIT_KEY_PREFIX,
+};
+use crate::database::health::HealthCheck;
+use crate::database::store::{
+    EmailVerificationStore, OidcStateStore, PasswordResetStore, RateLimitDecision,
+    RateLimitStore, TokenBlacklist,
+};
 use crate::errors::AppError;
-use redis::{Client, aio::ConnectionManager};
+use crate::utils::metrics::METRICS;
+use async_trait::async_trait;
+use rand_core::{OsRng, RngCore};
+use redis::{Client, RedisResult, Script, aio::ConnectionManager};
+use std::future::Future;
+use std::sync::LazyLock;
+use std::time::Instant;
+use tracing::{instrument, Span};
 
 pub async fn init_redis(uri: &str) -> Result<ConnectionManager, AppError> {
     let client = Client::open(uri).map_err(|_| AppError::Internal)?;
@@ -9,34 +25,378 @@ pub async fn init_redis(uri: &str) -> Result<ConnectionManager, AppError> {
         .map_err(|_| AppError::Internal)
 }
 
+/// Runs a Redis call, recording how long it took and whether it failed, and names it
+/// on the current repository span. `keyspace` names the kind of key it touches.
+async fn observe<T>(
+    keyspace: &str,
+    operation: &str,
+    call: impl Future<Output = RedisResult<T>>,
+) -> Result<T, AppError> {
+    let span = Span::current();
+    span.record("db.keyspace", keyspace);
+    span.record("db.operation", operation);
+
+    let started = Instant::now();
+    let result = call.await;
+    METRICS.observe_database("redis", operation, keyspace, started.elapsed(), result.is_ok());
+    result.map_err(|_| AppError::Internal)
+}
+
+#[derive(Clone)]
+pub struct RedisTokenBlacklist {
+    conn: ConnectionManager,
+}
+
+impl RedisTokenBlacklist {
+    pub fn new(conn: ConnectionManager) -> Self {
+        Self { conn }
+    }
+}
+
+#[async_trait]
+impl TokenBlacklist for RedisTokenBlacklist {
+    #[instrument(skip_all, fields(db.system = "redis", db.keyspace, db.operation))]
+    async fn add_token(&self, token: &str, exp_seconds: i64) -> Result<(), AppError> {
+        let mut conn = self.conn.clone();
+        observe(
+            "blacklist",
+            "SETEX",
+            redis::cmd("SETEX")
+                .arg(format!("blacklist:{}", token))
+                .arg(exp_seconds)
+                .arg("1")
+                .query_async(&mut conn),
+        )
+        .await
+    }
+
+    #[instrument(skip_all, fields(db.system = "redis", db.keyspace, db.operation))]
+    async fn is_blacklisted(&self, token: &str) -> Result<bool, AppError> {
+        let mut conn = self.conn.clone();
+        let result: Option<String> = observe(
+            "blacklist",
+            "GET",
+            redis::cmd("GET")
+                .arg(format!("blacklist:{}", token))
+                .query_async(&mut conn),
+        )
+        .await?;
+        Ok(result.is_some())
+    }
+}
+
+#[derive(Clone)]
+pub struct RedisPasswordResetStore {
+    conn: ConnectionManager,
+}
+
+impl RedisPasswordResetStore {
+    pub fn new(conn: ConnectionManager) -> Self {
+        Self { conn }
+    }
+}
+
+#[async_trait]
+impl PasswordResetStore for RedisPasswordResetStore {
+    #[instrument(skip_all, fields(db.system = "redis", db.keyspace, db.operation))]
+    async fn store(
+        &self,
+        token_hash: &str,
+        user_id: &str,
+        ttl_seconds: i64,
+    ) -> Result<(), AppError> {
+        let mut conn = self.conn.clone();
+        let user_key = format!("{}{}", PASSWORD_RESET_USER_KEY_PREFIX, user_id);
+
+        let previous: Option<String> = observe(
+            "password_reset",
+            "GET",
+            redis::cmd("GET")
+                .arg(&user_key)
+                .query_async(&mut conn),
+        )
+        .await?;
+
+        let mut pipe = redis::pipe();
+        if let Some(previous) = previous {
+            pipe.cmd("DEL")
+                .arg(format!("{}{}", PASSWORD_RESET_KEY_PREFIX, previous))
+                .ignore();
+        }
+        pipe.cmd("SETEX")
+            .arg(format!("{}{}", PASSWORD_RESET_KEY_PREFIX, token_hash))
+            .arg(ttl_seconds)
+            .arg(user_id)
+            .ignore()
+            .cmd("SETEX")
+            .arg(&user_key)
+            .arg(ttl_seconds)
+            .arg(token_hash)
+            .ignore();
+
+        observe("password_reset", "PIPELINE", pipe.query_async(&mut conn)).await
+    }
+
+    #[instrument(skip_all, fields(db.system = "redis", db.keyspace, db.operation))]
+    async fn peek(&self, token_hash: &str) -> Result<Option<String>, AppError> {
+        let mut conn = self.conn.clone();
+        observe(
+            "password_reset",
+            "GET",
+            redis::cmd("GET")
+                .arg(format!("{}{}", PASSWORD_RESET_KEY_PREFIX, token_hash))
+                .query_async(&mut conn),
+        )
+        .await
+    }
+
+    #[instrument(skip_all, fields(db.system = "redis", db.keyspace, db.operation))]
+    async fn consume(&self, token_hash: &str) -> Result<Option<String>, AppError> {
+        let mut conn = self.conn.clone();
+        let user_id: Option<String> = observe(
+            "password_reset",
+            "GETDEL",
+            redis::cmd("GETDEL")
+                .arg(format!("{}{}", PASSWORD_RESET_KEY_PREFIX, token_hash))
+                .query_async(&mut conn),
+        )
+        .await?;
+
+        if let Some(ref user_id) = user_id {
+            observe(
+                "password_reset",
+                "DEL",
+                redis::cmd("DEL")
+                    .arg(format!("{}{}", PASSWORD_RESET_USER_KEY_PREFIX, user_id))
+                    .query_async::<()>(&mut conn),
+            )
+            .await?;
+        }
+
+        Ok(user_id)
+    }
+}
+
 #[derive(Clone)]
-pub struct TokenBlacklist {
+pub struct RedisEmailVerificationStore {
     conn: ConnectionManager,
 }
 
-impl TokenBlacklist {
+impl RedisEmailVerificationStore {
     pub fn new(conn: ConnectionManager) -> Self {
         Self { conn }
     }
+}
+
+#[async_trait]
+impl EmailVerificationStore for RedisEmailVerificationStore {
+    #[instrument(skip_all, fields(db.system = "redis", db.keyspace, db.operation))]
+    async fn store(
+        &self,
+        token_hash: &str,
+        user_id: &str,
+        email: &str,
+        ttl_seconds: i64,
+    ) -> Result<(), AppError> {
+        let mut conn = self.conn.clone();
+        observe(
+            "email_verification",
+            "SETEX",
+            redis::cmd("SETEX")
+                .arg(format!("{}{}", EMAIL_VERIFICATION_KEY_PREFIX, token_hash))
+                .arg(ttl_seconds)
+                .arg(format!("{}:{}", user_id, email))
+                .query_async(&mut conn),
+        )
+        .await
+    }
 
-    pub async fn add_token(&self, token: &str, exp_seconds: i64) -> Result<(), AppError> {
+    #[instrument(skip_all, fields(db.system = "redis", db.keyspace, db.operation))]
+    async fn consume(&self, token_hash: &str) -> Result<Option<(String, String)>, AppError> {
         let mut conn = self.conn.clone();
-        redis::cmd("SETEX")
-            .arg(format!("blacklist:{}", token))
-            .arg(exp_seconds)
-            .arg("1")
-            .query_async(&mut conn)
-            .await
-            .map_err(|_| AppError::Internal)
+        let value: Option<String> = observe(
+            "email_verification",
+            "GETDEL",
+            redis::cmd("GETDEL")
+                .arg(format!("{}{}", EMAIL_VERIFICATION_KEY_PREFIX, token_hash))
+                .query_async(&mut conn),
+        )
+        .await?;
+
+        Ok(value.and_then(|v| {
+            v.split_once(':')
+                .map(|(user_id, email)| (user_id.to_string(), email.to_string()))
+        }))
     }
 
-    pub async fn is_blacklisted(&self, token: &str) -> Result<bool, AppError> {
+    #[instrument(skip_all, fields(db.system = "redis", db.keyspace, db.operation))]
+    async fn try_start_cooldown(
+        &self,
+        user_id: &str,
+        cooldown_seconds: i64,
+    ) -> Result<bool, AppError> {
         let mut conn = self.conn.clone();
-        let result: Option<String> = redis::cmd("GET")
-            .arg(format!("blacklist:{}", token))
-            .query_async(&mut conn)
-            .await
-            .map_err(|_| AppError::Internal)?;
+        let result: Option<String> = observe(
+            "email_verification",
+            "SET",
+            redis::cmd("SET")
+                .arg(format!("{}{}", EMAIL_VERIFICATION_COOLDOWN_KEY_PREFIX, user_id))
+                .arg("1")
+                .arg("EX")
+                .arg(cooldown_seconds)
+                .arg("NX")
+                .query_async(&mut conn),
+        )
+        .await?;
         Ok(result.is_some())
     }
 }
+
+#[derive(Clone)]
+pub struct RedisOidcStateStore {
+    conn: ConnectionManager,
+}
+
+impl RedisOidcStateStore {
+    pub fn new(conn: ConnectionManager) -> Self {
+        Self { conn }
+    }
+}
+
+#[async_trait]
+impl OidcStateStore for RedisOidcStateStore {
+    #[instrument(skip_all, fields(db.system = "redis", db.keyspace, db.operation))]
+    async fn store(
+        &self,
+        state: &str,
+        pkce_verifier: &str,
+        nonce: &str,
+        ttl_seconds: i64,
+    ) -> Result<(), AppError> {
+        let mut conn = self.conn.clone();
+        observe(
+            "oidc_state",
+            "SETEX",
+            redis::cmd("SETEX")
+                .arg(format!("{}{}", OIDC_STATE_KEY_PREFIX, state))
+                .arg(ttl_seconds)
+                .arg(format!("{}:{}", pkce_verifier, nonce))
+                .query_async(&mut conn),
+        )
+        .await
+    }
+
+    #[instrument(skip_all, fields(db.system = "redis", db.keyspace, db.operation))]
+    async fn consume(&self, state: &str) -> Result<Option<(String, String)>, AppError> {
+        let mut conn = self.conn.clone();
+        let value: Option<String> = observe(
+            "oidc_state",
+            "GETDEL",
+            redis::cmd("GETDEL")
+                .arg(format!("{}{}", OIDC_STATE_KEY_PREFIX, state))
+                .query_async(&mut conn),
+        )
+        .await?;
+
+        Ok(value.and_then(|v| {
+            v.split_once(':')
+                .map(|(verifier, nonce)| (verifier.to_string(), nonce.to_string()))
+        }))
+    }
+}
+
+#[derive(Clone)]
+pub struct RedisRateLimitStore {
+    conn: ConnectionManager,
+}
+
+impl RedisRateLimitStore {
+    pub fn new(conn: ConnectionManager) -> Self {
+        Self { conn }
+    }
+}
+
+/// Sliding window log: a sorted set of request times per client, trimmed to the
+/// window on every hit. Runs atomically and on the server clock, so every instance
+/// sees the same window.
+static SLIDING_WINDOW: LazyLock<Script> = LazyLock::new(|| {
+    Script::new(
+        r"
+        local time = redis.call('TIME')
+        local now = tonumber(time[1]) * 1000 + math.floor(tonumber(time[2]) / 1000)
+        local window = tonumber(ARGV[1])
+        local limit = tonumber(ARGV[2])
+        redis.call('ZREMRANGEBYSCORE', KEYS[1], '-inf', now - window)
+        local count = redis.call('ZCARD', KEYS[1])
+        local allowed = 0
+        if count < limit then
+            redis.call('ZADD', KEYS[1], now, now .. ':' .. ARGV[3])
+            redis.call('PEXPIRE', KEYS[1], window)
+            count = count + 1
+            allowed = 1
+        end
+        local oldest = redis.call('ZRANGE', KEYS[1], 0, 0, 'WITHSCORES')
+        local reset = window
+        if oldest[2] then
+            reset = tonumber(oldest[2]) + window - now
+        end
+        return {allowed, limit - count, reset}
+        ",
+    )
+});
+
+#[async_trait]
+impl RateLimitStore for RedisRateLimitStore {
+    #[instrument(skip_all, fields(db.system = "redis", db.keyspace, db.operation))]
+    async fn hit(
+        &self,
+        key: &str,
+        limit: u32,
+        window_ms: u64,
+    ) -> Result<RateLimitDecision, AppError> {
+        let mut conn = self.conn.clone();
+        let (allowed, remaining, reset_after_ms): (i64, i64, i64) = observe(
+            "rate_limit",
+            "EVALSHA",
+            SLIDING_WINDOW
+                .key(format!("{}{}", RATE_LIMIT_KEY_PREFIX, key))
+                .arg(window_ms)
+                .arg(limit)
+                // Two requests in the same millisecond still need distinct members.
+                .arg(OsRng.next_u64())
+                .invoke_async(&mut conn),
+        )
+        .await?;
+
+        Ok(RateLimitDecision {
+            allowed: allowed == 1,
+            remaining: remaining.max(0) as u32,
+            reset_after_ms: reset_after_ms.max(0) as u64,
+        })
+    }
+}
+
+#[derive(Clone)]
+pub struct RedisHealthCheck {
+    conn: ConnectionManager,
+}
+
+impl RedisHealthCheck {
+    pub fn new(conn: ConnectionManager) -> Self {
+        Self { conn }
+    }
+}
+
+#[async_trait]
+impl HealthCheck for RedisHealthCheck {
+    fn name(&self) -> &'static str {
+        "redis"
+    }
+
+    // Not passed through `observe`, so probes stay out of the operation metrics.
+    async fn ping(&self) -> Result<(), AppError> {
+        let mut conn = self.conn.clone();
+        redis::cmd("PING").query_async::<String>(&mut conn).await?;
+        Ok(())
+    }
+}
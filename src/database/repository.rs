@@ -0,0 +1,196 @@
+use crate::errors::AppError;
+use crate::models::api_key::ApiKey;
+use crate::models::book::Book;
+use crate::models::user::{PatronCategory, Suspension, User};
+use async_trait::async_trait;
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::DateTime;
+
+/// Storage for user accounts. A patron's loans live on the account itself.
+#[async_trait]
+pub trait UserRepository: Send + Sync {
+    async fn find_by_email(&self, email: &str) -> Result<Option<User>, AppError>;
+
+    async fn find_by_id(&self, id: &ObjectId) -> Result<Option<User>, AppError>;
+
+    async fn find_by_oidc_subject(&self, subject: &str) -> Result<Option<User>, AppError>;
+
+    async fn set_oidc_subject(&self, id: &ObjectId, subject: &str) -> Result<(), AppError>;
+
+    async fn find_by_ldap_dn(&self, dn: &str) -> Result<Option<User>, AppError>;
+
+    /// Enabled accounts linked to a directory entry, checked by the directory sync.
+    async fn find_ldap_users(&self) -> Result<Vec<User>, AppError>;
+
+    /// Links an account to its directory entry and applies the attributes and role
+    /// mapped from it. The directory is authoritative, so the address counts as verified.
+    async fn sync_ldap_account(
+        &self,
+        id: &ObjectId,
+        dn: &str,
+        username: &str,
+        email: &str,
+        is_admin: bool,
+    ) -> Result<(), AppError>;
+
+    /// Disables an account and invalidates its outstanding tokens.
+    async fn disable(&self, id: &ObjectId) -> Result<(), AppError>;
+
+    /// Re-enables an account, used when a directory entry the sync disabled comes back.
+    async fn enable(&self, id: &ObjectId) -> Result<(), AppError>;
+
+    /// Strips everything that identifies the patron, disables the account and
+    /// invalidates its tokens. The id, category and membership dates stay behind for
+    /// circulation statistics.
+    async fn anonymize(&self, id: &ObjectId) -> Result<(), AppError>;
+
+    async fn suspend(&self, id: &ObjectId, suspension: &Suspension) -> Result<(), AppError>;
+
+    async fn reactivate(&self, id: &ObjectId) -> Result<(), AppError>;
+
+    /// Marks an account as deleted and invalidates its outstanding tokens. The
+    /// account is kept until the purge job removes it.
+    async fn soft_delete(&self, id: &ObjectId) -> Result<(), AppError>;
+
+    async fn restore(&self, id: &ObjectId) -> Result<(), AppError>;
+
+    /// Hard-deletes accounts soft-deleted before `cutoff` and returns their ids.
+    async fn purge_deleted(&self, cutoff: DateTime) -> Result<Vec<ObjectId>, AppError>;
+
+    async fn set_category(&self, id: &ObjectId, category: PatronCategory) -> Result<(), AppError>;
+
+    /// Sets a new membership expiry; `None` makes the membership open-ended.
+    async fn set_membership_expiry(
+        &self,
+        id: &ObjectId,
+        expires_at: Option<DateTime>,
+    ) -> Result<(), AppError>;
+
+    /// Adds `days` to every expiring membership in a category, counting lapsed ones
+    /// from now. Returns the number of members updated.
+    async fn extend_memberships_by_days(
+        &self,
+        category: PatronCategory,
+        days: i64,
+    ) -> Result<u64, AppError>;
+
+    /// Moves every expiring membership in a category that ends before `until` to
+    /// `until`. Returns the number of members updated.
+    async fn extend_memberships_until(
+        &self,
+        category: PatronCategory,
+        until: DateTime,
+    ) -> Result<u64, AppError>;
+
+    /// Dependents of a guardian that have not been deleted.
+    async fn find_dependents(&self, guardian_id: &ObjectId) -> Result<Vec<User>, AppError>;
+
+    /// Links a dependent to a guardian. Unlinking also drops the per-dependent loan limit.
+    async fn set_guardian(
+        &self,
+        id: &ObjectId,
+        guardian_id: Option<&ObjectId>,
+    ) -> Result<(), AppError>;
+
+    async fn set_loan_limit(&self, id: &ObjectId, limit: Option<u32>) -> Result<(), AppError>;
+
+    async fn set_family_loan_cap(&self, id: &ObjectId, cap: Option<u32>) -> Result<(), AppError>;
+
+    async fn find_all(&self) -> Result<Vec<User>, AppError>;
+
+    async fn set_admin(&self, id: &ObjectId, is_admin: bool) -> Result<(), AppError>;
+
+    async fn create(&self, user: &User) -> Result<(), AppError>;
+
+    /// Replaces the email address directly; the new address starts out unverified.
+    async fn update_email(&self, id: &ObjectId, new_email: &str) -> Result<(), AppError>;
+
+    async fn set_pending_email(&self, id: &ObjectId, email: &str) -> Result<(), AppError>;
+
+    /// Switches the user to a confirmed address and clears any pending change.
+    async fn confirm_email(&self, id: &ObjectId, email: &str) -> Result<(), AppError>;
+
+    async fn mark_email_verified(&self, id: &ObjectId) -> Result<(), AppError>;
+
+    async fn update_username(&self, id: &ObjectId, new_username: &str) -> Result<(), AppError>;
+
+    async fn update_password(&self, id: &ObjectId, password_hash: &str) -> Result<(), AppError>;
+
+    /// Stores a newly chosen password along with the updated reuse history.
+    async fn change_password(
+        &self,
+        id: &ObjectId,
+        password_hash: &str,
+        password_history: &[String],
+    ) -> Result<(), AppError>;
+
+    async fn update_token_version(&self, id: &ObjectId, token_version: i32)
+        -> Result<(), AppError>;
+
+    /// Records a loan unless the book is already borrowed by the user or the user
+    /// has reached `limit` loans.
+    async fn add_borrowed_book(
+        &self,
+        user_id: &ObjectId,
+        book_id: &ObjectId,
+        limit: usize,
+    ) -> Result<(), AppError>;
+
+    async fn remove_borrowed_book(
+        &self,
+        user_id: &ObjectId,
+        book_id: &ObjectId,
+    ) -> Result<(), AppError>;
+
+    /// Loans currently held across all accounts.
+    async fn count_active_loans(&self) -> Result<u64, AppError>;
+}
+
+/// Storage for the catalogue and the number of copies on the shelf.
+#[async_trait]
+pub trait BookRepository: Send + Sync {
+    /// Creates a book with no stock; title and author together must be unique.
+    async fn create(&self, title: &str, author: &str) -> Result<Book, AppError>;
+
+    async fn find_by_id(&self, id: &ObjectId) -> Result<Option<Book>, AppError>;
+
+    async fn find_all(&self) -> Result<Vec<Book>, AppError>;
+
+    async fn find_by_title(&self, title: &str) -> Result<Vec<Book>, AppError>;
+
+    async fn find_by_author(&self, author: &str) -> Result<Vec<Book>, AppError>;
+
+    async fn delete_by_id(&self, id: &ObjectId) -> Result<(), AppError>;
+
+    async fn update_title(&self, id: &ObjectId, title: &str) -> Result<(), AppError>;
+
+    async fn update_author(&self, id: &ObjectId, author: &str) -> Result<(), AppError>;
+
+    async fn update_stock(&self, id: &ObjectId, stock: i32) -> Result<(), AppError>;
+
+    /// Takes a copy off the shelf. The stock check and decrement are one atomic
+    /// step, so concurrent borrows never drive the stock below zero.
+    async fn borrow_book(&self, id: &ObjectId) -> Result<(), AppError>;
+
+    async fn return_book(&self, id: &ObjectId) -> Result<(), AppError>;
+
+    /// Titles with no copy left on the shelf.
+    async fn count_out_of_stock(&self) -> Result<u64, AppError>;
+}
+
+#[async_trait]
+pub trait ApiKeyRepository: Send + Sync {
+    async fn create(&self, api_key: &ApiKey) -> Result<(), AppError>;
+
+    async fn find_by_id(&self, id: &ObjectId) -> Result<Option<ApiKey>, AppError>;
+
+    async fn find_by_hash(&self, key_hash: &str) -> Result<Option<ApiKey>, AppError>;
+
+    async fn find_by_user(&self, user_id: &ObjectId) -> Result<Vec<ApiKey>, AppError>;
+
+    async fn delete_by_id(&self, id: &ObjectId) -> Result<(), AppError>;
+
+    async fn delete_by_user(&self, user_id: &ObjectId) -> Result<(), AppError>;
+
+    async fn touch_last_used(&self, id: &ObjectId) -> Result<(), AppError>;
+}
@@ -0,0 +1,88 @@
+use crate::errors::AppError;
+use async_trait::async_trait;
+
+/// Session tokens revoked before they expire.
+#[async_trait]
+pub trait TokenBlacklist: Send + Sync {
+    async fn add_token(&self, token: &str, exp_seconds: i64) -> Result<(), AppError>;
+
+    async fn is_blacklisted(&self, token: &str) -> Result<bool, AppError>;
+}
+
+#[async_trait]
+pub trait PasswordResetStore: Send + Sync {
+    /// Stores a hashed reset token for the user, replacing any token issued before.
+    async fn store(
+        &self,
+        token_hash: &str,
+        user_id: &str,
+        ttl_seconds: i64,
+    ) -> Result<(), AppError>;
+
+    /// Returns the user id a live reset token belongs to without using it up.
+    async fn peek(&self, token_hash: &str) -> Result<Option<String>, AppError>;
+
+    /// Atomically takes a reset token out of the store and returns the user id it belongs to.
+    async fn consume(&self, token_hash: &str) -> Result<Option<String>, AppError>;
+}
+
+#[async_trait]
+pub trait EmailVerificationStore: Send + Sync {
+    /// Stores a hashed verification token for the address the user wants to confirm.
+    async fn store(
+        &self,
+        token_hash: &str,
+        user_id: &str,
+        email: &str,
+        ttl_seconds: i64,
+    ) -> Result<(), AppError>;
+
+    /// Atomically takes a verification token out of the store and returns the
+    /// user id and email address it confirms.
+    async fn consume(&self, token_hash: &str) -> Result<Option<(String, String)>, AppError>;
+
+    /// Returns false if a verification email was already sent to the user within the cooldown.
+    async fn try_start_cooldown(
+        &self,
+        user_id: &str,
+        cooldown_seconds: i64,
+    ) -> Result<bool, AppError>;
+}
+
+#[async_trait]
+pub trait OidcStateStore: Send + Sync {
+    /// Remembers the PKCE verifier and nonce of a login until the provider redirects back.
+    async fn store(
+        &self,
+        state: &str,
+        pkce_verifier: &str,
+        nonce: &str,
+        ttl_seconds: i64,
+    ) -> Result<(), AppError>;
+
+    /// Atomically takes a login state out of the store and returns its PKCE verifier and nonce.
+    async fn consume(&self, state: &str) -> Result<Option<(String, String)>, AppError>;
+}
+
+/// Outcome of counting one request against a rate limit.
+#[derive(Clone, Copy, Debug)]
+pub struct RateLimitDecision {
+    pub allowed: bool,
+    /// Requests still allowed in the current window
+    pub remaining: u32,
+    /// Milliseconds until the oldest counted request leaves the window
+    pub reset_after_ms: u64,
+}
+
+/// Requests per client over a sliding window, shared by every instance.
+#[async_trait]
+pub trait RateLimitStore: Send + Sync {
+    /// Counts a request for `key` unless `limit` were already counted in the last
+    /// `window_ms`. Rejected requests are not counted.
+    async fn hit(
+        &self,
+        key: &str,
+        limit: u32,
+        window_ms: u64,
+    ) -> Result<RateLimitDecision, AppError>;
+}
@@ -16,10 +16,15 @@ pub enum AppError {
     NotFound(String),
     #[error("Conflict: {0}")]
     Conflict(String),
+    #[error("TooManyRequests: {0}")]
+    TooManyRequests(String),
     #[error("Database error: {0}")]
     Database(#[from] mongodb::error::Error),
     #[error("Redis error: {0}")]
     Redis(#[from] redis::RedisError),
+    #[cfg(any(feature = "postgres", feature = "sqlite"))]
+    #[error("SQL error: {0}")]
+    Sql(#[from] sqlx::Error),
     #[error("Internal server error")]
     Internal,
 }
@@ -42,6 +47,7 @@ impl ResponseError for AppError {
             AppError::Forbidden(msg) => json_error(actix_web::http::StatusCode::FORBIDDEN, msg.into()),
             AppError::NotFound(msg) => json_error(actix_web::http::StatusCode::NOT_FOUND, msg.into()),
             AppError::Conflict(msg) => json_error(actix_web::http::StatusCode::CONFLICT, msg.into()),
+            AppError::TooManyRequests(msg) => json_error(actix_web::http::StatusCode::TOO_MANY_REQUESTS, msg.into()),
             AppError::Database(e) => {
                 tracing::error!("Database error: {:?}", e);
                 json_error(actix_web::http::StatusCode::INTERNAL_SERVER_ERROR, INTERNAL_SERVER_ERROR.into())
@@ -50,6 +56,11 @@ impl ResponseError for AppError {
                 tracing::error!("Redis error: {:?}", e);
                 json_error(actix_web::http::StatusCode::INTERNAL_SERVER_ERROR, INTERNAL_SERVER_ERROR.into())
             }
+            #[cfg(any(feature = "postgres", feature = "sqlite"))]
+            AppError::Sql(e) => {
+                tracing::error!("SQL error: {:?}", e);
+                json_error(actix_web::http::StatusCode::INTERNAL_SERVER_ERROR, INTERNAL_SERVER_ERROR.into())
+            }
             AppError::Internal => {
                 tracing::error!("Internal server error");
                 json_error(actix_web::http::StatusCode::INTERNAL_SERVER_ERROR, INTERNAL_SERVER_ERROR.into())
@@ -1,34 +1,58 @@
-use crate::auth::AdminUser;
+use crate::auth::{ensure_login_allowed, AdminUser};
+use crate::config::app_config::AppConfig;
 use crate::constants::*;
-use crate::database::mongodb::{BookRepository, UserRepository};
+use crate::database::repository::{ApiKeyRepository, BookRepository, UserRepository};
+use crate::database::store::EmailVerificationStore;
 use crate::errors::AppError;
+use crate::handlers::api_key::{api_key_info, issue_api_key};
+use crate::handlers::user::{active_suspension_info, membership_info, release_dependents};
+use crate::handlers::user_bulk::{export_users, import_users};
+use crate::handlers::verification::send_verification_email;
 use crate::models::request::{
-    CreateBookRequest, CreateUserRequest, SetRoleRequest, UpdateBookRequest, UpdateUserRequest,
+    CreateApiKeyRequest, CreateBookRequest, CreateUserRequest, DeleteUserQuery,
+    ExtendMembershipsRequest, LinkGuardianRequest, RenewMembershipRequest, SetRoleRequest,
+    SuspendUserRequest, UpdateBookRequest, UpdateUserRequest,
 };
-use crate::models::response::{Response, UserInfo};
-use crate::models::user::User;
+use crate::models::response::{ApiKeyInfo, MembershipExtension, Response, Token, UserInfo};
+use crate::models::user::{Suspension, User};
+use crate::utils::mailer::Mailer;
+use crate::utils::membership::{new_membership_expiry, renewed_membership_expiry};
+use crate::utils::metrics::METRICS;
 use crate::utils::password::hash_password;
-use actix_web::web::{Data, Json, Path};
+use crate::utils::password_policy::{next_password_history, validate_new_password};
+use crate::utils::token::generate_impersonation_token;
+use actix_web::web::{Data, Json, Path, PayloadConfig, Query};
 use actix_web::{delete, get, post, put, HttpResponse, Scope};
 use mongodb::bson::oid::ObjectId;
+use mongodb::bson::DateTime;
 use validator::Validate;
 
+pub(crate) fn user_info(user: User) -> UserInfo {
+    UserInfo {
+        id: user.id.to_hex(),
+        suspension: active_suspension_info(&user),
+        membership: membership_info(&user),
+        category: user.category,
+        guardian_id: user.guardian_id.map(|id| id.to_hex()),
+        deleted_at: user
+            .deleted_at
+            .map(|dt| dt.try_to_rfc3339_string().unwrap_or_default()),
+        email: user.email,
+        email_verified: user.email_verified,
+        username: user.username,
+        is_admin: user.is_admin,
+        disabled: user.disabled,
+    }
+}
+
 #[get("/users")]
 async fn get_all_users(
     _admin: AdminUser,
-    user_repo: Data<UserRepository>,
+    user_repo: Data<dyn UserRepository>,
 ) -> Result<HttpResponse, AppError> {
     let users = user_repo.find_all().await?;
 
-    let user_infos: Vec<UserInfo> = users
-        .into_iter()
-        .map(|u| UserInfo {
-            id: u.id.to_hex(),
-            email: u.email,
-            username: u.username,
-            is_admin: u.is_admin,
-        })
-        .collect();
+    let user_infos: Vec<UserInfo> = users.into_iter().map(user_info).collect();
 
     Ok(HttpResponse::Ok().json(Response {
         msg: USER_INFOS_FETCHED.into(),
@@ -39,7 +63,10 @@ async fn get_all_users(
 #[post("/users")]
 async fn create_user(
     _admin: AdminUser,
-    user_repo: Data<UserRepository>,
+    user_repo: Data<dyn UserRepository>,
+    verification_store: Data<dyn EmailVerificationStore>,
+    mailer: Data<Mailer>,
+    cfg: Data<AppConfig>,
     payload: Json<CreateUserRequest>,
 ) -> Result<HttpResponse, AppError> {
     payload
@@ -50,13 +77,37 @@ async fn create_user(
         return Err(AppError::Conflict(EMAIL_ALREADY_EXISTS.into()));
     }
 
-    let password_hash = hash_password(&payload.password)?;
+    validate_new_password(
+        &cfg,
+        &payload.password,
+        &payload.email,
+        &payload.username,
+        None,
+    )
+    .await?;
+
+    let password_hash = hash_password(&cfg, &payload.password).await?;
 
     let user = User {
         id: ObjectId::new(),
         email: payload.email.clone(),
         username: payload.username.clone(),
         password_hash,
+        password_history: Vec::new(),
+        password_changed_at: Some(DateTime::now()),
+        email_verified: false,
+        pending_email: None,
+        oidc_subject: None,
+        ldap_dn: None,
+        disabled: false,
+        suspension: None,
+        deleted_at: None,
+        category: payload.category.unwrap_or_default(),
+        membership_started_at: Some(DateTime::now()),
+        membership_expires_at: new_membership_expiry(&cfg),
+        guardian_id: None,
+        loan_limit: None,
+        family_loan_cap: None,
         is_admin: payload.is_admin,
         token_version: 0,
         borrowed_books: Vec::new(),
@@ -64,6 +115,15 @@ async fn create_user(
 
     user_repo.create(&user).await?;
 
+    actix_web::rt::spawn(async move {
+        let email = user.email.clone();
+        if let Err(e) =
+            send_verification_email(verification_store.get_ref(), &mailer, &cfg, &user, &email).await
+        {
+            tracing::error!("failed to send verification email: {:?}", e);
+        }
+    });
+
     Ok(HttpResponse::Created().json(Response::<()> {
         msg: USER_CREATED.into(),
         data: None,
@@ -73,7 +133,7 @@ async fn create_user(
 #[get("/users/{id}")]
 async fn get_user_by_id(
     _admin: AdminUser,
-    user_repo: Data<UserRepository>,
+    user_repo: Data<dyn UserRepository>,
     id: Path<String>,
 ) -> Result<HttpResponse, AppError> {
     let object_id = ObjectId::parse_str(id.as_str())
@@ -86,19 +146,17 @@ async fn get_user_by_id(
 
     Ok(HttpResponse::Ok().json(Response {
         msg: USER_INFO_FETCHED.into(),
-        data: Some(UserInfo {
-            id: user.id.to_hex(),
-            email: user.email,
-            username: user.username,
-            is_admin: user.is_admin,
-        }),
+        data: Some(user_info(user)),
     }))
 }
 
 #[put("/users/{id}")]
 async fn update_user(
     _admin: AdminUser,
-    user_repo: Data<UserRepository>,
+    user_repo: Data<dyn UserRepository>,
+    verification_store: Data<dyn EmailVerificationStore>,
+    mailer: Data<Mailer>,
+    cfg: Data<AppConfig>,
     id: Path<String>,
     payload: Json<UpdateUserRequest>,
 ) -> Result<HttpResponse, AppError> {
@@ -114,12 +172,21 @@ async fn update_user(
         .await?
         .ok_or_else(|| AppError::NotFound(USER_NOT_FOUND.into()))?;
 
+    // Checked before anything is written so a rejected password leaves the user untouched.
+    if let Some(ref password) = payload.password {
+        let email = payload.email.as_deref().unwrap_or(&user.email);
+        let username = payload.username.as_deref().unwrap_or(&user.username);
+        validate_new_password(&cfg, password, email, username, Some(&user)).await?;
+    }
+
+    let mut changed_email = None;
     if let Some(ref email) = payload.email {
         if email != &user.email {
             if user_repo.find_by_email(email).await?.is_some() {
                 return Err(AppError::Conflict(EMAIL_ALREADY_EXISTS.into()));
             }
             user_repo.update_email(&object_id, email).await?;
+            changed_email = Some(email.clone());
         }
     }
 
@@ -127,34 +194,94 @@ async fn update_user(
         user_repo.update_username(&object_id, username).await?;
     }
 
+    if let Some(category) = payload.category {
+        user_repo.set_category(&object_id, category).await?;
+    }
+
     if let Some(ref password) = payload.password {
-        let password_hash = hash_password(password)?;
+        let password_hash = hash_password(&cfg, password).await?;
+        let history = next_password_history(&cfg, &user);
         user_repo
-            .update_password(&object_id, &password_hash)
+            .change_password(&object_id, &password_hash, &history)
             .await?;
     }
 
+    // The new address starts out unverified, so it gets a confirmation link just like
+    // one the user set themselves.
+    if let Some(email) = changed_email {
+        let user = User {
+            username: payload.username.clone().unwrap_or(user.username),
+            ..user
+        };
+        actix_web::rt::spawn(async move {
+            if let Err(e) =
+                send_verification_email(verification_store.get_ref(), &mailer, &cfg, &user, &email)
+                    .await
+            {
+                tracing::error!("failed to send verification email: {:?}", e);
+            }
+        });
+    }
+
     Ok(HttpResponse::Ok().json(Response::<()> {
         msg: USER_UPDATED.into(),
         data: None,
     }))
 }
 
+/// Soft-deletes a user. Open loans block the deletion unless `force_return` is set,
+/// in which case the copies go back into stock first.
 #[delete("/users/{id}")]
 async fn delete_user(
-    _admin: AdminUser,
-    user_repo: Data<UserRepository>,
+    admin: AdminUser,
+    user_repo: Data<dyn UserRepository>,
+    book_repo: Data<dyn BookRepository>,
     id: Path<String>,
+    query: Query<DeleteUserQuery>,
 ) -> Result<HttpResponse, AppError> {
     let object_id = ObjectId::parse_str(id.as_str())
         .map_err(|_| AppError::BadRequest(INVALID_USER_ID.into()))?;
 
-    user_repo
+    if object_id.to_hex() == admin.user_id {
+        return Err(AppError::Forbidden(CANNOT_MODERATE_SELF.into()));
+    }
+
+    let user = user_repo
         .find_by_id(&object_id)
         .await?
         .ok_or_else(|| AppError::NotFound(USER_NOT_FOUND.into()))?;
 
-    user_repo.delete_by_id(&object_id).await?;
+    if user.deleted_at.is_some() {
+        return Err(AppError::Conflict(USER_ALREADY_DELETED.into()));
+    }
+
+    // Checked before any forced return so a rejected delete leaves the loans untouched.
+    let dependents = user_repo.find_dependents(&object_id).await?;
+    if dependents.iter().any(User::is_managed) {
+        return Err(AppError::Conflict(GUARDIAN_HAS_DEPENDENTS.into()));
+    }
+
+    if !user.borrowed_books.is_empty() {
+        if !query.force_return {
+            return Err(AppError::Conflict(USER_HAS_OPEN_LOANS.into()));
+        }
+
+        for book_id in &user.borrowed_books {
+            user_repo.remove_borrowed_book(&object_id, book_id).await?;
+            book_repo.return_book(book_id).await?;
+            METRICS.books_returned.inc();
+        }
+
+        tracing::info!(
+            admin_id = %admin.user_id,
+            user_id = %object_id,
+            "returned {} books for deleted user",
+            user.borrowed_books.len()
+        );
+    }
+
+    release_dependents(user_repo.get_ref(), &object_id).await?;
+    user_repo.soft_delete(&object_id).await?;
 
     Ok(HttpResponse::Ok().json(Response::<()> {
         msg: USER_DELETED.into(),
@@ -162,10 +289,199 @@ async fn delete_user(
     }))
 }
 
+#[post("/users/{id}/restore")]
+async fn restore_user(
+    _admin: AdminUser,
+    user_repo: Data<dyn UserRepository>,
+    cfg: Data<AppConfig>,
+    id: Path<String>,
+) -> Result<HttpResponse, AppError> {
+    let object_id = ObjectId::parse_str(id.as_str())
+        .map_err(|_| AppError::BadRequest(INVALID_USER_ID.into()))?;
+
+    let user = user_repo
+        .find_by_id(&object_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(USER_NOT_FOUND.into()))?;
+
+    let deleted_at = user
+        .deleted_at
+        .ok_or_else(|| AppError::BadRequest(USER_NOT_DELETED.into()))?;
+
+    // The purge job runs periodically, so an expired user may still be on record.
+    let retention_millis = cfg.deleted_user_retention_days * 24 * 60 * 60 * 1000;
+    if DateTime::now().timestamp_millis() - deleted_at.timestamp_millis() > retention_millis {
+        return Err(AppError::Conflict(RESTORE_WINDOW_EXPIRED.into()));
+    }
+
+    user_repo.restore(&object_id).await?;
+
+    Ok(HttpResponse::Ok().json(Response::<()> {
+        msg: USER_RESTORED.into(),
+        data: None,
+    }))
+}
+
+#[put("/users/{id}/suspension")]
+async fn suspend_user(
+    admin: AdminUser,
+    user_repo: Data<dyn UserRepository>,
+    id: Path<String>,
+    payload: Json<SuspendUserRequest>,
+) -> Result<HttpResponse, AppError> {
+    payload
+        .validate()
+        .map_err(|e| AppError::BadRequest(e.to_string()))?;
+
+    let object_id = ObjectId::parse_str(id.as_str())
+        .map_err(|_| AppError::BadRequest(INVALID_USER_ID.into()))?;
+
+    if object_id.to_hex() == admin.user_id {
+        return Err(AppError::Forbidden(CANNOT_MODERATE_SELF.into()));
+    }
+
+    let user = user_repo
+        .find_by_id(&object_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(USER_NOT_FOUND.into()))?;
+    if user.deleted_at.is_some() {
+        return Err(AppError::Conflict(USER_ALREADY_DELETED.into()));
+    }
+
+    let now = DateTime::now();
+    let suspension = Suspension {
+        reason: payload.reason.clone(),
+        suspended_at: now,
+        expires_at: payload.expires_in_days.map(|days| {
+            DateTime::from_millis(now.timestamp_millis() + days * 24 * 60 * 60 * 1000)
+        }),
+        suspended_by: ObjectId::parse_str(&admin.user_id)?,
+    };
+    user_repo.suspend(&object_id, &suspension).await?;
+
+    tracing::info!(
+        admin_id = %admin.user_id,
+        user_id = %object_id,
+        "user suspended: {}",
+        suspension.reason
+    );
+
+    Ok(HttpResponse::Ok().json(Response::<()> {
+        msg: USER_SUSPENDED.into(),
+        data: None,
+    }))
+}
+
+#[delete("/users/{id}/suspension")]
+async fn reactivate_user(
+    _admin: AdminUser,
+    user_repo: Data<dyn UserRepository>,
+    id: Path<String>,
+) -> Result<HttpResponse, AppError> {
+    let object_id = ObjectId::parse_str(id.as_str())
+        .map_err(|_| AppError::BadRequest(INVALID_USER_ID.into()))?;
+
+    let user = user_repo
+        .find_by_id(&object_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(USER_NOT_FOUND.into()))?;
+    if user.deleted_at.is_some() {
+        return Err(AppError::Conflict(USER_ALREADY_DELETED.into()));
+    }
+
+    user_repo.reactivate(&object_id).await?;
+
+    Ok(HttpResponse::Ok().json(Response::<()> {
+        msg: USER_REACTIVATED.into(),
+        data: None,
+    }))
+}
+
+#[post("/users/{id}/membership/renew")]
+async fn renew_membership(
+    _admin: AdminUser,
+    user_repo: Data<dyn UserRepository>,
+    cfg: Data<AppConfig>,
+    id: Path<String>,
+    payload: Json<RenewMembershipRequest>,
+) -> Result<HttpResponse, AppError> {
+    payload
+        .validate()
+        .map_err(|e| AppError::BadRequest(e.to_string()))?;
+
+    let object_id = ObjectId::parse_str(id.as_str())
+        .map_err(|_| AppError::BadRequest(INVALID_USER_ID.into()))?;
+
+    let mut user = user_repo
+        .find_by_id(&object_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(USER_NOT_FOUND.into()))?;
+
+    // An open-ended membership only gets an expiry when the admin asks for one
+    user.membership_expires_at = match (user.membership_expires_at, payload.days) {
+        (None, None) => None,
+        (current, days) => {
+            renewed_membership_expiry(current, days.unwrap_or(cfg.membership_duration_days))
+        }
+    };
+    user_repo
+        .set_membership_expiry(&object_id, user.membership_expires_at)
+        .await?;
+
+    Ok(HttpResponse::Ok().json(Response {
+        msg: MEMBERSHIP_RENEWED.into(),
+        data: Some(membership_info(&user)),
+    }))
+}
+
+/// Extends all expiring memberships of a category at once, e.g. for a new semester.
+/// Open-ended memberships are left alone.
+#[post("/memberships/extend")]
+async fn extend_memberships(
+    admin: AdminUser,
+    user_repo: Data<dyn UserRepository>,
+    payload: Json<ExtendMembershipsRequest>,
+) -> Result<HttpResponse, AppError> {
+    payload
+        .validate()
+        .map_err(|e| AppError::BadRequest(e.to_string()))?;
+
+    let updated = match (payload.days, &payload.until) {
+        (Some(days), None) => {
+            user_repo
+                .extend_memberships_by_days(payload.category, days)
+                .await?
+        }
+        (None, Some(until)) => {
+            let until = DateTime::parse_rfc3339_str(until)
+                .map_err(|_| AppError::BadRequest(INVALID_EXTENSION.into()))?;
+            user_repo
+                .extend_memberships_until(payload.category, until)
+                .await?
+        }
+        _ => return Err(AppError::BadRequest(INVALID_EXTENSION.into())),
+    };
+
+    tracing::info!(
+        admin_id = %admin.user_id,
+        "extended {} {} memberships",
+        updated,
+        payload.category
+    );
+
+    Ok(HttpResponse::Ok().json(Response {
+        msg: MEMBERSHIPS_EXTENDED.into(),
+        data: Some(MembershipExtension {
+            category: payload.category,
+            updated,
+        }),
+    }))
+}
+
 #[put("/users/{id}/admin")]
 async fn set_admin(
     _admin: AdminUser,
-    user_repo: Data<UserRepository>,
+    user_repo: Data<dyn UserRepository>,
     id: Path<String>,
     payload: Json<SetRoleRequest>,
 ) -> Result<HttpResponse, AppError> {
@@ -191,10 +507,195 @@ async fn set_admin(
     }))
 }
 
+/// Links an existing account to a guardian, who can then borrow and return for it.
+#[put("/users/{id}/guardian")]
+async fn link_guardian(
+    admin: AdminUser,
+    user_repo: Data<dyn UserRepository>,
+    id: Path<String>,
+    payload: Json<LinkGuardianRequest>,
+) -> Result<HttpResponse, AppError> {
+    let object_id = ObjectId::parse_str(id.as_str())
+        .map_err(|_| AppError::BadRequest(INVALID_USER_ID.into()))?;
+    let guardian_id = ObjectId::parse_str(&payload.guardian_id)
+        .map_err(|_| AppError::BadRequest(INVALID_USER_ID.into()))?;
+
+    user_repo
+        .find_by_id(&object_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(USER_NOT_FOUND.into()))?;
+
+    let guardian = user_repo
+        .find_by_id(&guardian_id)
+        .await?
+        .filter(|g| g.deleted_at.is_none())
+        .ok_or_else(|| AppError::NotFound(USER_NOT_FOUND.into()))?;
+
+    // Families are one level deep: a guardian has no guardian and a dependent has
+    // no dependents.
+    if guardian_id == object_id
+        || guardian.guardian_id.is_some()
+        || !user_repo.find_dependents(&object_id).await?.is_empty()
+    {
+        return Err(AppError::BadRequest(INVALID_GUARDIAN.into()));
+    }
+
+    user_repo.set_guardian(&object_id, Some(&guardian_id)).await?;
+
+    tracing::info!(
+        admin_id = %admin.user_id,
+        user_id = %object_id,
+        guardian_id = %guardian_id,
+        "guardian linked"
+    );
+
+    Ok(HttpResponse::Ok().json(Response::<()> {
+        msg: GUARDIAN_LINKED.into(),
+        data: None,
+    }))
+}
+
+#[delete("/users/{id}/guardian")]
+async fn unlink_guardian(
+    _admin: AdminUser,
+    user_repo: Data<dyn UserRepository>,
+    id: Path<String>,
+) -> Result<HttpResponse, AppError> {
+    let object_id = ObjectId::parse_str(id.as_str())
+        .map_err(|_| AppError::BadRequest(INVALID_USER_ID.into()))?;
+
+    let user = user_repo
+        .find_by_id(&object_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(USER_NOT_FOUND.into()))?;
+
+    if user.is_managed() {
+        return Err(AppError::Conflict(MANAGED_DEPENDENT_NEEDS_GUARDIAN.into()));
+    }
+
+    user_repo.set_guardian(&object_id, None).await?;
+
+    Ok(HttpResponse::Ok().json(Response::<()> {
+        msg: GUARDIAN_UNLINKED.into(),
+        data: None,
+    }))
+}
+
+#[post("/users/{id}/impersonate")]
+async fn impersonate_user(
+    admin: AdminUser,
+    user_repo: Data<dyn UserRepository>,
+    cfg: Data<AppConfig>,
+    id: Path<String>,
+) -> Result<HttpResponse, AppError> {
+    let object_id = ObjectId::parse_str(id.as_str())
+        .map_err(|_| AppError::BadRequest(INVALID_USER_ID.into()))?;
+
+    let user = user_repo
+        .find_by_id(&object_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(USER_NOT_FOUND.into()))?;
+
+    if user.is_admin {
+        return Err(AppError::Forbidden(CANNOT_IMPERSONATE_ADMIN.into()));
+    }
+
+    // Suspended patrons can still be impersonated, to see what they see; the token is
+    // held to the same suspension limits as their own.
+    ensure_login_allowed(&user)?;
+
+    // The user's token version is left alone so their own sessions stay valid.
+    let token = generate_impersonation_token(
+        &cfg,
+        &user.id.to_hex(),
+        user.token_version,
+        &admin.user_id,
+    )?;
+
+    tracing::info!(
+        admin_id = %admin.user_id,
+        user_id = %user.id,
+        "impersonation token issued"
+    );
+
+    Ok(HttpResponse::Ok().json(Response {
+        msg: IMPERSONATION_STARTED.into(),
+        data: Some(Token { token }),
+    }))
+}
+
+#[post("/users/{id}/api-keys")]
+async fn create_user_api_key(
+    _admin: AdminUser,
+    user_repo: Data<dyn UserRepository>,
+    key_repo: Data<dyn ApiKeyRepository>,
+    id: Path<String>,
+    payload: Json<CreateApiKeyRequest>,
+) -> Result<HttpResponse, AppError> {
+    let object_id = ObjectId::parse_str(id.as_str())
+        .map_err(|_| AppError::BadRequest(INVALID_USER_ID.into()))?;
+
+    user_repo
+        .find_by_id(&object_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(USER_NOT_FOUND.into()))?;
+
+    let info = issue_api_key(key_repo.get_ref(), object_id, &payload).await?;
+
+    Ok(HttpResponse::Created().json(Response {
+        msg: API_KEY_CREATED.into(),
+        data: Some(info),
+    }))
+}
+
+#[get("/users/{id}/api-keys")]
+async fn get_user_api_keys(
+    _admin: AdminUser,
+    key_repo: Data<dyn ApiKeyRepository>,
+    id: Path<String>,
+) -> Result<HttpResponse, AppError> {
+    let object_id = ObjectId::parse_str(id.as_str())
+        .map_err(|_| AppError::BadRequest(INVALID_USER_ID.into()))?;
+
+    let infos: Vec<ApiKeyInfo> = key_repo
+        .find_by_user(&object_id)
+        .await?
+        .into_iter()
+        .map(api_key_info)
+        .collect();
+
+    Ok(HttpResponse::Ok().json(Response {
+        msg: API_KEYS_FETCHED.into(),
+        data: Some(infos),
+    }))
+}
+
+#[delete("/api-keys/{id}")]
+async fn revoke_user_api_key(
+    _admin: AdminUser,
+    key_repo: Data<dyn ApiKeyRepository>,
+    id: Path<String>,
+) -> Result<HttpResponse, AppError> {
+    let object_id = ObjectId::parse_str(id.as_str())
+        .map_err(|_| AppError::BadRequest(INVALID_API_KEY_ID.into()))?;
+
+    key_repo
+        .find_by_id(&object_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(API_KEY_NOT_FOUND.into()))?;
+
+    key_repo.delete_by_id(&object_id).await?;
+
+    Ok(HttpResponse::Ok().json(Response::<()> {
+        msg: API_KEY_REVOKED.into(),
+        data: None,
+    }))
+}
+
 #[post("/books")]
 async fn create_book(
     _admin: AdminUser,
-    book_repo: Data<BookRepository>,
+    book_repo: Data<dyn BookRepository>,
     payload: Json<CreateBookRequest>,
 ) -> Result<HttpResponse, AppError> {
     payload
@@ -214,7 +715,7 @@ async fn create_book(
 #[put("/books/{id}")]
 async fn update_book(
     _admin: AdminUser,
-    book_repo: Data<BookRepository>,
+    book_repo: Data<dyn BookRepository>,
     id: Path<String>,
     payload: Json<UpdateBookRequest>,
 ) -> Result<HttpResponse, AppError> {
@@ -252,7 +753,7 @@ async fn update_book(
 #[delete("/books/{id}")]
 async fn delete_book(
     _admin: AdminUser,
-    book_repo: Data<BookRepository>,
+    book_repo: Data<dyn BookRepository>,
     id: Path<String>,
 ) -> Result<HttpResponse, AppError> {
     let object_id = ObjectId::parse_str(id.as_str()).map_err(|_| {
@@ -274,12 +775,27 @@ async fn delete_book(
 
 pub fn admin_scope() -> Scope {
     Scope::new("/admin")
+        .app_data(PayloadConfig::new(BULK_IMPORT_MAX_BYTES))
         .service(get_all_users)
+        // Registered before `/users/{id}` so that "export" is not taken for an id.
+        .service(export_users)
+        .service(import_users)
         .service(get_user_by_id)
         .service(create_user)
         .service(update_user)
         .service(delete_user)
+        .service(restore_user)
+        .service(suspend_user)
+        .service(reactivate_user)
+        .service(renew_membership)
+        .service(extend_memberships)
         .service(set_admin)
+        .service(link_guardian)
+        .service(unlink_guardian)
+        .service(impersonate_user)
+        .service(create_user_api_key)
+        .service(get_user_api_keys)
+        .service(revoke_user_api_key)
         .service(create_book)
         .service(update_book)
         .service(delete_book)
@@ -0,0 +1,73 @@
+use crate::constants::{
+    API_KEY_DISPLAY_PREFIX_LENGTH, API_KEY_PREFIX, API_KEY_SCOPES, INVALID_API_KEY_SCOPE,
+};
+use crate::database::repository::ApiKeyRepository;
+use crate::errors::AppError;
+use crate::models::api_key::ApiKey;
+use crate::models::request::CreateApiKeyRequest;
+use crate::models::response::ApiKeyInfo;
+use crate::utils::random_token::{generate_random_token, hash_random_token};
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::DateTime;
+use validator::Validate;
+
+/// Creates a key for `user_id`. The plain key is returned in the response only;
+/// the database keeps its hash.
+pub(crate) async fn issue_api_key(
+    key_repo: &dyn ApiKeyRepository,
+    user_id: ObjectId,
+    payload: &CreateApiKeyRequest,
+) -> Result<ApiKeyInfo, AppError> {
+    payload
+        .validate()
+        .map_err(|e| AppError::BadRequest(e.to_string()))?;
+
+    if let Some(scope) = payload
+        .scopes
+        .iter()
+        .find(|s| !API_KEY_SCOPES.contains(&s.as_str()))
+    {
+        return Err(AppError::BadRequest(format!("{}: {}", INVALID_API_KEY_SCOPE, scope)));
+    }
+
+    let mut scopes = payload.scopes.clone();
+    scopes.sort();
+    scopes.dedup();
+
+    let raw_key = format!("{}{}", API_KEY_PREFIX, generate_random_token());
+    let now = DateTime::now();
+    let api_key = ApiKey {
+        id: ObjectId::new(),
+        user_id,
+        name: payload.name.clone(),
+        key_hash: hash_random_token(&raw_key),
+        prefix: raw_key[..API_KEY_DISPLAY_PREFIX_LENGTH].to_string(),
+        scopes,
+        created_at: now,
+        expires_at: payload.expires_in_days.map(|days| {
+            DateTime::from_millis(now.timestamp_millis() + days * 24 * 3600 * 1000)
+        }),
+        last_used_at: None,
+    };
+
+    key_repo.create(&api_key).await?;
+
+    let mut info = api_key_info(api_key);
+    info.key = Some(raw_key);
+    Ok(info)
+}
+
+pub(crate) fn api_key_info(api_key: ApiKey) -> ApiKeyInfo {
+    let format = |dt: DateTime| dt.try_to_rfc3339_string().unwrap_or_default();
+
+    ApiKeyInfo {
+        id: api_key.id.to_hex(),
+        name: api_key.name,
+        prefix: api_key.prefix,
+        scopes: api_key.scopes,
+        created_at: format(api_key.created_at),
+        expires_at: api_key.expires_at.map(format),
+        last_used_at: api_key.last_used_at.map(format),
+        key: None,
+    }
+}
@@ -1,23 +1,43 @@
-use crate::auth::AuthenticatedUser;
+use crate::auth::{
+    clear_oidc_state_cookie, clear_session_cookies, ensure_login_allowed, issue_session,
+    oidc_state_matches, set_oidc_state_cookie, AuthenticatedUser, LdapDirectory, LdapIdentity,
+    LdapLogin, OidcProvider,
+};
 use crate::config::app_config::AppConfig;
 use crate::constants::*;
-use crate::database::mongodb::UserRepository;
-use crate::database::redis::TokenBlacklist;
+use crate::database::repository::UserRepository;
+use crate::database::store::{
+    EmailVerificationStore, OidcStateStore, PasswordResetStore, TokenBlacklist,
+};
 use crate::errors::AppError;
-use crate::models::request::{LoginRequest, RegisterRequest};
-use crate::models::response::{Response, Token};
-use crate::models::user::User;
-use crate::utils::password::{hash_password, verify_password};
+use crate::handlers::verification::send_verification_email;
+use crate::models::request::{
+    ForgotPasswordRequest, LoginRequest, OidcCallbackQuery, RegisterRequest,
+    ResetPasswordRequest, VerifyEmailRequest,
+};
+use crate::models::response::Response;
+use crate::models::user::{PatronCategory, User};
+use crate::utils::mailer::Mailer;
+use crate::utils::membership::new_membership_expiry;
+use crate::utils::metrics::METRICS;
+use crate::utils::password::{hash_password, needs_rehash, verify_password};
+use crate::utils::password_policy::{
+    next_password_history, password_expired, validate_new_password,
+};
+use crate::utils::random_token::{generate_random_token, hash_random_token};
 use crate::utils::token::generate_token;
-use actix_web::web::{scope, Data, Json};
-use actix_web::{post, HttpResponse};
+use actix_web::web::{scope, Data, Json, Query};
+use actix_web::{get, post, HttpRequest, HttpResponse};
 use mongodb::bson::oid::ObjectId;
+use mongodb::bson::DateTime;
 use time::OffsetDateTime;
 use validator::Validate;
 
 #[post("/register")]
 async fn register(
-    user_repo: Data<UserRepository>,
+    user_repo: Data<dyn UserRepository>,
+    verification_store: Data<dyn EmailVerificationStore>,
+    mailer: Data<Mailer>,
     cfg: Data<AppConfig>,
     payload: Json<RegisterRequest>,
 ) -> Result<HttpResponse, AppError> {
@@ -29,13 +49,37 @@ async fn register(
         return Err(AppError::Conflict(EMAIL_ALREADY_EXISTS.into()));
     }
 
-    let hash = hash_password(&payload.password)?;
+    validate_new_password(
+        &cfg,
+        &payload.password,
+        &payload.email,
+        &payload.username,
+        None,
+    )
+    .await?;
+
+    let hash = hash_password(&cfg, &payload.password).await?;
     let user_id = ObjectId::new();
     let new_user = User {
         id: user_id,
         email: payload.email.clone(),
         username: payload.username.clone(),
         password_hash: hash,
+        password_history: Vec::new(),
+        password_changed_at: Some(DateTime::now()),
+        email_verified: false,
+        pending_email: None,
+        oidc_subject: None,
+        ldap_dn: None,
+        disabled: false,
+        suspension: None,
+        deleted_at: None,
+        category: PatronCategory::default(),
+        membership_started_at: Some(DateTime::now()),
+        membership_expires_at: new_membership_expiry(&cfg),
+        guardian_id: None,
+        loan_limit: None,
+        family_loan_cap: None,
         is_admin: false,
         token_version: 0,
         borrowed_books: Vec::new(),
@@ -43,30 +87,65 @@ async fn register(
     user_repo.create(&new_user).await?;
 
     let token = generate_token(&cfg, &user_id.to_hex(), new_user.token_version)?;
-    Ok(HttpResponse::Ok().json(Response {
+    let mut response = HttpResponse::Ok();
+    let token = issue_session(&mut response, &cfg, token);
+
+    actix_web::rt::spawn(async move {
+        let email = new_user.email.clone();
+        if let Err(e) = send_verification_email(
+            verification_store.get_ref(),
+            &mailer,
+            &cfg,
+            &new_user,
+            &email,
+        )
+        .await
+        {
+            tracing::error!("failed to send verification email: {:?}", e);
+        }
+    });
+
+    Ok(response.json(Response {
         msg: REGISTER_SUCCESS.into(),
-        data: Some(Token { token }),
+        data: token,
     }))
 }
 
 #[post("/login")]
 async fn login(
-    user_repo: Data<UserRepository>,
+    user_repo: Data<dyn UserRepository>,
     cfg: Data<AppConfig>,
+    ldap: Option<Data<LdapDirectory>>,
     payload: Json<LoginRequest>,
 ) -> Result<HttpResponse, AppError> {
     payload
         .validate()
         .map_err(|e| AppError::BadRequest(e.to_string()))?;
 
-    let user = user_repo
-        .find_by_email(&payload.email)
-        .await?
-        .ok_or(AppError::Unauthorized(INVALID_CREDENTIALS.into()))?;
+    let ldap_login = match ldap {
+        Some(ref directory) => {
+            directory
+                .authenticate(&payload.email, &payload.password)
+                .await
+        }
+        None => LdapLogin::UnknownUser,
+    };
 
-    verify_password(&user.password_hash, &payload.password)?;
+    let account = match ldap_login {
+        LdapLogin::Authenticated(identity) => ldap_account(user_repo.get_ref(), &cfg, identity)
+            .await
+            .and_then(|user| ensure_login_allowed(&user).map(|_| user)),
+        LdapLogin::Rejected => Err(AppError::Unauthorized(INVALID_CREDENTIALS.into())),
+        LdapLogin::UnknownUser | LdapLogin::Unavailable => {
+            local_account(user_repo.get_ref(), &cfg, &payload).await
+        }
+    };
+    if let Err(AppError::Unauthorized(_) | AppError::Forbidden(_)) = account {
+        METRICS.failed_logins.inc();
+    }
+    let user = account?;
 
-    let user_id = user.id.clone();
+    let user_id = user.id;
     let new_token_version = user.token_version + 1;
     user_repo
         .update_token_version(&user_id, new_token_version)
@@ -74,16 +153,141 @@ async fn login(
 
     let id = user_id.to_hex();
     let token = generate_token(&cfg, &id, new_token_version)?;
-    Ok(HttpResponse::Ok().json(Response {
+    let mut response = HttpResponse::Ok();
+    let token = issue_session(&mut response, &cfg, token);
+    Ok(response.json(Response {
         msg: LOGIN_SUCCESS.into(),
-        data: Some(Token { token }),
+        data: token,
     }))
 }
 
+/// Finds or creates the account for an authenticated directory entry. An existing
+/// plain patron account with the same email is linked on first login.
+async fn ldap_account(
+    user_repo: &dyn UserRepository,
+    cfg: &AppConfig,
+    identity: LdapIdentity,
+) -> Result<User, AppError> {
+    let existing = match user_repo.find_by_ldap_dn(&identity.dn).await? {
+        // Linked accounts are only ever disabled by the directory sync, so the
+        // entry being back means the account is too.
+        Some(mut user) if user.disabled => {
+            user_repo.enable(&user.id).await?;
+            user.disabled = false;
+            Some(user)
+        }
+        Some(user) => Some(user),
+        None => match user_repo.find_by_email(&identity.email).await? {
+            // Administrators and accounts linked elsewhere keep the sign-in they
+            // were set up with.
+            Some(user)
+                if user.oidc_subject.is_some() || user.is_admin || user.ldap_dn.is_some() =>
+            {
+                tracing::warn!(
+                    "Not linking directory entry {} to account {} by email",
+                    identity.dn,
+                    user.id
+                );
+                return Err(AppError::Unauthorized(LDAP_ACCOUNT_NOT_LINKABLE.into()));
+            }
+            user => user,
+        },
+    };
+    let username = derive_username(identity.username.as_deref(), &identity.email);
+
+    match existing {
+        Some(mut user) => {
+            user_repo
+                .sync_ldap_account(
+                    &user.id,
+                    &identity.dn,
+                    &username,
+                    &identity.email,
+                    identity.is_admin,
+                )
+                .await?;
+            user.username = username;
+            user.email = identity.email;
+            user.is_admin = identity.is_admin;
+            Ok(user)
+        }
+        None => {
+            let user = User {
+                id: ObjectId::new(),
+                email: identity.email,
+                username,
+                // Directory accounts never authenticate against a local password.
+                password_hash: String::new(),
+                password_history: Vec::new(),
+                password_changed_at: None,
+                email_verified: true,
+                pending_email: None,
+                oidc_subject: None,
+                ldap_dn: Some(identity.dn),
+                disabled: false,
+                suspension: None,
+                deleted_at: None,
+                category: PatronCategory::default(),
+                membership_started_at: Some(DateTime::now()),
+                membership_expires_at: new_membership_expiry(cfg),
+                guardian_id: None,
+                loan_limit: None,
+                family_loan_cap: None,
+                is_admin: identity.is_admin,
+                token_version: 0,
+                borrowed_books: Vec::new(),
+            };
+            user_repo.create(&user).await?;
+            Ok(user)
+        }
+    }
+}
+
+/// Authenticates against the locally stored password hash.
+async fn local_account(
+    user_repo: &dyn UserRepository,
+    cfg: &AppConfig,
+    payload: &LoginRequest,
+) -> Result<User, AppError> {
+    let user = user_repo
+        .find_by_email(&payload.email)
+        .await?
+        .ok_or(AppError::Unauthorized(INVALID_CREDENTIALS.into()))?;
+
+    // Directory accounts only log in through the directory, even while it is down.
+    if user.ldap_dn.is_some() || user.deleted_at.is_some() {
+        return Err(AppError::Unauthorized(INVALID_CREDENTIALS.into()));
+    }
+
+    verify_password(&user.password_hash, &payload.password).await?;
+
+    ensure_login_allowed(&user)?;
+
+    if password_expired(cfg, &user) {
+        return Err(AppError::Forbidden(PASSWORD_EXPIRED.into()));
+    }
+
+    // Upgrades legacy bcrypt hashes and hashes made with outdated parameters,
+    // which is only possible while the plain password is at hand.
+    if needs_rehash(cfg, &user.password_hash) {
+        match hash_password(cfg, &payload.password).await {
+            Ok(new_hash) => {
+                if let Err(e) = user_repo.update_password(&user.id, &new_hash).await {
+                    tracing::error!("failed to store upgraded password hash: {:?}", e);
+                }
+            }
+            Err(e) => tracing::error!("failed to upgrade password hash: {:?}", e),
+        }
+    }
+
+    Ok(user)
+}
+
 #[post("/logout")]
 async fn logout(
     user: AuthenticatedUser,
-    blacklist: Data<TokenBlacklist>,
+    blacklist: Data<dyn TokenBlacklist>,
+    cfg: Data<AppConfig>,
 ) -> Result<HttpResponse, AppError> {
     let token = &user.token;
     let now = OffsetDateTime::now_utc().unix_timestamp();
@@ -93,15 +297,341 @@ async fn logout(
         blacklist.add_token(token, exp_seconds).await?;
     }
 
-    Ok(HttpResponse::Ok().json(Response::<()> {
+    let mut response = HttpResponse::Ok();
+    clear_session_cookies(&mut response, &cfg);
+    Ok(response.json(Response::<()> {
         msg: LOGOUT_SUCCESS.into(),
         data: None,
     }))
 }
 
+#[post("/forgot-password")]
+async fn forgot_password(
+    user_repo: Data<dyn UserRepository>,
+    reset_store: Data<dyn PasswordResetStore>,
+    mailer: Data<Mailer>,
+    cfg: Data<AppConfig>,
+    payload: Json<ForgotPasswordRequest>,
+) -> Result<HttpResponse, AppError> {
+    payload
+        .validate()
+        .map_err(|e| AppError::BadRequest(e.to_string()))?;
+
+    // The lookup and delivery run in the background so that neither the response
+    // body nor its timing reveals whether the email is registered.
+    let email = payload.into_inner().email;
+    actix_web::rt::spawn(async move {
+        if let Err(e) = send_password_reset(
+            user_repo.get_ref(),
+            reset_store.get_ref(),
+            &mailer,
+            &cfg,
+            &email,
+        )
+        .await
+        {
+            tracing::error!("failed to send password reset email: {:?}", e);
+        }
+    });
+
+    Ok(HttpResponse::Ok().json(Response::<()> {
+        msg: PASSWORD_RESET_REQUESTED.into(),
+        data: None,
+    }))
+}
+
+async fn send_password_reset(
+    user_repo: &dyn UserRepository,
+    reset_store: &dyn PasswordResetStore,
+    mailer: &Mailer,
+    cfg: &AppConfig,
+    email: &str,
+) -> Result<(), AppError> {
+    let user = match user_repo.find_by_email(email).await? {
+        Some(user) => user,
+        None => return Ok(()),
+    };
+
+    let token = generate_random_token();
+    reset_store
+        .store(
+            &hash_random_token(&token),
+            &user.id.to_hex(),
+            cfg.password_reset_ttl_minutes * 60,
+        )
+        .await?;
+
+    let link = match cfg.public_url {
+        Some(ref url) => format!("{}/reset-password?token={}", url, token),
+        None => format!("Reset token: {}", token),
+    };
+    let body = format!(
+        "Hello {},\n\n\
+         Someone asked to reset the password of your library account.\n\
+         Use the following to choose a new password within {} minutes:\n\n\
+         {}\n\n\
+         If this was not you, you can ignore this email.\n",
+        user.username, cfg.password_reset_ttl_minutes, link
+    );
+
+    mailer.send(&user.email, PASSWORD_RESET_EMAIL_SUBJECT, body).await
+}
+
+#[post("/reset-password")]
+async fn reset_password(
+    user_repo: Data<dyn UserRepository>,
+    reset_store: Data<dyn PasswordResetStore>,
+    cfg: Data<AppConfig>,
+    payload: Json<ResetPasswordRequest>,
+) -> Result<HttpResponse, AppError> {
+    payload
+        .validate()
+        .map_err(|e| AppError::BadRequest(e.to_string()))?;
+
+    // The token is only used up once the new password passes the policy, so a
+    // rejected password can be retried with the same link.
+    let token_hash = hash_random_token(&payload.token);
+    let user_id = reset_store
+        .peek(&token_hash)
+        .await?
+        .ok_or_else(|| AppError::BadRequest(INVALID_RESET_TOKEN.into()))?;
+
+    let object_id = ObjectId::parse_str(&user_id)
+        .map_err(|_| AppError::BadRequest(INVALID_RESET_TOKEN.into()))?;
+
+    let user = user_repo
+        .find_by_id(&object_id)
+        .await?
+        .ok_or_else(|| AppError::BadRequest(INVALID_RESET_TOKEN.into()))?;
+
+    validate_new_password(
+        &cfg,
+        &payload.new_password,
+        &user.email,
+        &user.username,
+        Some(&user),
+    )
+    .await?;
+
+    // Another request may have used the token while the password was being checked.
+    if reset_store.consume(&token_hash).await?.as_deref() != Some(user_id.as_str()) {
+        return Err(AppError::BadRequest(INVALID_RESET_TOKEN.into()));
+    }
+
+    let new_hash = hash_password(&cfg, &payload.new_password).await?;
+    let history = next_password_history(&cfg, &user);
+    user_repo
+        .change_password(&object_id, &new_hash, &history)
+        .await?;
+
+    // Invalidates every token issued before the reset.
+    user_repo
+        .update_token_version(&object_id, user.token_version + 1)
+        .await?;
+
+    Ok(HttpResponse::Ok().json(Response::<()> {
+        msg: PASSWORD_RESET_SUCCESS.into(),
+        data: None,
+    }))
+}
+
+#[post("/verify-email")]
+async fn verify_email(
+    user_repo: Data<dyn UserRepository>,
+    verification_store: Data<dyn EmailVerificationStore>,
+    payload: Json<VerifyEmailRequest>,
+) -> Result<HttpResponse, AppError> {
+    payload
+        .validate()
+        .map_err(|e| AppError::BadRequest(e.to_string()))?;
+
+    let (user_id, email) = verification_store
+        .consume(&hash_random_token(&payload.token))
+        .await?
+        .ok_or_else(|| AppError::BadRequest(INVALID_VERIFICATION_TOKEN.into()))?;
+
+    let object_id = ObjectId::parse_str(&user_id)
+        .map_err(|_| AppError::BadRequest(INVALID_VERIFICATION_TOKEN.into()))?;
+
+    let user = user_repo
+        .find_by_id(&object_id)
+        .await?
+        .ok_or_else(|| AppError::BadRequest(INVALID_VERIFICATION_TOKEN.into()))?;
+
+    if email == user.email {
+        user_repo.mark_email_verified(&object_id).await?;
+    } else if user.pending_email.as_deref() == Some(email.as_str()) {
+        if user_repo.find_by_email(&email).await?.is_some() {
+            return Err(AppError::Conflict(EMAIL_ALREADY_EXISTS.into()));
+        }
+        user_repo.confirm_email(&object_id, &email).await?;
+    } else {
+        // The token belongs to an address the user has since moved away from.
+        return Err(AppError::BadRequest(INVALID_VERIFICATION_TOKEN.into()));
+    }
+
+    Ok(HttpResponse::Ok().json(Response::<()> {
+        msg: EMAIL_VERIFIED.into(),
+        data: None,
+    }))
+}
+
+#[get("/oidc/login")]
+async fn oidc_login(
+    provider: Option<Data<OidcProvider>>,
+    state_store: Data<dyn OidcStateStore>,
+    cfg: Data<AppConfig>,
+) -> Result<HttpResponse, AppError> {
+    let provider = provider.ok_or_else(|| AppError::NotFound(OIDC_NOT_CONFIGURED.into()))?;
+    let start = provider.begin_login().await?;
+
+    state_store
+        .store(
+            &start.state,
+            &start.pkce_verifier,
+            &start.nonce,
+            OIDC_STATE_TTL_SECONDS,
+        )
+        .await?;
+
+    let mut response = HttpResponse::Found();
+    set_oidc_state_cookie(&mut response, &cfg, &start.state);
+    Ok(response
+        .insert_header(("Location", start.authorize_url))
+        .finish())
+}
+
+#[get("/oidc/callback")]
+async fn oidc_callback(
+    provider: Option<Data<OidcProvider>>,
+    state_store: Data<dyn OidcStateStore>,
+    user_repo: Data<dyn UserRepository>,
+    cfg: Data<AppConfig>,
+    req: HttpRequest,
+    query: Query<OidcCallbackQuery>,
+) -> Result<HttpResponse, AppError> {
+    let provider = provider.ok_or_else(|| AppError::NotFound(OIDC_NOT_CONFIGURED.into()))?;
+
+    if let Some(ref error) = query.error {
+        tracing::warn!("OIDC provider returned error: {}", error);
+        return Err(AppError::Unauthorized(OIDC_LOGIN_FAILED.into()));
+    }
+
+    let (code, state) = match (&query.code, &query.state) {
+        (Some(code), Some(state)) => (code, state),
+        _ => return Err(AppError::BadRequest(OIDC_INVALID_STATE.into())),
+    };
+
+    if !oidc_state_matches(&req, state) {
+        return Err(AppError::BadRequest(OIDC_INVALID_STATE.into()));
+    }
+
+    let (pkce_verifier, nonce) = state_store
+        .consume(state)
+        .await?
+        .ok_or_else(|| AppError::BadRequest(OIDC_INVALID_STATE.into()))?;
+
+    let identity = provider.complete_login(code, &pkce_verifier, &nonce).await?;
+
+    let user = match user_repo.find_by_oidc_subject(&identity.subject).await? {
+        Some(user) => user,
+        None => match user_repo.find_by_email(&identity.email).await? {
+            // Only plain patron accounts are linked by email. Administrators and
+            // directory accounts keep the sign-in they were set up with.
+            Some(user)
+                if user.oidc_subject.is_some() || user.is_admin || user.ldap_dn.is_some() =>
+            {
+                tracing::warn!(
+                    "Not linking identity {} to account {} by email",
+                    identity.subject,
+                    user.id
+                );
+                return Err(AppError::Unauthorized(OIDC_ACCOUNT_NOT_LINKABLE.into()));
+            }
+            Some(user) => {
+                user_repo.set_oidc_subject(&user.id, &identity.subject).await?;
+                if !user.email_verified {
+                    user_repo.mark_email_verified(&user.id).await?;
+                }
+                user
+            }
+            None if cfg.oidc.as_ref().is_some_and(|oidc| oidc.auto_provision) => {
+                let user = User {
+                    id: ObjectId::new(),
+                    username: derive_username(
+                        identity.preferred_username.as_deref(),
+                        &identity.email,
+                    ),
+                    email: identity.email,
+                    // Not a valid hash, so the account cannot log in with a password
+                    // until one is set through the reset flow.
+                    password_hash: String::new(),
+                    password_history: Vec::new(),
+                    password_changed_at: None,
+                    email_verified: true,
+                    pending_email: None,
+                    oidc_subject: Some(identity.subject),
+                    ldap_dn: None,
+                    disabled: false,
+                    suspension: None,
+                    deleted_at: None,
+                    category: PatronCategory::default(),
+                    membership_started_at: Some(DateTime::now()),
+                    membership_expires_at: new_membership_expiry(&cfg),
+                    guardian_id: None,
+                    loan_limit: None,
+                    family_loan_cap: None,
+                    is_admin: false,
+                    token_version: 0,
+                    borrowed_books: Vec::new(),
+                };
+                user_repo.create(&user).await?;
+                user
+            }
+            None => return Err(AppError::Unauthorized(OIDC_ACCOUNT_NOT_FOUND.into())),
+        },
+    };
+
+    ensure_login_allowed(&user)?;
+
+    let new_token_version = user.token_version + 1;
+    user_repo
+        .update_token_version(&user.id, new_token_version)
+        .await?;
+
+    let token = generate_token(&cfg, &user.id.to_hex(), new_token_version)?;
+    let mut response = HttpResponse::Ok();
+    clear_oidc_state_cookie(&mut response, &cfg);
+    let token = issue_session(&mut response, &cfg, token);
+    Ok(response.json(Response {
+        msg: LOGIN_SUCCESS.into(),
+        data: token,
+    }))
+}
+
+/// Picks a username that satisfies the 3-30 character rule from the identity
+/// provider's preferred username, falling back to the email's local part.
+fn derive_username(preferred: Option<&str>, email: &str) -> String {
+    let local_part = email.split('@').next().unwrap_or_default();
+    let candidate = preferred
+        .filter(|name| name.chars().count() >= 3)
+        .unwrap_or(local_part);
+
+    let mut username: String = candidate.chars().take(30).collect();
+    while username.chars().count() < 3 {
+        username.push('_');
+    }
+    username
+}
+
 pub fn auth_scope() -> actix_web::Scope {
     scope("/auth")
         .service(register)
         .service(login)
         .service(logout)
+        .service(forgot_password)
+        .service(reset_password)
+        .service(verify_email)
+        .service(oidc_login)
+        .service(oidc_callback)
 }
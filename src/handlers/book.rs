@@ -1,14 +1,20 @@
-use crate::auth::AuthenticatedUser;
+use crate::auth::{ensure_account_active, AuthenticatedUser, CatalogReader};
+use crate::config::app_config::AppConfig;
 use crate::constants::*;
-use crate::database::mongodb::{BookRepository, UserRepository};
+use crate::database::repository::{BookRepository, UserRepository};
 use crate::errors::AppError;
 use crate::models::response::{BookDetail, BookInfo, Response};
+use crate::models::user::User;
+use crate::utils::metrics::METRICS;
 use actix_web::web::{scope, Data, Path};
 use actix_web::{get, post, HttpResponse};
 use mongodb::bson::oid::ObjectId;
 
 #[get("")]
-async fn get_all_books(book_repo: Data<BookRepository>) -> Result<HttpResponse, AppError> {
+async fn get_all_books(
+    _reader: CatalogReader,
+    book_repo: Data<dyn BookRepository>,
+) -> Result<HttpResponse, AppError> {
     let books = book_repo.find_all().await?;
 
     let infos: Vec<BookInfo> = books
@@ -29,7 +35,8 @@ async fn get_all_books(book_repo: Data<BookRepository>) -> Result<HttpResponse,
 
 #[get("/title/{title}")]
 async fn get_books_by_title(
-    book_repo: Data<BookRepository>,
+    _reader: CatalogReader,
+    book_repo: Data<dyn BookRepository>,
     title: Path<String>,
 ) -> Result<HttpResponse, AppError> {
     let books = book_repo.find_by_title(title.as_str()).await?;
@@ -51,7 +58,8 @@ async fn get_books_by_title(
 
 #[get("/author/{author}")]
 async fn get_books_by_author(
-    book_repo: Data<BookRepository>,
+    _reader: CatalogReader,
+    book_repo: Data<dyn BookRepository>,
     author: Path<String>,
 ) -> Result<HttpResponse, AppError> {
     let books = book_repo.find_by_author(author.as_str()).await?;
@@ -73,7 +81,8 @@ async fn get_books_by_author(
 
 #[get("/id/{id}")]
 async fn get_book_by_id(
-    book_repo: Data<BookRepository>,
+    _reader: CatalogReader,
+    book_repo: Data<dyn BookRepository>,
     id: Path<String>,
 ) -> Result<HttpResponse, AppError> {
     let object_id = ObjectId::parse_str(id.as_str()).map_err(|_| {
@@ -96,11 +105,112 @@ async fn get_book_by_id(
     }))
 }
 
+/// The patron's loan limit: their category's, lowered by a per-user limit if set.
+pub(crate) fn loan_limit_for(user: &User, cfg: &AppConfig) -> usize {
+    let limit = cfg.loan_limit(user.category);
+    match user.loan_limit {
+        Some(own) => limit.min(own as usize),
+        None => limit,
+    }
+}
+
+/// Open loans of a guardian and all of their dependents.
+async fn family_loans(
+    head_id: &ObjectId,
+    user_repo: &dyn UserRepository,
+) -> Result<usize, AppError> {
+    let head = user_repo
+        .find_by_id(head_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(USER_NOT_FOUND.into()))?;
+    let dependents = user_repo.find_dependents(head_id).await?;
+    Ok(head.borrowed_books.len()
+        + dependents.iter().map(|d| d.borrowed_books.len()).sum::<usize>())
+}
+
+/// Applies the circulation policy to `borrower` and records the loan. Dependents are
+/// also held to their guardian's family cap.
+pub(crate) async fn borrow_for(
+    borrower: &User,
+    book_id: &ObjectId,
+    book_repo: &dyn BookRepository,
+    user_repo: &dyn UserRepository,
+    cfg: &AppConfig,
+) -> Result<(), AppError> {
+    // Borrowing is what a suspension most directly blocks, so the stored account is
+    // checked here rather than left to the extractor alone.
+    ensure_account_active(borrower)?;
+
+    let guardian = match borrower.guardian_id {
+        Some(ref guardian_id) => user_repo.find_by_id(guardian_id).await?,
+        None => None,
+    };
+
+    // Managed dependents have no address of their own and borrow on their guardian's.
+    let email_verified = match guardian {
+        Some(ref guardian) if borrower.is_managed() => guardian.email_verified,
+        _ => borrower.email_verified,
+    };
+    if cfg.require_verified_email_to_borrow && !email_verified {
+        return Err(AppError::Forbidden(EMAIL_NOT_VERIFIED.into()));
+    }
+
+    if borrower.membership_expired() {
+        return Err(AppError::Forbidden(MEMBERSHIP_EXPIRED.into()));
+    }
+
+    let head = guardian.as_ref().unwrap_or(borrower);
+    let family_cap = head.family_loan_cap.map(|cap| cap as usize);
+    if let Some(cap) = family_cap {
+        if family_loans(&head.id, user_repo).await? >= cap {
+            return Err(AppError::BadRequest(FAMILY_LIMIT_REACHED.into()));
+        }
+    }
+
+    let limit = loan_limit_for(borrower, cfg);
+
+    book_repo.borrow_book(book_id).await?;
+
+    if let Err(e) = user_repo.add_borrowed_book(&borrower.id, book_id, limit).await {
+        if let Err(rollback_err) = book_repo.return_book(book_id).await {
+            tracing::error!("failed to rollback book stock after user borrow failure: {:?}", rollback_err);
+        }
+        return Err(e);
+    }
+
+    // The cap spans several accounts, so no single write can enforce it. Counting again
+    // once the loan is recorded catches family members borrowing at the same time; in
+    // that race both may be turned away, but the cap is never exceeded.
+    if let Some(cap) = family_cap {
+        if family_loans(&head.id, user_repo).await? > cap {
+            user_repo.remove_borrowed_book(&borrower.id, book_id).await?;
+            book_repo.return_book(book_id).await?;
+            return Err(AppError::BadRequest(FAMILY_LIMIT_REACHED.into()));
+        }
+    }
+
+    METRICS.books_borrowed.inc();
+    Ok(())
+}
+
+pub(crate) async fn return_for(
+    user_id: &ObjectId,
+    book_id: &ObjectId,
+    book_repo: &dyn BookRepository,
+    user_repo: &dyn UserRepository,
+) -> Result<(), AppError> {
+    user_repo.remove_borrowed_book(user_id, book_id).await?;
+    book_repo.return_book(book_id).await?;
+    METRICS.books_returned.inc();
+    Ok(())
+}
+
 #[post("/borrow/{id}")]
 async fn borrow_book(
     user: AuthenticatedUser,
-    book_repo: Data<BookRepository>,
-    user_repo: Data<UserRepository>,
+    book_repo: Data<dyn BookRepository>,
+    user_repo: Data<dyn UserRepository>,
+    cfg: Data<AppConfig>,
     id: Path<String>,
 ) -> Result<HttpResponse, AppError> {
     let object_id = ObjectId::parse_str(id.as_str()).map_err(|_| {
@@ -111,14 +221,12 @@ async fn borrow_book(
         AppError::BadRequest(INVALID_USER_ID.into())
     })?;
 
-    book_repo.borrow_book(&object_id).await?;
+    let borrower = user_repo
+        .find_by_id(&user_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(USER_NOT_FOUND.into()))?;
 
-    if let Err(e) = user_repo.add_borrowed_book(&user_id, &object_id).await {
-        if let Err(rollback_err) = book_repo.return_book(&object_id).await {
-            tracing::error!("failed to rollback book stock after user borrow failure: {:?}", rollback_err);
-        }
-        return Err(e);
-    }
+    borrow_for(&borrower, &object_id, book_repo.get_ref(), user_repo.get_ref(), &cfg).await?;
 
     Ok(HttpResponse::Ok().json(Response::<()> {
         msg: BOOK_BORROWED.into(),
@@ -129,8 +237,8 @@ async fn borrow_book(
 #[post("/return/{id}")]
 async fn return_book(
     user: AuthenticatedUser,
-    book_repo: Data<BookRepository>,
-    user_repo: Data<UserRepository>,
+    book_repo: Data<dyn BookRepository>,
+    user_repo: Data<dyn UserRepository>,
     id: Path<String>,
 ) -> Result<HttpResponse, AppError> {
     let object_id = ObjectId::parse_str(id.as_str()).map_err(|_| {
@@ -141,8 +249,7 @@ async fn return_book(
         AppError::BadRequest(INVALID_USER_ID.into())
     })?;
 
-    user_repo.remove_borrowed_book(&user_id, &object_id).await?;
-    book_repo.return_book(&object_id).await?;
+    return_for(&user_id, &object_id, book_repo.get_ref(), user_repo.get_ref()).await?;
 
     Ok(HttpResponse::Ok().json(Response::<()> {
         msg: BOOK_RETURNED.into(),
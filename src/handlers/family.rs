@@ -0,0 +1,254 @@
+use crate::auth::AuthenticatedUser;
+use crate::config::app_config::AppConfig;
+use crate::constants::*;
+use crate::database::repository::{BookRepository, UserRepository};
+use crate::errors::AppError;
+use crate::handlers::book::{borrow_for, loan_limit_for, return_for};
+use crate::handlers::user::{borrowed_book_details, membership_info};
+use crate::models::request::{CreateDependentRequest, UpdateDependentRequest, UpdateFamilyRequest};
+use crate::models::response::{DependentInfo, FamilyInfo, Response};
+use crate::models::user::{PatronCategory, User};
+use crate::utils::membership::new_membership_expiry;
+use actix_web::web::{Data, Json, Path};
+use actix_web::{get, post, put, HttpResponse};
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::DateTime;
+use validator::Validate;
+
+async fn load_guardian(
+    user_repo: &dyn UserRepository,
+    user: &AuthenticatedUser,
+) -> Result<User, AppError> {
+    let uid = ObjectId::parse_str(&user.user_id)?;
+    let guardian = user_repo
+        .find_by_id(&uid)
+        .await?
+        .ok_or(AppError::Unauthorized(USER_NOT_FOUND.into()))?;
+
+    // Families are one level deep, so a dependent cannot manage dependents.
+    if guardian.guardian_id.is_some() {
+        return Err(AppError::Forbidden(INVALID_GUARDIAN.into()));
+    }
+
+    Ok(guardian)
+}
+
+/// Loads a dependent, answering 404 for accounts the guardian is not linked to.
+async fn load_dependent(
+    user_repo: &dyn UserRepository,
+    guardian: &User,
+    id: &str,
+) -> Result<User, AppError> {
+    let object_id =
+        ObjectId::parse_str(id).map_err(|_| AppError::BadRequest(INVALID_USER_ID.into()))?;
+
+    user_repo
+        .find_by_id(&object_id)
+        .await?
+        .filter(|d| d.guardian_id == Some(guardian.id) && d.deleted_at.is_none())
+        .ok_or_else(|| AppError::NotFound(DEPENDENT_NOT_FOUND.into()))
+}
+
+async fn dependent_info(
+    book_repo: &dyn BookRepository,
+    cfg: &AppConfig,
+    dependent: User,
+) -> Result<DependentInfo, AppError> {
+    Ok(DependentInfo {
+        id: dependent.id.to_hex(),
+        managed: dependent.is_managed(),
+        loan_limit: loan_limit_for(&dependent, cfg),
+        membership: membership_info(&dependent),
+        borrowed_books: borrowed_book_details(book_repo, &dependent.borrowed_books).await?,
+        category: dependent.category,
+        username: dependent.username,
+    })
+}
+
+#[get("/me/family")]
+async fn get_family(
+    user_repo: Data<dyn UserRepository>,
+    book_repo: Data<dyn BookRepository>,
+    cfg: Data<AppConfig>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, AppError> {
+    let guardian = load_guardian(user_repo.get_ref(), &user).await?;
+    let mut active_loans = guardian.borrowed_books.len();
+
+    let mut dependents = Vec::new();
+    for dependent in user_repo.find_dependents(&guardian.id).await? {
+        active_loans += dependent.borrowed_books.len();
+        dependents.push(dependent_info(book_repo.get_ref(), &cfg, dependent).await?);
+    }
+
+    Ok(HttpResponse::Ok().json(Response {
+        msg: FAMILY_FETCHED.into(),
+        data: Some(FamilyInfo {
+            loan_cap: guardian.family_loan_cap,
+            active_loans,
+            dependents,
+        }),
+    }))
+}
+
+#[put("/me/family")]
+async fn update_family(
+    user_repo: Data<dyn UserRepository>,
+    user: AuthenticatedUser,
+    payload: Json<UpdateFamilyRequest>,
+) -> Result<HttpResponse, AppError> {
+    payload
+        .validate()
+        .map_err(|e| AppError::BadRequest(e.to_string()))?;
+
+    let guardian = load_guardian(user_repo.get_ref(), &user).await?;
+    user_repo
+        .set_family_loan_cap(&guardian.id, payload.loan_cap)
+        .await?;
+
+    Ok(HttpResponse::Ok().json(Response::<()> {
+        msg: FAMILY_UPDATED.into(),
+        data: None,
+    }))
+}
+
+/// Creates a dependent without an email or password. The account can only be used
+/// through the guardian until an administrator gives it an email address.
+#[post("/me/dependents")]
+async fn create_dependent(
+    user_repo: Data<dyn UserRepository>,
+    book_repo: Data<dyn BookRepository>,
+    cfg: Data<AppConfig>,
+    user: AuthenticatedUser,
+    payload: Json<CreateDependentRequest>,
+) -> Result<HttpResponse, AppError> {
+    payload
+        .validate()
+        .map_err(|e| AppError::BadRequest(e.to_string()))?;
+
+    let guardian = load_guardian(user_repo.get_ref(), &user).await?;
+
+    let dependent = User {
+        id: ObjectId::new(),
+        email: String::new(),
+        username: payload.username.clone(),
+        password_hash: String::new(),
+        password_history: Vec::new(),
+        password_changed_at: None,
+        email_verified: false,
+        pending_email: None,
+        oidc_subject: None,
+        ldap_dn: None,
+        disabled: false,
+        suspension: None,
+        deleted_at: None,
+        category: payload.category.unwrap_or(PatronCategory::Child),
+        membership_started_at: Some(DateTime::now()),
+        membership_expires_at: new_membership_expiry(&cfg),
+        guardian_id: Some(guardian.id),
+        loan_limit: payload.loan_limit,
+        family_loan_cap: None,
+        is_admin: false,
+        token_version: 0,
+        borrowed_books: Vec::new(),
+    };
+
+    user_repo.create(&dependent).await?;
+
+    tracing::info!(guardian_id = %guardian.id, dependent_id = %dependent.id, "dependent created");
+
+    Ok(HttpResponse::Created().json(Response {
+        msg: DEPENDENT_CREATED.into(),
+        data: Some(dependent_info(book_repo.get_ref(), &cfg, dependent).await?),
+    }))
+}
+
+#[put("/me/dependents/{id}")]
+async fn update_dependent(
+    user_repo: Data<dyn UserRepository>,
+    user: AuthenticatedUser,
+    id: Path<String>,
+    payload: Json<UpdateDependentRequest>,
+) -> Result<HttpResponse, AppError> {
+    payload
+        .validate()
+        .map_err(|e| AppError::BadRequest(e.to_string()))?;
+
+    let guardian = load_guardian(user_repo.get_ref(), &user).await?;
+    let dependent = load_dependent(user_repo.get_ref(), &guardian, &id).await?;
+
+    // Dependents with their own login keep control of their username.
+    if let Some(ref username) = payload.username {
+        if !dependent.is_managed() {
+            return Err(AppError::Forbidden(PERMISSION_DENIED.into()));
+        }
+        user_repo.update_username(&dependent.id, username).await?;
+    }
+
+    if let Some(loan_limit) = payload.loan_limit {
+        user_repo.set_loan_limit(&dependent.id, loan_limit).await?;
+    }
+
+    Ok(HttpResponse::Ok().json(Response::<()> {
+        msg: DEPENDENT_UPDATED.into(),
+        data: None,
+    }))
+}
+
+#[post("/me/dependents/{id}/borrow/{book_id}")]
+async fn borrow_for_dependent(
+    user_repo: Data<dyn UserRepository>,
+    book_repo: Data<dyn BookRepository>,
+    cfg: Data<AppConfig>,
+    user: AuthenticatedUser,
+    path: Path<(String, String)>,
+) -> Result<HttpResponse, AppError> {
+    let (id, book_id) = path.into_inner();
+    let book_id =
+        ObjectId::parse_str(&book_id).map_err(|_| AppError::BadRequest(INVALID_BOOK_ID.into()))?;
+
+    let guardian = load_guardian(user_repo.get_ref(), &user).await?;
+    let dependent = load_dependent(user_repo.get_ref(), &guardian, &id).await?;
+
+    borrow_for(
+        &dependent,
+        &book_id,
+        book_repo.get_ref(),
+        user_repo.get_ref(),
+        &cfg,
+    )
+    .await?;
+
+    Ok(HttpResponse::Ok().json(Response::<()> {
+        msg: BOOK_BORROWED.into(),
+        data: None,
+    }))
+}
+
+#[post("/me/dependents/{id}/return/{book_id}")]
+async fn return_for_dependent(
+    user_repo: Data<dyn UserRepository>,
+    book_repo: Data<dyn BookRepository>,
+    user: AuthenticatedUser,
+    path: Path<(String, String)>,
+) -> Result<HttpResponse, AppError> {
+    let (id, book_id) = path.into_inner();
+    let book_id =
+        ObjectId::parse_str(&book_id).map_err(|_| AppError::BadRequest(INVALID_BOOK_ID.into()))?;
+
+    let guardian = load_guardian(user_repo.get_ref(), &user).await?;
+    let dependent = load_dependent(user_repo.get_ref(), &guardian, &id).await?;
+
+    return_for(
+        &dependent.id,
+        &book_id,
+        book_repo.get_ref(),
+        user_repo.get_ref(),
+    )
+    .await?;
+
+    Ok(HttpResponse::Ok().json(Response::<()> {
+        msg: BOOK_RETURNED.into(),
+        data: None,
+    }))
+}
@@ -1,9 +1,96 @@
-use actix_web::{get, HttpResponse};
+use crate::config::app_config::AppConfig;
+use crate::database::health::{HealthCheck, HealthChecks};
+use actix_web::rt::time::timeout;
+use actix_web::web::{scope, Data};
+use actix_web::{get, HttpResponse, Scope};
+use futures::future::join_all;
+use serde_json::{json, Map, Value};
+use std::time::{Duration, Instant};
 
-#[get("/health")]
-async fn health_check() -> HttpResponse {
-    HttpResponse::Ok().json(serde_json::json!({
+/// Readiness report, kept on the original path for existing probes.
+#[get("")]
+async fn health_check(checks: Data<HealthChecks>, cfg: Data<AppConfig>) -> HttpResponse {
+    readiness_report(&checks, &cfg).await
+}
+
+/// Answers as long as the process can serve requests. Dependencies are left out so
+/// that an outage elsewhere does not get every instance restarted.
+#[get("/live")]
+async fn liveness() -> HttpResponse {
+    HttpResponse::Ok().json(json!({
         "status": "ok",
-        "timestamp": time::OffsetDateTime::now_utc().unix_timestamp()
+        "timestamp": time::OffsetDateTime::now_utc().unix_timestamp(),
+        "build": build_info(),
     }))
 }
+
+#[get("/ready")]
+async fn readiness(checks: Data<HealthChecks>, cfg: Data<AppConfig>) -> HttpResponse {
+    readiness_report(&checks, &cfg).await
+}
+
+/// Pings every dependency concurrently. Any that fails or misses the timeout makes
+/// the instance degraded and the response a 503.
+async fn readiness_report(checks: &HealthChecks, cfg: &AppConfig) -> HttpResponse {
+    let limit = Duration::from_millis(cfg.health_check_timeout_ms);
+    let results = join_all(checks.0.iter().map(|check| probe(check.as_ref(), limit))).await;
+
+    let healthy = results.iter().all(|(_, up, _)| *up);
+    let dependencies: Map<String, Value> = results
+        .into_iter()
+        .map(|(name, _, report)| (name.to_string(), report))
+        .collect();
+
+    let body = json!({
+        "status": if healthy { "ok" } else { "degraded" },
+        "timestamp": time::OffsetDateTime::now_utc().unix_timestamp(),
+        "build": build_info(),
+        "dependencies": dependencies,
+    });
+    if healthy {
+        HttpResponse::Ok().json(body)
+    } else {
+        HttpResponse::ServiceUnavailable().json(body)
+    }
+}
+
+async fn probe(check: &dyn HealthCheck, limit: Duration) -> (&'static str, bool, Value) {
+    let started = Instant::now();
+    let outcome = timeout(limit, check.ping()).await;
+    let latency_ms = started.elapsed().as_secs_f64() * 1000.0;
+
+    // The cause is logged rather than returned, as the probe needs no authentication.
+    let error = match outcome {
+        Ok(Ok(())) => None,
+        Ok(Err(e)) => {
+            tracing::warn!("{} health check failed: {}", check.name(), e);
+            Some("unreachable")
+        }
+        Err(_) => {
+            tracing::warn!("{} health check timed out after {:?}", check.name(), limit);
+            Some("timed out")
+        }
+    };
+
+    let report = match error {
+        None => json!({ "status": "up", "latency_ms": latency_ms }),
+        Some(error) => json!({ "status": "down", "latency_ms": latency_ms, "error": error }),
+    };
+    (check.name(), error.is_none(), report)
+}
+
+fn build_info() -> Value {
+    json!({
+        "name": env!("CARGO_PKG_NAME"),
+        "version": env!("CARGO_PKG_VERSION"),
+        // Set by build.rs when the commit is known
+        "commit": option_env!("BUILD_GIT_COMMIT"),
+    })
+}
+
+pub fn health_scope() -> Scope {
+    scope("/health")
+        .service(health_check)
+        .service(liveness)
+        .service(readiness)
+}
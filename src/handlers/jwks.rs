@@ -0,0 +1,12 @@
+use crate::config::app_config::AppConfig;
+use actix_web::web::Data;
+use actix_web::{get, HttpResponse};
+
+/// Publishes the public verification keys so other services can check our tokens
+/// without holding the signing key. Empty when tokens are signed with a shared secret.
+#[get("/.well-known/jwks.json")]
+async fn get_jwks(cfg: Data<AppConfig>) -> HttpResponse {
+    HttpResponse::Ok()
+        .insert_header(("Cache-Control", "public, max-age=300"))
+        .json(cfg.jwt_keys.jwk_set())
+}
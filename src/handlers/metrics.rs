@@ -0,0 +1,64 @@
+use crate::auth::constant_time_eq;
+use crate::config::app_config::AppConfig;
+use crate::constants::{AUTH_REQUIRED, METRICS_GAUGE_REFRESH_SECONDS};
+use crate::database::repository::{BookRepository, UserRepository};
+use crate::errors::AppError;
+use crate::utils::metrics::METRICS;
+use actix_web::web::Data;
+use actix_web::{get, HttpRequest, HttpResponse};
+use prometheus::TEXT_FORMAT;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+static GAUGES_REFRESHED_AT: Mutex<Option<Instant>> = Mutex::new(None);
+
+/// Whether the database-backed gauges are due for a refresh, claiming it if so.
+fn gauges_due() -> bool {
+    let mut refreshed_at = GAUGES_REFRESHED_AT
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    let interval = Duration::from_secs(METRICS_GAUGE_REFRESH_SECONDS);
+    if refreshed_at.is_some_and(|at| at.elapsed() < interval) {
+        return false;
+    }
+    *refreshed_at = Some(Instant::now());
+    true
+}
+
+/// Prometheus scrape endpoint. Requires `Authorization: Bearer <METRICS_TOKEN>` when
+/// a token is configured. The loan and stock gauges are counted at most every
+/// `METRICS_GAUGE_REFRESH_SECONDS`; if the database is unreachable they keep their
+/// last value.
+#[get("/metrics")]
+async fn get_metrics(
+    req: HttpRequest,
+    cfg: Data<AppConfig>,
+    user_repo: Data<dyn UserRepository>,
+    book_repo: Data<dyn BookRepository>,
+) -> Result<HttpResponse, AppError> {
+    if let Some(ref expected) = cfg.metrics_token {
+        let token = req
+            .headers()
+            .get("Authorization")
+            .and_then(|h| h.to_str().ok())
+            .and_then(|h| h.strip_prefix("Bearer "));
+        if !token.is_some_and(|token| constant_time_eq(token.trim(), expected)) {
+            return Err(AppError::Unauthorized(AUTH_REQUIRED.into()));
+        }
+    }
+
+    if gauges_due() {
+        match user_repo.count_active_loans().await {
+            Ok(loans) => METRICS.active_loans.set(loans as i64),
+            Err(e) => tracing::warn!("failed to count active loans: {:?}", e),
+        }
+        match book_repo.count_out_of_stock().await {
+            Ok(titles) => METRICS.out_of_stock_titles.set(titles as i64),
+            Err(e) => tracing::warn!("failed to count out-of-stock titles: {:?}", e),
+        }
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(TEXT_FORMAT)
+        .body(METRICS.render()))
+}
@@ -1,11 +1,35 @@
 mod admin;
+mod api_key;
 mod auth;
+mod family;
 mod health;
+mod jwks;
+mod metrics;
 mod user;
+mod user_bulk;
 mod book;
+mod verification;
 
-pub use admin::admin_scope;
-pub use auth::auth_scope;
-pub use health::health_check;
-pub use user::user_scope;
-pub use book::book_scope;
+#[cfg(test)]
+mod tests;
+
+use crate::utils::rate_limit::{rate_limit, RateLimitGroup};
+use actix_web::middleware::from_fn;
+use actix_web::web::ServiceConfig;
+
+/// Registers every route of the API.
+pub fn configure(cfg: &mut ServiceConfig) {
+    cfg.service(health::health_scope())
+        .service(metrics::get_metrics)
+        .service(jwks::get_jwks)
+        .service(auth::auth_scope().wrap(from_fn(|req, next| {
+            rate_limit(RateLimitGroup::Auth, req, next)
+        })))
+        .service(user::user_scope())
+        .service(book::book_scope().wrap(from_fn(|req, next| {
+            rate_limit(RateLimitGroup::Book, req, next)
+        })))
+        .service(admin::admin_scope().wrap(from_fn(|req, next| {
+            rate_limit(RateLimitGroup::Admin, req, next)
+        })));
+}
@@ -0,0 +1,1174 @@
+//! End-to-end tests of the HTTP API against the in-memory backend.
+
+use crate::auth::OidcProvider;
+use crate::config::app_config::{AppConfig, OidcConfig, RateLimit, SessionCookieConfig};
+use crate::config::live::LiveConfig;
+use crate::config::source::ConfigSource;
+use crate::constants::*;
+use crate::database::health::{HealthCheck, HealthChecks};
+use crate::database::memory::{
+    MemoryApiKeyRepository, MemoryBookRepository, MemoryEmailVerificationStore,
+    MemoryOidcStateStore, MemoryPasswordResetStore, MemoryRateLimitStore, MemoryTokenBlacklist,
+    MemoryUserRepository,
+};
+use crate::database::repository::{ApiKeyRepository, BookRepository, UserRepository};
+use crate::database::store::{
+    EmailVerificationStore, OidcStateStore, PasswordResetStore, RateLimitStore, TokenBlacklist,
+};
+use crate::errors::AppError;
+use crate::handlers::configure;
+use crate::models::user::{PatronCategory, Suspension, User};
+use crate::utils::mailer::Mailer;
+use crate::utils::metrics::track_requests;
+use crate::utils::random_token::hash_random_token;
+use crate::utils::token::generate_token;
+use actix_web::body::MessageBody;
+use actix_web::cookie::SameSite;
+use actix_web::dev::{Service, ServiceResponse};
+use actix_web::http::StatusCode;
+use actix_web::middleware::from_fn;
+use actix_web::test::{self, TestRequest};
+use actix_web::web::{self, Data, ServiceConfig};
+use actix_web::{App, HttpResponse, HttpServer};
+use async_trait::async_trait;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use jsonwebtoken::{Algorithm, EncodingKey, Header};
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::DateTime;
+use rsa::pkcs8::DecodePrivateKey;
+use rsa::traits::PublicKeyParts;
+use serde_json::{json, Value};
+use sha1::{Digest, Sha1};
+use std::sync::{Arc, Mutex, Once};
+
+const PASSWORD: &str = "correct horse battery staple";
+
+fn config() -> AppConfig {
+    static ENV: Once = Once::new();
+    ENV.call_once(|| {
+        std::env::set_var(JWT_SECRET, "an-hs256-secret-used-only-by-the-test-suite");
+        std::env::set_var(MONGO_URI, "mongodb://unused");
+        std::env::set_var(MONGO_DB, "unused");
+        std::env::set_var(REDIS_URI, "redis://unused");
+        // Keep password hashing cheap; the cost is irrelevant to what is tested.
+        std::env::set_var(ARGON2_MEMORY_KIB, "1024");
+        std::env::set_var(ARGON2_ITERATIONS, "1");
+        std::env::set_var(HEALTH_CHECK_TIMEOUT_MS, "50");
+    });
+    AppConfig::load(&ConfigSource::new(None)).expect("test configuration must be valid")
+}
+
+/// The application state, backed entirely by memory.
+struct Backend {
+    users: Arc<dyn UserRepository>,
+    books: Arc<dyn BookRepository>,
+    keys: Arc<dyn ApiKeyRepository>,
+    blacklist: Arc<dyn TokenBlacklist>,
+    reset_store: Arc<dyn PasswordResetStore>,
+    verification_store: Arc<dyn EmailVerificationStore>,
+    oidc_state_store: Arc<dyn OidcStateStore>,
+    rate_limits: Arc<dyn RateLimitStore>,
+    health: HealthChecks,
+    live: LiveConfig,
+}
+
+impl Backend {
+    fn new() -> Self {
+        Self {
+            users: Arc::new(MemoryUserRepository::default()),
+            books: Arc::new(MemoryBookRepository::default()),
+            keys: Arc::new(MemoryApiKeyRepository::default()),
+            blacklist: Arc::new(MemoryTokenBlacklist::default()),
+            reset_store: Arc::new(MemoryPasswordResetStore::default()),
+            verification_store: Arc::new(MemoryEmailVerificationStore::default()),
+            oidc_state_store: Arc::new(MemoryOidcStateStore::default()),
+            rate_limits: Arc::new(MemoryRateLimitStore::default()),
+            health: HealthChecks::default(),
+            live: LiveConfig::new(&config()),
+        }
+    }
+
+    fn register(&self, cfg: &mut ServiceConfig) {
+        let app_config = config();
+        let mailer = Mailer::new(&app_config).expect("mailer without SMTP must build");
+        cfg.app_data(Data::new(app_config))
+            .app_data(Data::new(mailer))
+            .app_data(Data::from(self.users.clone()))
+            .app_data(Data::from(self.books.clone()))
+            .app_data(Data::from(self.keys.clone()))
+            .app_data(Data::from(self.blacklist.clone()))
+            .app_data(Data::from(self.reset_store.clone()))
+            .app_data(Data::from(self.verification_store.clone()))
+            .app_data(Data::from(self.oidc_state_store.clone()))
+            .app_data(Data::from(self.rate_limits.clone()))
+            .app_data(Data::new(self.health.clone()))
+            .app_data(Data::new(self.live.clone()));
+    }
+}
+
+async fn call<S, B>(app: &S, req: TestRequest) -> (StatusCode, Value)
+where
+    S: Service<actix_http::Request, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    B: MessageBody,
+{
+    let res = test::call_service(app, req.to_request()).await;
+    let status = res.status();
+    let body = test::read_body(res).await;
+    (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+}
+
+fn bearer(token: &str) -> (&'static str, String) {
+    ("Authorization", format!("Bearer {}", token))
+}
+
+async fn register<S, B>(app: &S, email: &str, username: &str) -> String
+where
+    S: Service<actix_http::Request, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    B: MessageBody,
+{
+    let req = TestRequest::post()
+        .uri("/auth/register")
+        .set_json(json!({ "email": email, "username": username, "password": PASSWORD }));
+    let (status, body) = call(app, req).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    body["data"]["token"].as_str().unwrap().to_string()
+}
+
+async fn find_user(backend: &Backend, email: &str) -> User {
+    backend
+        .users
+        .find_by_email(email)
+        .await
+        .unwrap()
+        .expect("the user must exist")
+}
+
+/// Registers an account and gives it the admin role.
+async fn register_admin<S, B>(app: &S, backend: &Backend, email: &str, username: &str) -> String
+where
+    S: Service<actix_http::Request, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    B: MessageBody,
+{
+    let token = register(app, email, username).await;
+    let admin = find_user(backend, email).await;
+    backend.users.set_admin(&admin.id, true).await.unwrap();
+    token
+}
+
+const OIDC_PROVIDER_KEY: &str = include_str!("testdata/oidc_provider_key.pem");
+
+/// Just enough of an OpenID provider for the authorization code flow: discovery,
+/// the signing key and a token endpoint issuing an ID token for the expected nonce.
+#[derive(Clone)]
+struct MockIdentityProvider {
+    issuer: String,
+    nonce: Arc<Mutex<String>>,
+}
+
+impl MockIdentityProvider {
+    fn start() -> Self {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let idp = MockIdentityProvider {
+            issuer: format!("http://{}", listener.local_addr().unwrap()),
+            nonce: Arc::default(),
+        };
+        let state = idp.clone();
+        let server = HttpServer::new(move || {
+            App::new()
+                .app_data(Data::new(state.clone()))
+                .route(
+                    "/.well-known/openid-configuration",
+                    web::get().to(Self::discovery),
+                )
+                .route("/jwks", web::get().to(Self::jwks))
+                .route("/token", web::post().to(Self::token))
+        })
+        .workers(1)
+        .listen(listener)
+        .unwrap()
+        .run();
+        actix_web::rt::spawn(server);
+        idp
+    }
+
+    async fn discovery(idp: Data<Self>) -> HttpResponse {
+        HttpResponse::Ok().json(json!({
+            "issuer": idp.issuer,
+            "authorization_endpoint": format!("{}/authorize", idp.issuer),
+            "token_endpoint": format!("{}/token", idp.issuer),
+            "jwks_uri": format!("{}/jwks", idp.issuer),
+            "response_types_supported": ["code"],
+            "subject_types_supported": ["public"],
+            "id_token_signing_alg_values_supported": ["RS256"],
+        }))
+    }
+
+    async fn jwks() -> HttpResponse {
+        let key = rsa::RsaPrivateKey::from_pkcs8_pem(OIDC_PROVIDER_KEY).unwrap();
+        HttpResponse::Ok().json(json!({ "keys": [{
+            "kty": "RSA",
+            "use": "sig",
+            "alg": "RS256",
+            "kid": "mock",
+            "n": URL_SAFE_NO_PAD.encode(key.n().to_bytes_be()),
+            "e": URL_SAFE_NO_PAD.encode(key.e().to_bytes_be()),
+        }] }))
+    }
+
+    async fn token(idp: Data<Self>) -> HttpResponse {
+        let now = DateTime::now().timestamp_millis() / 1000;
+        let claims = json!({
+            "iss": idp.issuer,
+            "sub": "mock-subject",
+            "aud": "library",
+            "iat": now,
+            "exp": now + 300,
+            "nonce": *idp.nonce.lock().unwrap(),
+            "email": "sso@example.com",
+            "email_verified": true,
+            "preferred_username": "sso-reader",
+        });
+        let mut header = Header::new(Algorithm::RS256);
+        header.kid = Some("mock".into());
+        let key = EncodingKey::from_rsa_pem(OIDC_PROVIDER_KEY.as_bytes()).unwrap();
+        HttpResponse::Ok().json(json!({
+            "access_token": "mock-access-token",
+            "token_type": "Bearer",
+            "expires_in": 300,
+            "id_token": jsonwebtoken::encode(&header, &claims, &key).unwrap(),
+        }))
+    }
+}
+
+macro_rules! app {
+    ($backend:expr) => {
+        test::init_service(
+            App::new()
+                .configure(|cfg| $backend.register(cfg))
+                .configure(configure),
+        )
+        .await
+    };
+}
+
+#[actix_web::test]
+async fn register_login_and_fetch_profile() {
+    let backend = Backend::new();
+    let app = app!(backend);
+
+    register(&app, "reader@example.com", "reader").await;
+
+    let req = TestRequest::post()
+        .uri("/auth/login")
+        .set_json(json!({ "email": "reader@example.com", "password": "wrong password" }));
+    assert_eq!(call(&app, req).await.0, StatusCode::UNAUTHORIZED);
+
+    let req = TestRequest::post()
+        .uri("/auth/login")
+        .set_json(json!({ "email": "reader@example.com", "password": PASSWORD }));
+    let (status, body) = call(&app, req).await;
+    assert_eq!(status, StatusCode::OK);
+    let token = body["data"]["token"].as_str().unwrap();
+
+    let req = TestRequest::get()
+        .uri("/user/me")
+        .insert_header(bearer(token));
+    let (status, body) = call(&app, req).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["data"]["username"], "reader");
+    assert_eq!(body["data"]["borrowed_books"], json!([]));
+
+    let req = TestRequest::post().uri("/auth/register").set_json(
+        json!({ "email": "reader@example.com", "username": "again", "password": PASSWORD }),
+    );
+    assert_eq!(call(&app, req).await.0, StatusCode::CONFLICT);
+}
+
+#[actix_web::test]
+async fn single_sign_on_provisions_one_account_per_identity() {
+    let idp = MockIdentityProvider::start();
+    let backend = Backend::new();
+    let oidc = OidcConfig {
+        issuer_url: idp.issuer.clone(),
+        client_id: "library".into(),
+        client_secret: Some("secret".into()),
+        redirect_url: "http://localhost/auth/oidc/callback".into(),
+        scopes: vec!["email".into(), "profile".into()],
+        auto_provision: true,
+    };
+    let provider = OidcProvider::new(&oidc).unwrap();
+    let mut cfg = config();
+    cfg.oidc = Some(oidc);
+    let app = test::init_service(
+        App::new()
+            .configure(|cfg| backend.register(cfg))
+            .app_data(Data::new(cfg))
+            .app_data(Data::new(provider))
+            .configure(configure),
+    )
+    .await;
+
+    for _ in 0..2 {
+        let req = TestRequest::get().uri("/auth/oidc/login").to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::FOUND);
+        let location = res.headers().get("Location").unwrap().to_str().unwrap();
+        let param = |name: &str| {
+            location
+                .split(['?', '&'])
+                .find_map(|p| p.strip_prefix(&format!("{}=", name)))
+                .unwrap()
+                .to_string()
+        };
+        *idp.nonce.lock().unwrap() = param("nonce");
+        let state_cookie = res
+            .response()
+            .cookies()
+            .find(|c| c.name() == OIDC_STATE_COOKIE_NAME)
+            .unwrap()
+            .into_owned();
+        let uri = format!(
+            "/auth/oidc/callback?code=mock-code&state={}",
+            param("state")
+        );
+
+        // A callback from a browser that did not start the login is refused.
+        let forged = TestRequest::get().uri(&uri);
+        assert_eq!(call(&app, forged).await.0, StatusCode::BAD_REQUEST);
+
+        let callback = TestRequest::get().uri(&uri).cookie(state_cookie);
+        let (status, body) = call(&app, callback).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert!(body["data"]["token"].is_string());
+    }
+
+    let user = backend
+        .users
+        .find_by_oidc_subject("mock-subject")
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(user.email, "sso@example.com");
+    assert_eq!(backend.users.find_all().await.unwrap().len(), 1);
+}
+
+#[actix_web::test]
+async fn logout_revokes_the_token() {
+    let backend = Backend::new();
+    let app = app!(backend);
+    let token = register(&app, "reader@example.com", "reader").await;
+
+    let req = TestRequest::post()
+        .uri("/auth/logout")
+        .insert_header(bearer(&token));
+    assert_eq!(call(&app, req).await.0, StatusCode::OK);
+
+    let req = TestRequest::get()
+        .uri("/user/me")
+        .insert_header(bearer(&token));
+    assert_eq!(call(&app, req).await.0, StatusCode::UNAUTHORIZED);
+
+    // Admin routes honour the blacklist too.
+    let admin = register(&app, "admin@example.com", "librarian").await;
+    let admin_id = backend
+        .users
+        .find_by_email("admin@example.com")
+        .await
+        .unwrap()
+        .unwrap()
+        .id;
+    backend.users.set_admin(&admin_id, true).await.unwrap();
+
+    let req = TestRequest::post()
+        .uri("/auth/logout")
+        .insert_header(bearer(&admin));
+    assert_eq!(call(&app, req).await.0, StatusCode::OK);
+
+    let req = TestRequest::get()
+        .uri("/admin/users")
+        .insert_header(bearer(&admin));
+    assert_eq!(call(&app, req).await.0, StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn suspended_patrons_can_sign_in_but_only_to_return_books() {
+    let backend = Backend::new();
+    let app = app!(backend);
+    register(&app, "reader@example.com", "reader").await;
+
+    let user = backend
+        .users
+        .find_by_email("reader@example.com")
+        .await
+        .unwrap()
+        .unwrap();
+    let suspension = Suspension {
+        reason: "overdue fines".into(),
+        suspended_at: mongodb::bson::DateTime::now(),
+        expires_at: None,
+        suspended_by: mongodb::bson::oid::ObjectId::new(),
+    };
+    backend.users.suspend(&user.id, &suspension).await.unwrap();
+
+    let req = TestRequest::post()
+        .uri("/auth/login")
+        .set_json(json!({ "email": "reader@example.com", "password": PASSWORD }));
+    let (status, body) = call(&app, req).await;
+    assert_eq!(status, StatusCode::OK);
+    let token = body["data"]["token"].as_str().unwrap();
+
+    let req = TestRequest::get()
+        .uri("/user/me")
+        .insert_header(bearer(token));
+    assert_eq!(call(&app, req).await.0, StatusCode::OK);
+
+    let req = TestRequest::post()
+        .uri(&format!(
+            "/books/borrow/{}",
+            mongodb::bson::oid::ObjectId::new()
+        ))
+        .insert_header(bearer(token));
+    assert_eq!(call(&app, req).await.0, StatusCode::FORBIDDEN);
+}
+
+#[actix_web::test]
+async fn deleting_an_account_leaves_an_anonymous_record() {
+    let backend = Backend::new();
+    let app = app!(backend);
+    let token = register(&app, "reader@example.com", "reader").await;
+    let delete = |token: &str, body: Value| {
+        TestRequest::delete()
+            .uri("/user/me")
+            .insert_header(bearer(token))
+            .set_json(body)
+    };
+
+    let req = delete(&token, json!({ "password": "wrong password" }));
+    assert_eq!(call(&app, req).await.0, StatusCode::UNAUTHORIZED);
+    let req = delete(&token, json!({ "password": PASSWORD }));
+    assert_eq!(call(&app, req).await.0, StatusCode::OK);
+
+    let all = backend.users.find_all().await.unwrap();
+    assert_eq!(all.len(), 1);
+    assert!(all[0].email.is_empty() && all[0].disabled);
+    assert_eq!(all[0].username, User::anonymized_username(&all[0].id));
+
+    // The address is free again.
+    register(&app, "reader@example.com", "reader").await;
+    let reader = backend
+        .users
+        .find_by_email("reader@example.com")
+        .await
+        .unwrap()
+        .unwrap();
+
+    // Accounts without a password confirm with a fresh sign-in instead.
+    let sso = User {
+        id: mongodb::bson::oid::ObjectId::new(),
+        email: "sso@example.com".into(),
+        username: "sso".into(),
+        password_hash: String::new(),
+        oidc_subject: Some("subject".into()),
+        ..reader
+    };
+    backend.users.create(&sso).await.unwrap();
+    let token = generate_token(&config(), &sso.id.to_hex(), sso.token_version).unwrap();
+    assert_eq!(
+        call(&app, delete(&token, json!({}))).await.0,
+        StatusCode::OK
+    );
+}
+
+#[actix_web::test]
+async fn borrowing_stops_when_the_shelf_is_empty() {
+    let backend = Backend::new();
+    let app = app!(backend);
+
+    let admin = register(&app, "admin@example.com", "librarian").await;
+    let first = register(&app, "first@example.com", "first").await;
+    let second = register(&app, "second@example.com", "second").await;
+    let admin_id = backend
+        .users
+        .find_by_email("admin@example.com")
+        .await
+        .unwrap()
+        .unwrap()
+        .id;
+    backend.users.set_admin(&admin_id, true).await.unwrap();
+
+    let req = TestRequest::post()
+        .uri("/admin/books")
+        .insert_header(bearer(&admin))
+        .set_json(json!({ "title": "Dune", "author": "Frank Herbert" }));
+    assert_eq!(call(&app, req).await.0, StatusCode::CREATED);
+
+    let (_, body) = call(&app, TestRequest::get().uri("/books")).await;
+    let book_id = body["data"][0]["id"].as_str().unwrap().to_string();
+
+    let req = TestRequest::put()
+        .uri(&format!("/admin/books/{}", book_id))
+        .insert_header(bearer(&admin))
+        .set_json(json!({ "stock": 1 }));
+    assert_eq!(call(&app, req).await.0, StatusCode::OK);
+
+    let borrow = |token: &str| {
+        TestRequest::post()
+            .uri(&format!("/books/borrow/{}", book_id))
+            .insert_header(bearer(token))
+    };
+    let give_back = |token: &str| {
+        TestRequest::post()
+            .uri(&format!("/books/return/{}", book_id))
+            .insert_header(bearer(token))
+    };
+
+    assert_eq!(call(&app, borrow(&first)).await.0, StatusCode::OK);
+    let (status, body) = call(&app, borrow(&second)).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["msg"], NO_STOCK_AVAILABLE);
+
+    // Returning a book the user never borrowed must not add stock.
+    assert_eq!(
+        call(&app, give_back(&second)).await.0,
+        StatusCode::BAD_REQUEST
+    );
+
+    assert_eq!(call(&app, give_back(&first)).await.0, StatusCode::OK);
+    assert_eq!(call(&app, borrow(&second)).await.0, StatusCode::OK);
+
+    let (_, body) = call(
+        &app,
+        TestRequest::get().uri(&format!("/books/id/{}", book_id)),
+    )
+    .await;
+    assert_eq!(body["data"]["stock"], 0);
+}
+
+#[actix_web::test]
+async fn reset_and_verification_tokens_work_once() {
+    let backend = Backend::new();
+    let app = app!(backend);
+    register(&app, "reader@example.com", "reader").await;
+    let user = find_user(&backend, "reader@example.com").await;
+
+    backend
+        .reset_store
+        .store(&hash_random_token("reset-token"), &user.id.to_hex(), 600)
+        .await
+        .unwrap();
+    let reset = |password: &str| {
+        TestRequest::post()
+            .uri("/auth/reset-password")
+            .set_json(json!({ "token": "reset-token", "new_password": password }))
+    };
+
+    // A password the policy rejects leaves the link usable.
+    let (status, body) = call(&app, reset("short")).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(body["msg"]
+        .as_str()
+        .unwrap()
+        .starts_with(PASSWORD_TOO_SHORT));
+
+    assert_eq!(
+        call(&app, reset("a new passphrase")).await.0,
+        StatusCode::OK
+    );
+    let (status, body) = call(&app, reset("another passphrase")).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["msg"], INVALID_RESET_TOKEN);
+
+    let req = TestRequest::post()
+        .uri("/auth/login")
+        .set_json(json!({ "email": "reader@example.com", "password": "a new passphrase" }));
+    assert_eq!(call(&app, req).await.0, StatusCode::OK);
+
+    assert!(!user.email_verified);
+    backend
+        .verification_store
+        .store(
+            &hash_random_token("verify-token"),
+            &user.id.to_hex(),
+            &user.email,
+            600,
+        )
+        .await
+        .unwrap();
+    let verify = || {
+        TestRequest::post()
+            .uri("/auth/verify-email")
+            .set_json(json!({ "token": "verify-token" }))
+    };
+    assert_eq!(call(&app, verify()).await.0, StatusCode::OK);
+    assert!(
+        find_user(&backend, "reader@example.com")
+            .await
+            .email_verified
+    );
+
+    let (status, body) = call(&app, verify()).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["msg"], INVALID_VERIFICATION_TOKEN);
+}
+
+#[actix_web::test]
+async fn password_policy_rejects_short_breached_and_reused_passwords() {
+    let breached = std::env::temp_dir().join(format!("lms-breached-{}", std::process::id()));
+    std::fs::create_dir_all(&breached).unwrap();
+    let digest = hex::encode_upper(Sha1::digest(b"password123456"));
+    let (prefix, suffix) = digest.split_at(5);
+    let range = format!("{}:42\n", suffix);
+    std::fs::write(breached.join(format!("{}.txt", prefix)), range).unwrap();
+
+    let mut cfg = config();
+    cfg.password_policy.min_length = 12;
+    cfg.password_policy.history_size = 2;
+    cfg.password_policy.breached_passwords_dir = Some(breached.to_string_lossy().into());
+
+    let backend = Backend::new();
+    let app = test::init_service(
+        App::new()
+            .configure(|cfg| backend.register(cfg))
+            .app_data(Data::new(cfg))
+            .configure(configure),
+    )
+    .await;
+    let token = register(&app, "reader@example.com", "reader").await;
+
+    let change = |old: &str, new: &str| {
+        TestRequest::put()
+            .uri("/user/password")
+            .insert_header(bearer(&token))
+            .set_json(json!({ "old_password": old, "new_password": new }))
+    };
+    let rejection = |(status, body): (StatusCode, Value)| {
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        body["msg"].as_str().unwrap().to_string()
+    };
+
+    let msg = rejection(call(&app, change(PASSWORD, "too short")).await);
+    assert!(msg.starts_with(PASSWORD_TOO_SHORT), "{}", msg);
+    let msg = rejection(call(&app, change(PASSWORD, "password123456")).await);
+    assert_eq!(msg, PASSWORD_BREACHED);
+    let msg = rejection(call(&app, change(PASSWORD, PASSWORD)).await);
+    assert_eq!(msg, PASSWORD_REUSED);
+    std::fs::remove_dir_all(&breached).unwrap();
+
+    let status = call(&app, change(PASSWORD, "second passphrase")).await.0;
+    assert_eq!(status, StatusCode::OK);
+    let status = call(&app, change("second passphrase", "third passphrase"))
+        .await
+        .0;
+    assert_eq!(status, StatusCode::OK);
+
+    // The history holds two passwords, the current one included.
+    let msg = rejection(call(&app, change("third passphrase", "second passphrase")).await);
+    assert_eq!(msg, PASSWORD_REUSED);
+    let status = call(&app, change("third passphrase", PASSWORD)).await.0;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn impersonation_is_limited_to_patrons_who_can_sign_in() {
+    let backend = Backend::new();
+    let app = app!(backend);
+    let admin = register_admin(&app, &backend, "admin@example.com", "librarian").await;
+    register_admin(&app, &backend, "other@example.com", "colleague").await;
+    register(&app, "reader@example.com", "reader").await;
+    register(&app, "gone@example.com", "gone").await;
+
+    let impersonate = |token: &str, id: &ObjectId| {
+        TestRequest::post()
+            .uri(&format!("/admin/users/{}/impersonate", id))
+            .insert_header(bearer(token))
+    };
+
+    let colleague = find_user(&backend, "other@example.com").await;
+    let (status, body) = call(&app, impersonate(&admin, &colleague.id)).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(body["msg"], CANNOT_IMPERSONATE_ADMIN);
+
+    let gone = find_user(&backend, "gone@example.com").await;
+    backend.users.disable(&gone.id).await.unwrap();
+    let (status, body) = call(&app, impersonate(&admin, &gone.id)).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(body["msg"], ACCOUNT_DISABLED);
+
+    // A suspended patron can be looked at, within the limits of the suspension.
+    let reader = find_user(&backend, "reader@example.com").await;
+    let suspension = Suspension {
+        reason: "overdue fines".into(),
+        suspended_at: DateTime::now(),
+        expires_at: None,
+        suspended_by: colleague.id,
+    };
+    backend
+        .users
+        .suspend(&reader.id, &suspension)
+        .await
+        .unwrap();
+    let (status, body) = call(&app, impersonate(&admin, &reader.id)).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let acting = body["data"]["token"].as_str().unwrap().to_string();
+
+    let req = TestRequest::get()
+        .uri("/user/me")
+        .insert_header(bearer(&acting));
+    let (status, body) = call(&app, req).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["data"]["impersonated"], true);
+
+    let req = TestRequest::post()
+        .uri(&format!("/books/borrow/{}", ObjectId::new()))
+        .insert_header(bearer(&acting));
+    assert_eq!(call(&app, req).await.0, StatusCode::FORBIDDEN);
+
+    // An impersonation token carries no admin rights, so it cannot start another one.
+    let status = call(&app, impersonate(&acting, &reader.id)).await.0;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+}
+
+#[actix_web::test]
+async fn expired_memberships_cannot_borrow() {
+    let backend = Backend::new();
+    let app = app!(backend);
+    let token = register(&app, "reader@example.com", "reader").await;
+    let reader = find_user(&backend, "reader@example.com").await;
+    let book = backend.books.create("Dune", "Frank Herbert").await.unwrap();
+    backend.books.update_stock(&book.id, 1).await.unwrap();
+
+    let yesterday = DateTime::from_millis(DateTime::now().timestamp_millis() - 86_400_000);
+    backend
+        .users
+        .set_membership_expiry(&reader.id, Some(yesterday))
+        .await
+        .unwrap();
+
+    let req = TestRequest::post()
+        .uri(&format!("/books/borrow/{}", book.id))
+        .insert_header(bearer(&token));
+    let (status, body) = call(&app, req).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(body["msg"], MEMBERSHIP_EXPIRED);
+
+    let stock = backend
+        .books
+        .find_by_id(&book.id)
+        .await
+        .unwrap()
+        .unwrap()
+        .stock;
+    assert_eq!(stock, 1);
+}
+
+#[actix_web::test]
+async fn bulk_import_supports_dry_runs_and_upserts() {
+    let backend = Backend::new();
+    let app = app!(backend);
+    let admin = register_admin(&app, &backend, "admin@example.com", "librarian").await;
+    register(&app, "reader@example.com", "reader").await;
+
+    let import = |query: &str, csv: &'static str| {
+        TestRequest::post()
+            .uri(&format!("/admin/users/import?{}", query))
+            .insert_header(bearer(&admin))
+            .insert_header(("Content-Type", "text/csv"))
+            .set_payload(csv)
+    };
+    let csv = "email,username,category\n\
+               new@example.com,newcomer,student\n\
+               reader@example.com,renamed,staff\n";
+
+    let (status, body) = call(&app, import("dry_run=true", csv)).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["data"]["dry_run"], true);
+    assert_eq!(body["data"]["created"], 1);
+    assert_eq!(body["data"]["failed"], 1);
+    assert!(backend
+        .users
+        .find_by_email("new@example.com")
+        .await
+        .unwrap()
+        .is_none());
+
+    let (_, body) = call(&app, import("dry_run=true&upsert=true", csv)).await;
+    assert_eq!(body["data"]["created"], 1);
+    assert_eq!(body["data"]["updated"], 1);
+    assert_eq!(
+        find_user(&backend, "reader@example.com").await.username,
+        "reader"
+    );
+
+    let (status, body) = call(&app, import("upsert=true", csv)).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["data"]["created"], 1);
+    assert_eq!(body["data"]["updated"], 1);
+
+    let created = find_user(&backend, "new@example.com").await;
+    assert_eq!(created.category, PatronCategory::Student);
+    let updated = find_user(&backend, "reader@example.com").await;
+    assert_eq!(updated.username, "renamed");
+    assert_eq!(updated.category, PatronCategory::Staff);
+}
+
+#[actix_web::test]
+async fn family_caps_and_dependent_limits_apply_to_borrowing() {
+    let backend = Backend::new();
+    let app = app!(backend);
+    let guardian = register(&app, "parent@example.com", "parent").await;
+
+    let mut books = Vec::new();
+    for title in ["Dune", "Emma", "Ulysses", "Beloved"] {
+        let book = backend.books.create(title, "Various").await.unwrap();
+        backend.books.update_stock(&book.id, 5).await.unwrap();
+        books.push(book.id);
+    }
+
+    let req = TestRequest::post()
+        .uri("/user/me/dependents")
+        .insert_header(bearer(&guardian))
+        .set_json(json!({ "username": "little one", "loan_limit": 1 }));
+    let (status, body) = call(&app, req).await;
+    assert_eq!(status, StatusCode::CREATED, "{}", body);
+    let child = body["data"]["id"].as_str().unwrap().to_string();
+
+    let borrow_for_child = |book: &ObjectId| {
+        TestRequest::post()
+            .uri(&format!("/user/me/dependents/{}/borrow/{}", child, book))
+            .insert_header(bearer(&guardian))
+    };
+    let update_child = |payload: Value| {
+        TestRequest::put()
+            .uri(&format!("/user/me/dependents/{}", child))
+            .insert_header(bearer(&guardian))
+            .set_json(payload)
+    };
+
+    assert_eq!(
+        call(&app, borrow_for_child(&books[0])).await.0,
+        StatusCode::OK
+    );
+    let (status, body) = call(&app, borrow_for_child(&books[1])).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["msg"], BORROW_LIMIT_REACHED);
+
+    // Leaving the limit out keeps it, an explicit null removes it.
+    let status = call(&app, update_child(json!({ "username": "little two" })))
+        .await
+        .0;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = call(&app, borrow_for_child(&books[1])).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let status = call(&app, update_child(json!({ "loan_limit": null })))
+        .await
+        .0;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        call(&app, borrow_for_child(&books[1])).await.0,
+        StatusCode::OK
+    );
+
+    // The cap counts the dependent's loans against the guardian's too.
+    let req = TestRequest::put()
+        .uri("/user/me/family")
+        .insert_header(bearer(&guardian))
+        .set_json(json!({ "loan_cap": 3 }));
+    assert_eq!(call(&app, req).await.0, StatusCode::OK);
+
+    let borrow = |book: &ObjectId| {
+        TestRequest::post()
+            .uri(&format!("/books/borrow/{}", book))
+            .insert_header(bearer(&guardian))
+    };
+    assert_eq!(call(&app, borrow(&books[2])).await.0, StatusCode::OK);
+    let (status, body) = call(&app, borrow(&books[3])).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["msg"], FAMILY_LIMIT_REACHED);
+    let (_, body) = call(&app, borrow_for_child(&books[3])).await;
+    assert_eq!(body["msg"], FAMILY_LIMIT_REACHED);
+
+    let stock = backend
+        .books
+        .find_by_id(&books[3])
+        .await
+        .unwrap()
+        .unwrap()
+        .stock;
+    assert_eq!(stock, 5);
+}
+
+#[actix_web::test]
+async fn api_keys_are_limited_to_their_scopes() {
+    let backend = Backend::new();
+    let app = app!(backend);
+    let token = register(&app, "reader@example.com", "reader").await;
+
+    let req = TestRequest::post()
+        .uri("/user/api-keys")
+        .insert_header(bearer(&token))
+        .set_json(json!({ "name": "reading list", "scopes": [SCOPE_PROFILE_READ] }));
+    let (status, body) = call(&app, req).await;
+    assert_eq!(status, StatusCode::CREATED);
+    let key = body["data"]["key"].as_str().unwrap().to_string();
+
+    let req = TestRequest::get()
+        .uri("/user/me")
+        .insert_header((API_KEY_HEADER, key.clone()));
+    assert_eq!(call(&app, req).await.0, StatusCode::OK);
+
+    let req = TestRequest::post()
+        .uri(&format!(
+            "/books/borrow/{}",
+            mongodb::bson::oid::ObjectId::new()
+        ))
+        .insert_header((API_KEY_HEADER, key.clone()));
+    let (status, body) = call(&app, req).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(body["msg"], API_KEY_SCOPE_MISSING);
+
+    // The catalogue is public, but a key presented for it needs catalog:read.
+    let req = TestRequest::get()
+        .uri("/books")
+        .insert_header((API_KEY_HEADER, key));
+    assert_eq!(call(&app, req).await.0, StatusCode::FORBIDDEN);
+    assert_eq!(
+        call(&app, TestRequest::get().uri("/books")).await.0,
+        StatusCode::OK
+    );
+}
+
+#[actix_web::test]
+async fn metrics_report_requests_and_catalogue_state() {
+    let backend = Backend::new();
+    let mut cfg = config();
+    cfg.metrics_token = Some("scrape-secret".into());
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(track_requests))
+            .configure(|cfg| backend.register(cfg))
+            .app_data(Data::new(cfg))
+            .configure(configure),
+    )
+    .await;
+
+    let req = TestRequest::post()
+        .uri("/auth/login")
+        .set_json(json!({ "email": "nobody@example.com", "password": PASSWORD }));
+    assert_eq!(call(&app, req).await.0, StatusCode::UNAUTHORIZED);
+    backend.books.create("Dune", "Frank Herbert").await.unwrap();
+
+    let res = test::call_service(&app, TestRequest::get().uri("/metrics").to_request()).await;
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+    let req = TestRequest::get()
+        .uri("/metrics")
+        .insert_header(bearer("scrape-secret"))
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let body = String::from_utf8(test::read_body(res).await.to_vec()).unwrap();
+
+    assert!(body.contains(r#"http_requests_total{method="POST",route="/auth/login",status="401"}"#));
+    assert!(body.contains("library_failed_logins_total"));
+    assert!(body.contains("library_active_loans 0"));
+    assert!(body.contains("library_out_of_stock_titles 1"));
+}
+
+#[actix_web::test]
+async fn requests_beyond_the_budget_are_rejected() {
+    let backend = Backend::new();
+    let app = app!(backend);
+    let limit = config().rate_limits.auth.unwrap().requests;
+    let login = |peer: &str| {
+        TestRequest::post()
+            .uri("/auth/login")
+            .peer_addr(peer.parse().unwrap())
+            .set_json(json!({ "email": "nobody@example.com", "password": PASSWORD }))
+            .to_request()
+    };
+
+    for sent in 1..=limit {
+        let res = test::call_service(&app, login("203.0.113.7:4000")).await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+        let remaining = res.headers().get(RATE_LIMIT_REMAINING_HEADER).unwrap();
+        assert_eq!(remaining.to_str().unwrap(), (limit - sent).to_string());
+    }
+
+    let res = test::call_service(&app, login("203.0.113.7:4001")).await;
+    assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert!(res.headers().contains_key("retry-after"));
+    assert_eq!(res.headers().get(RATE_LIMIT_REMAINING_HEADER).unwrap(), "0");
+
+    // Other clients and other route groups have budgets of their own.
+    let res = test::call_service(&app, login("198.51.100.1:4000")).await;
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    let books = TestRequest::get()
+        .uri("/books")
+        .peer_addr("203.0.113.7:4000".parse().unwrap());
+    assert_eq!(call(&app, books).await.0, StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn rate_limits_follow_a_configuration_reload() {
+    let backend = Backend::new();
+    let app = app!(backend);
+    let login = || {
+        TestRequest::post()
+            .uri("/auth/login")
+            .peer_addr("203.0.113.9:4000".parse().unwrap())
+            .set_json(json!({ "email": "nobody@example.com", "password": PASSWORD }))
+    };
+    assert_eq!(call(&app, login()).await.0, StatusCode::UNAUTHORIZED);
+
+    let mut cfg = config();
+    cfg.rate_limits.auth = Some(RateLimit {
+        requests: 1,
+        window_seconds: 60,
+    });
+    assert_eq!(backend.live.apply(&cfg), ["rate limits"]);
+
+    // The new budget applies to the running app; the earlier request counts against it.
+    assert_eq!(call(&app, login()).await.0, StatusCode::TOO_MANY_REQUESTS);
+}
+
+/// A dependency that is either up or never answers.
+struct StubCheck {
+    name: &'static str,
+    hangs: bool,
+}
+
+#[async_trait]
+impl HealthCheck for StubCheck {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    async fn ping(&self) -> Result<(), AppError> {
+        if self.hangs {
+            std::future::pending::<()>().await;
+        }
+        Ok(())
+    }
+}
+
+#[actix_web::test]
+async fn readiness_degrades_when_a_dependency_is_down() {
+    let mut backend = Backend::new();
+    let app = app!(backend);
+    let (status, body) = call(&app, TestRequest::get().uri("/health/ready")).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["status"], "ok");
+    assert_eq!(body["build"]["version"], env!("CARGO_PKG_VERSION"));
+
+    backend.health = HealthChecks(vec![
+        Arc::new(StubCheck {
+            name: "mongodb",
+            hangs: false,
+        }),
+        Arc::new(StubCheck {
+            name: "redis",
+            hangs: true,
+        }),
+    ]);
+    let app = app!(backend);
+    for uri in ["/health", "/health/ready"] {
+        let (status, body) = call(&app, TestRequest::get().uri(uri)).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE, "{}", body);
+        assert_eq!(body["status"], "degraded");
+        assert_eq!(body["dependencies"]["mongodb"]["status"], "up");
+        assert_eq!(body["dependencies"]["redis"]["status"], "down");
+        assert_eq!(body["dependencies"]["redis"]["error"], "timed out");
+    }
+
+    // Liveness ignores dependencies.
+    let (status, _) = call(&app, TestRequest::get().uri("/health/live")).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn cookie_sessions_keep_the_token_out_of_the_body() {
+    let backend = Backend::new();
+    let mut cfg = config();
+    cfg.session_cookie = Some(SessionCookieConfig {
+        name: "lms_session".into(),
+        csrf_name: "lms_csrf".into(),
+        secure: true,
+        same_site: SameSite::Lax,
+        domain: None,
+    });
+    let app = test::init_service(
+        App::new()
+            .configure(|cfg| backend.register(cfg))
+            .app_data(Data::new(cfg))
+            .configure(configure),
+    )
+    .await;
+
+    let req = TestRequest::post().uri("/auth/register").set_json(
+        json!({ "email": "reader@example.com", "username": "reader", "password": PASSWORD }),
+    );
+    let res = test::call_service(&app, req.to_request()).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let session = res
+        .response()
+        .cookies()
+        .find(|c| c.name() == "lms_session")
+        .expect("the session cookie must be set")
+        .into_owned();
+    let body: Value = test::read_body_json(res).await;
+    assert!(body.get("data").is_none(), "{}", body);
+
+    let req = TestRequest::get().uri("/user/me").cookie(session);
+    assert_eq!(call(&app, req).await.0, StatusCode::OK);
+}
+
+#[test]
+fn config_file_settings_yield_to_the_environment() {
+    config();
+    let path = std::env::temp_dir().join(format!("lms-config-{}.toml", std::process::id()));
+    let load = |contents: &str| {
+        std::fs::write(&path, contents).unwrap();
+        AppConfig::load(&ConfigSource::new(Some(path.clone())))
+    };
+
+    let cfg = load(
+        r#"
+        [server]
+        port = 9090
+
+        [jwt]
+        secret = "short"
+
+        [circulation]
+        membership_duration_days = 30
+        loan_limits = { child = 3 }
+        "#,
+    )
+    .expect("file settings must be valid");
+    assert_eq!(cfg.port, 9090);
+    assert_eq!(cfg.membership_duration_days, 30);
+    assert_eq!(cfg.loan_limit(PatronCategory::Child), 3);
+
+    // Every problem is reported, not only the first.
+    let errors = load(
+        r#"
+        [server]
+        port = "eighty"
+        hots = "localhost"
+
+        [circulation]
+        membership_duration_days = -1
+        "#,
+    )
+    .err()
+    .expect("invalid settings must be rejected");
+    std::fs::remove_file(&path).unwrap();
+    for expected in [
+        "unknown setting server.hots",
+        "APP_PORT must be a valid port number",
+        "MEMBERSHIP_DURATION_DAYS must not be negative",
+    ] {
+        assert!(errors.contains(expected), "{}", errors);
+    }
+}
@@ -1,19 +1,37 @@
-use crate::auth::AuthenticatedUser;
+use crate::auth::{clear_session_cookies, AuthenticatedUser};
+use crate::config::app_config::AppConfig;
 use crate::constants::*;
-use crate::database::mongodb::{BookRepository, UserRepository};
+use crate::database::repository::{ApiKeyRepository, BookRepository, UserRepository};
+use crate::database::store::{EmailVerificationStore, TokenBlacklist};
 use crate::errors::AppError;
-use crate::models::request::{UpdateEmailRequest, UpdatePasswordRequest, UpdateUsernameRequest};
-use crate::models::response::{AboutMe, BookDetail, Response};
+use crate::handlers::api_key::{api_key_info, issue_api_key};
+use crate::handlers::family::{
+    borrow_for_dependent, create_dependent, get_family, return_for_dependent, update_dependent,
+    update_family,
+};
+use crate::handlers::verification::send_verification_email;
+use crate::models::request::{
+    CreateApiKeyRequest, DeleteAccountRequest, UpdateEmailRequest, UpdatePasswordRequest,
+    UpdateUsernameRequest,
+};
+use crate::models::response::{
+    AboutMe, AccountExport, ApiKeyInfo, BookDetail, MembershipInfo, ProfileExport, Response,
+    SuspensionInfo,
+};
+use crate::models::user::User;
+use crate::utils::mailer::Mailer;
 use crate::utils::password::{hash_password, verify_password};
-use actix_web::web::{scope, Data, Json};
-use actix_web::{get, put, HttpResponse};
+use crate::utils::password_policy::{next_password_history, validate_new_password};
+use actix_web::web::{scope, Data, Json, Path};
+use actix_web::{delete, get, post, put, HttpResponse};
 use mongodb::bson::oid::ObjectId;
+use mongodb::bson::DateTime;
 use validator::Validate;
 
 #[get("/me")]
 pub async fn get_me(
-    user_repo: Data<UserRepository>,
-    book_repo: Data<BookRepository>,
+    user_repo: Data<dyn UserRepository>,
+    book_repo: Data<dyn BookRepository>,
     user: AuthenticatedUser,
 ) -> Result<HttpResponse, AppError> {
     let uid = ObjectId::parse_str(&user.user_id)?;
@@ -22,10 +40,59 @@ pub async fn get_me(
         .await?
         .ok_or(AppError::Unauthorized(USER_NOT_FOUND.into()))?;
 
-    let mut borrowed_books = Vec::new();
-    for book_id in &user_doc.borrowed_books {
+    let borrowed_books =
+        borrowed_book_details(book_repo.get_ref(), &user_doc.borrowed_books).await?;
+    let suspension = active_suspension_info(&user_doc);
+    let membership = membership_info(&user_doc);
+
+    Ok(HttpResponse::Ok().json(Response {
+        msg: PROFILE_FETCHED.into(),
+        data: Some(AboutMe {
+            email: user_doc.email,
+            email_verified: user_doc.email_verified,
+            pending_email: user_doc.pending_email,
+            username: user_doc.username,
+            impersonated: user.impersonator.is_some(),
+            impersonated_by: user.impersonator,
+            suspension,
+            category: user_doc.category,
+            membership,
+            borrowed_books,
+        }),
+    }))
+}
+
+pub(crate) fn membership_info(user: &User) -> MembershipInfo {
+    let format = |dt: DateTime| dt.try_to_rfc3339_string().unwrap_or_default();
+    MembershipInfo {
+        started_at: user.membership_started_at.map(format),
+        expires_at: user.membership_expires_at.map(format),
+        expired: user.membership_expired(),
+    }
+}
+
+/// The user's suspension, unless there is none or it has run out.
+pub(crate) fn active_suspension_info(user: &User) -> Option<SuspensionInfo> {
+    let format = |dt: DateTime| dt.try_to_rfc3339_string().unwrap_or_default();
+    user.suspension
+        .as_ref()
+        .filter(|suspension| suspension.is_active())
+        .map(|suspension| SuspensionInfo {
+            reason: suspension.reason.clone(),
+            suspended_at: format(suspension.suspended_at),
+            expires_at: suspension.expires_at.map(format),
+            suspended_by: suspension.suspended_by.to_hex(),
+        })
+}
+
+pub(crate) async fn borrowed_book_details(
+    book_repo: &dyn BookRepository,
+    book_ids: &[ObjectId],
+) -> Result<Vec<BookDetail>, AppError> {
+    let mut books = Vec::new();
+    for book_id in book_ids {
         if let Some(book) = book_repo.find_by_id(book_id).await? {
-            borrowed_books.push(BookDetail {
+            books.push(BookDetail {
                 id: book.id.to_hex(),
                 title: book.title,
                 author: book.author,
@@ -33,23 +100,152 @@ pub async fn get_me(
             });
         }
     }
+    Ok(books)
+}
 
-    Ok(HttpResponse::Ok().json(Response {
-        msg: PROFILE_FETCHED.into(),
-        data: Some(AboutMe {
+#[get("/me/export")]
+async fn export_me(
+    user_repo: Data<dyn UserRepository>,
+    book_repo: Data<dyn BookRepository>,
+    key_repo: Data<dyn ApiKeyRepository>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, AppError> {
+    user.ensure_not_impersonated()?;
+
+    let uid = ObjectId::parse_str(&user.user_id)?;
+    let user_doc = user_repo
+        .find_by_id(&uid)
+        .await?
+        .ok_or(AppError::Unauthorized(USER_NOT_FOUND.into()))?;
+
+    let format = |dt: DateTime| dt.try_to_rfc3339_string().unwrap_or_default();
+    let api_keys = key_repo
+        .find_by_user(&uid)
+        .await?
+        .into_iter()
+        .map(api_key_info)
+        .collect();
+    let suspension = active_suspension_info(&user_doc);
+    let membership = membership_info(&user_doc);
+
+    let export = AccountExport {
+        exported_at: format(DateTime::now()),
+        borrowed_books: borrowed_book_details(book_repo.get_ref(), &user_doc.borrowed_books)
+            .await?,
+        api_keys,
+        profile: ProfileExport {
+            id: user_doc.id.to_hex(),
+            created_at: format(user_doc.id.timestamp()),
             email: user_doc.email,
+            email_verified: user_doc.email_verified,
+            pending_email: user_doc.pending_email,
             username: user_doc.username,
-            borrowed_books,
-        }),
+            is_admin: user_doc.is_admin,
+            disabled: user_doc.disabled,
+            category: user_doc.category,
+            membership,
+            suspension,
+            oidc_subject: user_doc.oidc_subject,
+            ldap_dn: user_doc.ldap_dn,
+            password_changed_at: user_doc.password_changed_at.map(format),
+        },
+    };
+
+    Ok(HttpResponse::Ok()
+        .insert_header((
+            "Content-Disposition",
+            format!("attachment; filename=\"account-{}.json\"", user.user_id),
+        ))
+        .json(Response {
+            msg: ACCOUNT_EXPORTED.into(),
+            data: Some(export),
+        }))
+}
+
+/// Anonymizes the account after the password is re-entered, or after a recent
+/// sign-in for accounts without one. Loans are the only records tied to a patron, so
+/// the account must have none outstanding.
+#[delete("/me")]
+async fn delete_me(
+    user_repo: Data<dyn UserRepository>,
+    key_repo: Data<dyn ApiKeyRepository>,
+    blacklist: Data<dyn TokenBlacklist>,
+    cfg: Data<AppConfig>,
+    user: AuthenticatedUser,
+    payload: Json<DeleteAccountRequest>,
+) -> Result<HttpResponse, AppError> {
+    user.ensure_not_impersonated()?;
+
+    let uid = ObjectId::parse_str(&user.user_id)?;
+    let user_doc = user_repo
+        .find_by_id(&uid)
+        .await?
+        .ok_or(AppError::Unauthorized(USER_NOT_FOUND.into()))?;
+
+    if user_doc.password_hash.is_empty() {
+        let age = DateTime::now().timestamp_millis() / 1000 - user.iat as i64;
+        if age > RECENT_LOGIN_MAX_AGE_SECONDS {
+            return Err(AppError::Unauthorized(RECENT_LOGIN_REQUIRED.into()));
+        }
+    } else {
+        let password = payload.password.as_deref().unwrap_or_default();
+        verify_password(&user_doc.password_hash, password)
+            .await
+            .map_err(|_| AppError::Unauthorized(INVALID_PASSWORD.into()))?;
+    }
+
+    if !user_doc.borrowed_books.is_empty() {
+        return Err(AppError::Conflict(ACCOUNT_HAS_BORROWED_BOOKS.into()));
+    }
+
+    release_dependents(user_repo.get_ref(), &uid).await?;
+
+    key_repo.delete_by_user(&uid).await?;
+    user_repo.anonymize(&uid).await?;
+
+    let exp_seconds = (user.exp as i64) - DateTime::now().timestamp_millis() / 1000;
+    if exp_seconds > 0 {
+        blacklist.add_token(&user.token, exp_seconds).await?;
+    }
+
+    tracing::info!(user_id = %uid, "account anonymized at the user's request");
+
+    let mut response = HttpResponse::Ok();
+    clear_session_cookies(&mut response, &cfg);
+    Ok(response.json(Response::<()> {
+        msg: ACCOUNT_DELETED.into(),
+        data: None,
     }))
 }
 
+/// Unlinks dependents that have their own login so the account can be deleted.
+/// Managed dependents would be left unreachable, so their guardian cannot go.
+pub(crate) async fn release_dependents(
+    user_repo: &dyn UserRepository,
+    guardian_id: &ObjectId,
+) -> Result<(), AppError> {
+    let dependents = user_repo.find_dependents(guardian_id).await?;
+    if dependents.iter().any(User::is_managed) {
+        return Err(AppError::Conflict(GUARDIAN_HAS_DEPENDENTS.into()));
+    }
+
+    for dependent in dependents {
+        user_repo.set_guardian(&dependent.id, None).await?;
+    }
+    Ok(())
+}
+
 #[put("/email")]
 async fn update_email(
-    user_repo: Data<UserRepository>,
+    user_repo: Data<dyn UserRepository>,
+    verification_store: Data<dyn EmailVerificationStore>,
+    mailer: Data<Mailer>,
+    cfg: Data<AppConfig>,
     user: AuthenticatedUser,
     payload: Json<UpdateEmailRequest>,
 ) -> Result<HttpResponse, AppError> {
+    user.ensure_not_impersonated()?;
+
     payload
         .validate()
         .map_err(|e| AppError::BadRequest(e.to_string()))?;
@@ -59,17 +255,82 @@ async fn update_email(
     }
 
     let uid = ObjectId::parse_str(&user.user_id)?;
-    user_repo.update_email(&uid, &payload.email).await?;
+    let current = user_repo
+        .find_by_id(&uid)
+        .await?
+        .ok_or(AppError::Unauthorized(USER_NOT_FOUND.into()))?;
+
+    if !verification_store
+        .try_start_cooldown(&user.user_id, cfg.email_verification_resend_cooldown_seconds)
+        .await?
+    {
+        return Err(AppError::TooManyRequests(VERIFICATION_RESEND_TOO_SOON.into()));
+    }
+
+    // The address only changes once the confirmation link sent to it is used.
+    user_repo.set_pending_email(&uid, &payload.email).await?;
+    send_verification_email(
+        verification_store.get_ref(),
+        &mailer,
+        &cfg,
+        &current,
+        &payload.email,
+    )
+    .await?;
+
+    Ok(HttpResponse::Ok().json(Response::<()> {
+        msg: EMAIL_VERIFICATION_SENT.into(),
+        data: None,
+    }))
+}
+
+#[post("/email/verification")]
+async fn resend_verification(
+    user_repo: Data<dyn UserRepository>,
+    verification_store: Data<dyn EmailVerificationStore>,
+    mailer: Data<Mailer>,
+    cfg: Data<AppConfig>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, AppError> {
+    user.ensure_not_impersonated()?;
+
+    let uid = ObjectId::parse_str(&user.user_id)?;
+    let current = user_repo
+        .find_by_id(&uid)
+        .await?
+        .ok_or(AppError::Unauthorized(USER_NOT_FOUND.into()))?;
+
+    let email = match current.pending_email {
+        Some(ref pending) => pending.clone(),
+        None if !current.email_verified => current.email.clone(),
+        None => return Err(AppError::BadRequest(EMAIL_ALREADY_VERIFIED.into())),
+    };
+
+    if !verification_store
+        .try_start_cooldown(&user.user_id, cfg.email_verification_resend_cooldown_seconds)
+        .await?
+    {
+        return Err(AppError::TooManyRequests(VERIFICATION_RESEND_TOO_SOON.into()));
+    }
+
+    send_verification_email(
+        verification_store.get_ref(),
+        &mailer,
+        &cfg,
+        &current,
+        &email,
+    )
+    .await?;
 
     Ok(HttpResponse::Ok().json(Response::<()> {
-        msg: EMAIL_UPDATED.into(),
+        msg: EMAIL_VERIFICATION_SENT.into(),
         data: None,
     }))
 }
 
 #[put("/username")]
 async fn update_username(
-    user_repo: Data<UserRepository>,
+    user_repo: Data<dyn UserRepository>,
     user: AuthenticatedUser,
     payload: Json<UpdateUsernameRequest>,
 ) -> Result<HttpResponse, AppError> {
@@ -88,10 +349,13 @@ async fn update_username(
 
 #[put("/password")]
 async fn update_password(
-    user_repo: Data<UserRepository>,
+    user_repo: Data<dyn UserRepository>,
+    cfg: Data<AppConfig>,
     user: AuthenticatedUser,
     payload: Json<UpdatePasswordRequest>,
 ) -> Result<HttpResponse, AppError> {
+    user.ensure_not_impersonated()?;
+
     payload
         .validate()
         .map_err(|e| AppError::BadRequest(e.to_string()))?;
@@ -103,10 +367,21 @@ async fn update_password(
         .ok_or(AppError::Unauthorized(USER_NOT_FOUND.into()))?;
 
     verify_password(&current.password_hash, &payload.old_password)
+        .await
         .map_err(|_| AppError::Unauthorized(INVALID_OLD_PASSWORD.into()))?;
 
-    let new_hash = hash_password(&payload.new_password)?;
-    user_repo.update_password(&uid, &new_hash).await?;
+    validate_new_password(
+        &cfg,
+        &payload.new_password,
+        &current.email,
+        &current.username,
+        Some(&current),
+    )
+    .await?;
+
+    let new_hash = hash_password(&cfg, &payload.new_password).await?;
+    let history = next_password_history(&cfg, &current);
+    user_repo.change_password(&uid, &new_hash, &history).await?;
 
     Ok(HttpResponse::Ok().json(Response::<()> {
         msg: PASSWORD_UPDATED.into(),
@@ -114,10 +389,85 @@ async fn update_password(
     }))
 }
 
+#[post("/api-keys")]
+async fn create_api_key(
+    key_repo: Data<dyn ApiKeyRepository>,
+    user: AuthenticatedUser,
+    payload: Json<CreateApiKeyRequest>,
+) -> Result<HttpResponse, AppError> {
+    user.ensure_not_impersonated()?;
+
+    let uid = ObjectId::parse_str(&user.user_id)?;
+    let info = issue_api_key(key_repo.get_ref(), uid, &payload).await?;
+
+    Ok(HttpResponse::Created().json(Response {
+        msg: API_KEY_CREATED.into(),
+        data: Some(info),
+    }))
+}
+
+#[get("/api-keys")]
+async fn get_api_keys(
+    key_repo: Data<dyn ApiKeyRepository>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, AppError> {
+    let uid = ObjectId::parse_str(&user.user_id)?;
+    let infos: Vec<ApiKeyInfo> = key_repo
+        .find_by_user(&uid)
+        .await?
+        .into_iter()
+        .map(api_key_info)
+        .collect();
+
+    Ok(HttpResponse::Ok().json(Response {
+        msg: API_KEYS_FETCHED.into(),
+        data: Some(infos),
+    }))
+}
+
+#[delete("/api-keys/{id}")]
+async fn revoke_api_key(
+    key_repo: Data<dyn ApiKeyRepository>,
+    user: AuthenticatedUser,
+    id: Path<String>,
+) -> Result<HttpResponse, AppError> {
+    user.ensure_not_impersonated()?;
+
+    let uid = ObjectId::parse_str(&user.user_id)?;
+    let key_id = ObjectId::parse_str(id.as_str())
+        .map_err(|_| AppError::BadRequest(INVALID_API_KEY_ID.into()))?;
+
+    // Keys of other users are reported as missing rather than forbidden.
+    key_repo
+        .find_by_id(&key_id)
+        .await?
+        .filter(|key| key.user_id == uid)
+        .ok_or_else(|| AppError::NotFound(API_KEY_NOT_FOUND.into()))?;
+
+    key_repo.delete_by_id(&key_id).await?;
+
+    Ok(HttpResponse::Ok().json(Response::<()> {
+        msg: API_KEY_REVOKED.into(),
+        data: None,
+    }))
+}
+
 pub fn user_scope() -> actix_web::Scope {
     scope("/user")
         .service(get_me)
+        .service(export_me)
+        .service(delete_me)
         .service(update_email)
+        .service(resend_verification)
         .service(update_username)
         .service(update_password)
+        .service(create_api_key)
+        .service(get_api_keys)
+        .service(revoke_api_key)
+        .service(get_family)
+        .service(update_family)
+        .service(create_dependent)
+        .service(update_dependent)
+        .service(borrow_for_dependent)
+        .service(return_for_dependent)
 }
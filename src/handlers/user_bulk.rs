@@ -0,0 +1,425 @@
+use crate::auth::AdminUser;
+use crate::config::app_config::AppConfig;
+use crate::constants::*;
+use crate::database::repository::UserRepository;
+use crate::database::store::PasswordResetStore;
+use crate::errors::AppError;
+use crate::handlers::admin::user_info;
+use crate::models::request::{
+    CreateUserRequest, ExportFormat, ExportUsersQuery, ImportCredentials, ImportUserRow,
+    ImportUsersQuery,
+};
+use crate::models::response::{ImportReport, ImportRowResult, ImportRowStatus, Response};
+use crate::models::user::User;
+use crate::utils::mailer::Mailer;
+use crate::utils::membership::new_membership_expiry;
+use crate::utils::password::hash_password;
+use crate::utils::password_policy::{
+    generate_password, next_password_history, validate_new_password,
+};
+use crate::utils::random_token::{generate_random_token, hash_random_token};
+use actix_web::web::{Bytes, Data, Query};
+use actix_web::{get, post, HttpMessage, HttpRequest, HttpResponse};
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::DateTime;
+use std::collections::HashSet;
+use validator::Validate;
+
+/// What happened to a row that was accepted.
+struct RowOutcome {
+    status: ImportRowStatus,
+    password: Option<String>,
+    invitee: Option<User>,
+}
+
+/// Creates or updates users from a CSV or JSON upload. Every row is validated with
+/// the same rules as `POST /admin/users`, and a failing row never stops the others.
+#[post("/users/import")]
+#[allow(clippy::too_many_arguments)]
+async fn import_users(
+    admin: AdminUser,
+    user_repo: Data<dyn UserRepository>,
+    reset_store: Data<dyn PasswordResetStore>,
+    mailer: Data<Mailer>,
+    cfg: Data<AppConfig>,
+    req: HttpRequest,
+    query: Query<ImportUsersQuery>,
+    body: Bytes,
+) -> Result<HttpResponse, AppError> {
+    let rows = parse_rows(req.content_type(), &body)?;
+    if rows.len() > BULK_IMPORT_MAX_ROWS {
+        return Err(AppError::BadRequest(format!(
+            "{} (maximum {})",
+            TOO_MANY_IMPORT_ROWS, BULK_IMPORT_MAX_ROWS
+        )));
+    }
+
+    let mut seen = HashSet::new();
+    let mut invitees = Vec::new();
+    let mut report = ImportReport {
+        dry_run: query.dry_run,
+        created: 0,
+        updated: 0,
+        failed: 0,
+        rows: Vec::with_capacity(rows.len()),
+    };
+
+    for (index, row) in rows.into_iter().enumerate() {
+        let email = row.as_ref().ok().map(|row| row.email.clone());
+        let outcome = match row {
+            Ok(row) => import_row(user_repo.get_ref(), &cfg, &query, &mut seen, row)
+                .await
+                .map_err(row_error),
+            Err(e) => Err(e),
+        };
+
+        let result = match outcome {
+            Ok(outcome) => {
+                match outcome.status {
+                    ImportRowStatus::Created => report.created += 1,
+                    _ => report.updated += 1,
+                }
+                let invited = outcome.invitee.is_some();
+                invitees.extend(outcome.invitee);
+                ImportRowResult {
+                    row: index + 1,
+                    email,
+                    status: outcome.status,
+                    error: None,
+                    password: outcome.password,
+                    invited,
+                }
+            }
+            Err(error) => {
+                report.failed += 1;
+                ImportRowResult {
+                    row: index + 1,
+                    email,
+                    status: ImportRowStatus::Failed,
+                    error: Some(error),
+                    password: None,
+                    invited: false,
+                }
+            }
+        };
+        report.rows.push(result);
+    }
+
+    tracing::info!(
+        admin_id = %admin.user_id,
+        dry_run = query.dry_run,
+        "user import: {} created, {} updated, {} failed",
+        report.created,
+        report.updated,
+        report.failed
+    );
+
+    if !invitees.is_empty() {
+        actix_web::rt::spawn(async move {
+            for user in invitees {
+                if let Err(e) = send_invite(reset_store.get_ref(), &mailer, &cfg, &user).await {
+                    tracing::error!("failed to send invite to user {}: {:?}", user.id, e);
+                }
+            }
+        });
+    }
+
+    Ok(HttpResponse::Ok().json(Response {
+        msg: USERS_IMPORTED.into(),
+        data: Some(report),
+    }))
+}
+
+async fn import_row(
+    user_repo: &dyn UserRepository,
+    cfg: &AppConfig,
+    query: &ImportUsersQuery,
+    seen: &mut HashSet<String>,
+    row: ImportUserRow,
+) -> Result<RowOutcome, AppError> {
+    let request = CreateUserRequest {
+        email: row.email,
+        username: row.username,
+        password: row.password.clone().unwrap_or_default(),
+        is_admin: row.is_admin.unwrap_or(false),
+        category: row.category,
+    };
+    let password = row.password;
+    request
+        .validate()
+        .map_err(|e| AppError::BadRequest(e.to_string()))?;
+
+    if !seen.insert(request.email.to_lowercase()) {
+        return Err(AppError::BadRequest(DUPLICATE_IMPORT_ROW.into()));
+    }
+
+    let existing = user_repo.find_by_email(&request.email).await?;
+    if let Some(ref password) = password {
+        validate_new_password(
+            cfg,
+            password,
+            &request.email,
+            &request.username,
+            existing.as_ref(),
+        )
+        .await?;
+    }
+
+    match existing {
+        Some(user) => {
+            if !query.upsert {
+                return Err(AppError::Conflict(EMAIL_ALREADY_EXISTS.into()));
+            }
+            if user.deleted_at.is_some() {
+                return Err(AppError::Conflict(USER_ALREADY_DELETED.into()));
+            }
+
+            if !query.dry_run {
+                update_user(user_repo, cfg, &user, &request, row.is_admin, password).await?;
+            }
+            Ok(RowOutcome {
+                status: ImportRowStatus::Updated,
+                password: None,
+                invitee: None,
+            })
+        }
+        None => create_user(user_repo, cfg, query, request, password).await,
+    }
+}
+
+async fn update_user(
+    user_repo: &dyn UserRepository,
+    cfg: &AppConfig,
+    user: &User,
+    request: &CreateUserRequest,
+    is_admin: Option<bool>,
+    password: Option<String>,
+) -> Result<(), AppError> {
+    if request.username != user.username {
+        user_repo
+            .update_username(&user.id, &request.username)
+            .await?;
+    }
+    if let Some(category) = request
+        .category
+        .filter(|category| *category != user.category)
+    {
+        user_repo.set_category(&user.id, category).await?;
+    }
+    if let Some(is_admin) = is_admin.filter(|is_admin| *is_admin != user.is_admin) {
+        user_repo.set_admin(&user.id, is_admin).await?;
+    }
+    if let Some(ref password) = password {
+        let password_hash = hash_password(cfg, password).await?;
+        let history = next_password_history(cfg, user);
+        user_repo
+            .change_password(&user.id, &password_hash, &history)
+            .await?;
+    }
+    Ok(())
+}
+
+async fn create_user(
+    user_repo: &dyn UserRepository,
+    cfg: &AppConfig,
+    query: &ImportUsersQuery,
+    request: CreateUserRequest,
+    password: Option<String>,
+) -> Result<RowOutcome, AppError> {
+    let (password, generated) = match password {
+        Some(password) => (Some(password), false),
+        None if query.credentials == ImportCredentials::Generate => {
+            (Some(generate_password(cfg)), true)
+        }
+        None => (None, false),
+    };
+
+    if query.dry_run {
+        return Ok(RowOutcome {
+            status: ImportRowStatus::Created,
+            password: None,
+            invitee: None,
+        });
+    }
+
+    // Invited users get no usable password until they follow the emailed link.
+    let password_hash = match password {
+        Some(ref password) => hash_password(cfg, password).await?,
+        None => String::new(),
+    };
+
+    let user = User {
+        id: ObjectId::new(),
+        email: request.email,
+        username: request.username,
+        password_changed_at: (!password_hash.is_empty()).then(DateTime::now),
+        password_hash,
+        password_history: Vec::new(),
+        email_verified: false,
+        pending_email: None,
+        oidc_subject: None,
+        ldap_dn: None,
+        disabled: false,
+        suspension: None,
+        deleted_at: None,
+        category: request.category.unwrap_or_default(),
+        membership_started_at: Some(DateTime::now()),
+        membership_expires_at: new_membership_expiry(cfg),
+        guardian_id: None,
+        loan_limit: None,
+        family_loan_cap: None,
+        is_admin: request.is_admin,
+        token_version: 0,
+        borrowed_books: Vec::new(),
+    };
+    user_repo.create(&user).await?;
+
+    let invited = password.is_none();
+    Ok(RowOutcome {
+        status: ImportRowStatus::Created,
+        password: password.filter(|_| generated),
+        invitee: invited.then_some(user),
+    })
+}
+
+/// Splits an upload into rows. A row that cannot be decoded becomes an error entry
+/// rather than failing the whole import.
+fn parse_rows(
+    content_type: &str,
+    body: &[u8],
+) -> Result<Vec<Result<ImportUserRow, String>>, AppError> {
+    match content_type {
+        "text/csv" => {
+            let mut reader = csv::ReaderBuilder::new()
+                .trim(csv::Trim::All)
+                .from_reader(body);
+            Ok(reader
+                .deserialize::<ImportUserRow>()
+                .map(|row| row.map_err(|e| e.to_string()))
+                .collect())
+        }
+        "application/json" => {
+            let values: Vec<serde_json::Value> =
+                serde_json::from_slice(body).map_err(|e| AppError::BadRequest(e.to_string()))?;
+            Ok(values
+                .into_iter()
+                .map(|value| serde_json::from_value(value).map_err(|e| e.to_string()))
+                .collect())
+        }
+        _ => Err(AppError::BadRequest(UNSUPPORTED_IMPORT_FORMAT.into())),
+    }
+}
+
+/// The message reported for a failed row. Internal errors are logged, not exposed.
+fn row_error(e: AppError) -> String {
+    match e {
+        AppError::BadRequest(msg)
+        | AppError::Unauthorized(msg)
+        | AppError::Forbidden(msg)
+        | AppError::NotFound(msg)
+        | AppError::Conflict(msg)
+        | AppError::TooManyRequests(msg) => msg,
+        e => {
+            tracing::error!("user import row failed: {:?}", e);
+            INTERNAL_SERVER_ERROR.into()
+        }
+    }
+}
+
+async fn send_invite(
+    reset_store: &dyn PasswordResetStore,
+    mailer: &Mailer,
+    cfg: &AppConfig,
+    user: &User,
+) -> Result<(), AppError> {
+    let token = generate_random_token();
+    reset_store
+        .store(
+            &hash_random_token(&token),
+            &user.id.to_hex(),
+            cfg.invite_ttl_hours * 60 * 60,
+        )
+        .await?;
+
+    let link = match cfg.public_url {
+        Some(ref url) => format!("{}/reset-password?token={}", url, token),
+        None => format!("Set-password token: {}", token),
+    };
+    let body = format!(
+        "Hello {},\n\n\
+         A library account has been created for you.\n\
+         Use the following to choose your password within {} hours:\n\n\
+         {}\n",
+        user.username, cfg.invite_ttl_hours, link
+    );
+
+    mailer.send(&user.email, INVITE_EMAIL_SUBJECT, body).await
+}
+
+#[get("/users/export")]
+async fn export_users(
+    _admin: AdminUser,
+    user_repo: Data<dyn UserRepository>,
+    query: Query<ExportUsersQuery>,
+) -> Result<HttpResponse, AppError> {
+    let users = user_repo.find_all().await?;
+
+    match query.format {
+        ExportFormat::Json => Ok(HttpResponse::Ok()
+            .insert_header(("Content-Disposition", "attachment; filename=\"users.json\""))
+            .json(Response {
+                msg: USERS_EXPORTED.into(),
+                data: Some(users.into_iter().map(user_info).collect::<Vec<_>>()),
+            })),
+        ExportFormat::Csv => {
+            let body = users_csv(&users).map_err(|e| {
+                tracing::error!("failed to write user export: {}", e);
+                AppError::Internal
+            })?;
+            Ok(HttpResponse::Ok()
+                .content_type("text/csv; charset=utf-8")
+                .insert_header(("Content-Disposition", "attachment; filename=\"users.csv\""))
+                .body(body))
+        }
+    }
+}
+
+/// The columns start with the ones the import reads, so an export can be edited and
+/// uploaded again with `upsert=true`. Accounts without an email, managed dependents
+/// and anonymized ones, cannot be imported and are left out.
+fn users_csv(users: &[User]) -> Result<Vec<u8>, csv::Error> {
+    let format = |dt: Option<DateTime>| {
+        dt.map(|dt| dt.try_to_rfc3339_string().unwrap_or_default())
+            .unwrap_or_default()
+    };
+
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record([
+        "email",
+        "username",
+        "is_admin",
+        "category",
+        "id",
+        "email_verified",
+        "disabled",
+        "membership_expires_at",
+        "deleted_at",
+    ])?;
+    for user in users.iter().filter(|user| !user.email.is_empty()) {
+        writer.write_record([
+            user.email.clone(),
+            user.username.clone(),
+            user.is_admin.to_string(),
+            user.category.to_string(),
+            user.id.to_hex(),
+            user.email_verified.to_string(),
+            user.disabled.to_string(),
+            format(user.membership_expires_at),
+            format(user.deleted_at),
+        ])?;
+    }
+
+    writer
+        .into_inner()
+        .map_err(|e| csv::Error::from(e.into_error()))
+}
@@ -0,0 +1,41 @@
+use crate::config::app_config::AppConfig;
+use crate::constants::EMAIL_VERIFICATION_EMAIL_SUBJECT;
+use crate::database::store::EmailVerificationStore;
+use crate::errors::AppError;
+use crate::models::user::User;
+use crate::utils::mailer::Mailer;
+use crate::utils::random_token::{generate_random_token, hash_random_token};
+
+/// Emails a confirmation token for `email`, which is either the user's current
+/// address or the pending address they asked to switch to.
+pub(crate) async fn send_verification_email(
+    store: &dyn EmailVerificationStore,
+    mailer: &Mailer,
+    cfg: &AppConfig,
+    user: &User,
+    email: &str,
+) -> Result<(), AppError> {
+    let token = generate_random_token();
+    store
+        .store(
+            &hash_random_token(&token),
+            &user.id.to_hex(),
+            email,
+            cfg.email_verification_ttl_hours * 3600,
+        )
+        .await?;
+
+    let link = match cfg.public_url {
+        Some(ref url) => format!("{}/verify-email?token={}", url, token),
+        None => format!("Verification token: {}", token),
+    };
+    let body = format!(
+        "Hello {},\n\n\
+         Please confirm that {} is your email address within {} hours:\n\n\
+         {}\n\n\
+         If you did not request this, you can ignore this email.\n",
+        user.username, email, cfg.email_verification_ttl_hours, link
+    );
+
+    mailer.send(email, EMAIL_VERIFICATION_EMAIL_SUBJECT, body).await
+}
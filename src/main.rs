@@ -7,79 +7,196 @@ mod handlers;
 mod models;
 mod utils;
 
+use crate::auth::{run_directory_sync, LdapDirectory, OidcProvider};
 use crate::config::app_config::AppConfig;
-use crate::config::rustls_config::load_rustls_config;
-use crate::database::mongodb::{init_mongodb, BookRepository, UserRepository};
-use crate::database::redis::{init_redis, TokenBlacklist};
-use crate::handlers::{admin_scope, auth_scope, book_scope, health_check, user_scope};
+use crate::config::live::LiveConfig;
+use crate::config::rustls_config::{load_rustls_config, CertResolver};
+use crate::config::source::ConfigSource;
+use crate::database::health::HealthChecks;
+use crate::database::{init_repositories, init_stores};
+use crate::handlers::configure;
+use crate::utils::config_reload::{run_config_reload, Reloader};
+use crate::utils::mailer::Mailer;
+use crate::utils::metrics::track_requests;
+use crate::utils::telemetry::{init_tracing, set_log_level, shutdown_tracing};
+use crate::utils::user_purge::run_user_purge;
 use actix_cors::Cors;
+use actix_web::middleware::from_fn;
 use actix_web::web::Data;
 use actix_web::{App, HttpServer};
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
 #[actix_web::main]
 async fn main() -> Result<(), std::io::Error> {
-    tracing_subscriber::registry()
-        .with(
-            tracing_subscriber::EnvFilter::try_from_default_env().unwrap_or_else(|_| "info".into()),
-        )
-        .with(tracing_subscriber::fmt::layer())
-        .init();
+    let (tracer_provider, log_filter) = init_tracing().expect("Failed to configure tracing");
 
     rustls::crypto::aws_lc_rs::default_provider()
         .install_default()
         .expect("Failed to install rustls crypto provider");
 
     tracing::info!("Loading configuration...");
-    let cfg = AppConfig::from_env().expect("Failed to load configuration");
+    let source = ConfigSource::new(flag_value("--config").map(PathBuf::from));
+    let cfg = AppConfig::load(&source);
 
-    tracing::info!("Connecting to MongoDB at {}...", cfg.mongo_uri);
-    let db = init_mongodb(&cfg.mongo_uri, &cfg.mongo_db)
+    // `--check-config` validates the configuration, prints the values in effect and exits.
+    if std::env::args().any(|arg| arg == "--check-config") {
+        println!("{}", source.report());
+        return match cfg {
+            Ok(_) => {
+                println!("Configuration is valid");
+                Ok(())
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        };
+    }
+
+    let cfg = cfg.unwrap_or_else(|e| {
+        tracing::error!("Failed to load configuration: {}", e);
+        std::process::exit(1);
+    });
+    set_log_level(&log_filter, &cfg.log_level).expect("Failed to set log level");
+
+    // `--migrate` applies pending schema migrations and exits without serving.
+    let migrate_only = std::env::args().any(|arg| arg == "--migrate");
+
+    let repositories = init_repositories(&cfg.database, cfg.migrate_on_startup || migrate_only)
         .await
         .expect("Failed to connect to database");
 
-    tracing::info!("Connecting to Redis at {}...", cfg.redis_uri);
-    let redis_conn = init_redis(&cfg.redis_uri)
+    if migrate_only {
+        tracing::info!("Schema migrations are up to date");
+        return Ok(());
+    }
+
+    let stores = init_stores(cfg.redis_uri.as_deref(), repositories.stores)
         .await
         .expect("Failed to connect to Redis");
 
-    let user_repo = UserRepository::new(&db);
-    let book_repo = BookRepository::new(&db);
-    let blacklist = TokenBlacklist::new(redis_conn);
+    let health_checks = HealthChecks(
+        std::iter::once(repositories.health)
+            .chain(stores.health)
+            .collect(),
+    );
+    let user_repo = repositories.users;
+    let book_repo = repositories.books;
+    let key_repo = repositories.api_keys;
+    let blacklist = stores.blacklist;
+    let reset_store = stores.password_resets;
+    let verification_store = stores.email_verifications;
+    let oidc_state_store = stores.oidc_states;
+    let rate_limit_store = stores.rate_limits;
+    let mailer = Mailer::new(&cfg).expect("Failed to configure mailer");
+    let oidc_provider = cfg
+        .oidc
+        .as_ref()
+        .map(|oidc| OidcProvider::new(oidc).expect("Failed to configure OIDC provider"));
+    let ldap_directory = cfg
+        .ldap
+        .as_ref()
+        .map(|ldap| LdapDirectory::new(ldap).expect("Failed to configure LDAP directory"));
+
+    if let (Some(directory), Some(ldap)) = (&ldap_directory, &cfg.ldap) {
+        if ldap.sync_interval_seconds > 0 {
+            actix_web::rt::spawn(run_directory_sync(
+                directory.clone(),
+                user_repo.clone(),
+                ldap.sync_interval_seconds,
+            ));
+        }
+    }
+
+    actix_web::rt::spawn(run_user_purge(
+        user_repo.clone(),
+        key_repo.clone(),
+        cfg.deleted_user_retention_days,
+    ));
+
+    let cert_resolver = match (&cfg.ssl_cert_path, &cfg.ssl_key_path) {
+        (Some(cert_path), Some(key_path)) => Some(Arc::new(
+            CertResolver::load(cert_path, key_path).expect("Failed to load SSL certificates"),
+        )),
+        _ => None,
+    };
+
+    // CORS origins, rate limits and the log level follow the configuration file and
+    // SIGHUP; new TLS connections use the latest certificate.
+    let live_config = LiveConfig::new(&cfg);
+    actix_web::rt::spawn(run_config_reload(Reloader {
+        config_path: source.path().map(Path::to_path_buf),
+        live: live_config.clone(),
+        certs: cert_resolver.clone(),
+        log_filter,
+    }));
 
     let host = cfg.host.clone();
     let port = cfg.port;
-    let ssl_cert_path = cfg.ssl_cert_path.clone();
-    let ssl_key_path = cfg.ssl_key_path.clone();
 
     let server = HttpServer::new(move || {
-        App::new()
-            .wrap(Cors::permissive())
+        let cors_origins = live_config.clone();
+        let app = App::new()
+            .wrap(from_fn(track_requests))
+            .wrap(Cors::permissive().allowed_origin_fn(move |origin, _| {
+                cors_origins.allows_origin(origin.as_bytes())
+            }))
             .wrap(tracing_actix_web::TracingLogger::default())
             .app_data(Data::new(cfg.clone()))
-            .app_data(Data::new(user_repo.clone()))
-            .app_data(Data::new(book_repo.clone()))
-            .app_data(Data::new(blacklist.clone()))
-            .service(health_check)
-            .service(auth_scope())
-            .service(user_scope())
-            .service(book_scope())
-            .service(admin_scope())
+            .app_data(Data::new(live_config.clone()))
+            .app_data(Data::from(user_repo.clone()))
+            .app_data(Data::from(book_repo.clone()))
+            .app_data(Data::from(key_repo.clone()))
+            .app_data(Data::from(blacklist.clone()))
+            .app_data(Data::from(reset_store.clone()))
+            .app_data(Data::from(verification_store.clone()))
+            .app_data(Data::new(mailer.clone()))
+            .app_data(Data::from(oidc_state_store.clone()))
+            .app_data(Data::from(rate_limit_store.clone()))
+            .app_data(Data::new(health_checks.clone()))
+            .configure(configure);
+
+        let app = match oidc_provider {
+            Some(ref provider) => app.app_data(Data::new(provider.clone())),
+            None => app,
+        };
+
+        match ldap_directory {
+            Some(ref directory) => app.app_data(Data::new(directory.clone())),
+            None => app,
+        }
     });
 
-    let server = match (&ssl_cert_path, &ssl_key_path) {
-        (Some(cert_path), Some(key_path)) => {
+    let server = match cert_resolver {
+        Some(resolver) => {
             tracing::info!("Starting HTTPS server at https://{}:{}", host, port);
-            let tls_config =
-                load_rustls_config(cert_path, key_path).expect("Failed to load SSL certificates");
-            server.bind_rustls_0_23((host, port), tls_config)?
+            server.bind_rustls_0_23((host, port), load_rustls_config(resolver))?
         }
-        _ => {
+        None => {
             tracing::warn!("SSL certificates not configured, falling back to HTTP");
             tracing::info!("Starting HTTP server at http://{}:{}", host, port);
             server.bind((host, port))?
         }
     };
 
-    server.run().await
+    let result = server.run().await;
+    if let Some(provider) = tracer_provider {
+        shutdown_tracing(provider);
+    }
+    result
+}
+
+/// The value of `--name <value>` or `--name=<value>` on the command line.
+fn flag_value(name: &str) -> Option<String> {
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == name {
+            return args.next();
+        }
+        if let Some(value) = arg.strip_prefix(name).and_then(|rest| rest.strip_prefix('=')) {
+            return Some(value.to_string());
+        }
+    }
+    None
 }
@@ -0,0 +1,19 @@
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::DateTime;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ApiKey {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    pub user_id: ObjectId,
+    pub name: String,
+    /// SHA-256 of the full key; the key itself is only shown once on creation
+    pub key_hash: String,
+    /// First characters of the key, so users can tell their keys apart
+    pub prefix: String,
+    pub scopes: Vec<String>,
+    pub created_at: DateTime,
+    pub expires_at: Option<DateTime>,
+    pub last_used_at: Option<DateTime>,
+}
@@ -1,3 +1,4 @@
+pub mod api_key;
 pub mod request;
 pub mod response;
 pub mod user;
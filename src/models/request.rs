@@ -1,13 +1,22 @@
-use serde::Deserialize;
+use crate::models::user::PatronCategory;
+use serde::{Deserialize, Deserializer};
 use validator::Validate;
 
+/// Tells an explicit `null` (`Some(None)`) apart from a missing field (`None`).
+fn double_option<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
 #[derive(Debug, Deserialize, Validate)]
 pub struct RegisterRequest {
     #[validate(email(message = "invalid email format"))]
     pub email: String,
     #[validate(length(min = 3, max = 30, message = "username must be 3-30 characters"))]
     pub username: String,
-    #[validate(length(min = 8, message = "password must be at least 8 characters"))]
     pub password: String,
 }
 
@@ -18,6 +27,32 @@ pub struct LoginRequest {
     pub password: String,
 }
 
+#[derive(Debug, Deserialize, Validate)]
+pub struct ForgotPasswordRequest {
+    #[validate(email(message = "invalid email format"))]
+    pub email: String,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct ResetPasswordRequest {
+    #[validate(length(min = 1, message = "token must not be empty"))]
+    pub token: String,
+    pub new_password: String,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct VerifyEmailRequest {
+    #[validate(length(min = 1, message = "token must not be empty"))]
+    pub token: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OidcCallbackQuery {
+    pub code: Option<String>,
+    pub state: Option<String>,
+    pub error: Option<String>,
+}
+
 #[derive(Debug, Deserialize, Validate)]
 pub struct UpdateEmailRequest {
     #[validate(email(message = "invalid email format"))]
@@ -33,19 +68,26 @@ pub struct UpdateUsernameRequest {
 #[derive(Debug, Deserialize, Validate)]
 pub struct UpdatePasswordRequest {
     pub old_password: String,
-    #[validate(length(min = 8, message = "password must be at least 8 characters"))]
     pub new_password: String,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct DeleteAccountRequest {
+    /// Required unless the account has no password, as with single sign-on
+    #[serde(default)]
+    pub password: Option<String>,
+}
+
 #[derive(Debug, Deserialize, Validate)]
 pub struct CreateUserRequest {
     #[validate(email(message = "invalid email format"))]
     pub email: String,
     #[validate(length(min = 3, max = 30, message = "username must be 3-30 characters"))]
     pub username: String,
-    #[validate(length(min = 8, message = "password must be at least 8 characters"))]
     pub password: String,
     pub is_admin: bool,
+    /// Defaults to `public`
+    pub category: Option<PatronCategory>,
 }
 
 #[derive(Debug, Deserialize, Validate)]
@@ -54,8 +96,18 @@ pub struct UpdateUserRequest {
     pub email: Option<String>,
     #[validate(length(min = 3, max = 30, message = "username must be 3-30 characters"))]
     pub username: Option<String>,
-    #[validate(length(min = 8, message = "password must be at least 8 characters"))]
     pub password: Option<String>,
+    pub category: Option<PatronCategory>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct CreateApiKeyRequest {
+    #[validate(length(min = 1, max = 64, message = "name must be 1-64 characters"))]
+    pub name: String,
+    #[validate(length(min = 1, message = "at least one scope is required"))]
+    pub scopes: Vec<String>,
+    #[validate(range(min = 1, max = 3650, message = "expiry must be 1-3650 days"))]
+    pub expires_in_days: Option<i64>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -63,6 +115,76 @@ pub struct SetRoleRequest {
     pub is_admin: bool,
 }
 
+#[derive(Debug, Deserialize, Validate)]
+pub struct SuspendUserRequest {
+    #[validate(length(min = 1, max = 500, message = "reason must be 1-500 characters"))]
+    pub reason: String,
+    /// Omit for a suspension that lasts until the user is reactivated
+    #[validate(range(min = 1, max = 3650, message = "expiry must be 1-3650 days"))]
+    pub expires_in_days: Option<i64>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct RenewMembershipRequest {
+    /// Defaults to the configured membership duration. Without it, open-ended
+    /// memberships stay open-ended.
+    #[validate(range(min = 1, max = 3650, message = "renewal must be 1-3650 days"))]
+    pub days: Option<i64>,
+}
+
+/// Extends every membership in a category, either by a number of days or up to a
+/// fixed date such as the end of a semester.
+#[derive(Debug, Deserialize, Validate)]
+pub struct ExtendMembershipsRequest {
+    pub category: PatronCategory,
+    #[validate(range(min = 1, max = 3650, message = "extension must be 1-3650 days"))]
+    pub days: Option<i64>,
+    /// RFC 3339 timestamp; memberships that already run longer are left alone
+    pub until: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LinkGuardianRequest {
+    pub guardian_id: String,
+}
+
+/// Creates a dependent that has no email or password and is only reachable through
+/// the guardian's account.
+#[derive(Debug, Deserialize, Validate)]
+pub struct CreateDependentRequest {
+    #[validate(length(min = 3, max = 30, message = "username must be 3-30 characters"))]
+    pub username: String,
+    /// Defaults to `child`
+    pub category: Option<PatronCategory>,
+    /// Caps the dependent below their category's loan limit
+    #[validate(range(max = 100, message = "loan limit must be at most 100"))]
+    pub loan_limit: Option<u32>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct UpdateDependentRequest {
+    #[validate(length(min = 3, max = 30, message = "username must be 3-30 characters"))]
+    pub username: Option<String>,
+    /// `null` removes the limit; leaving the field out keeps it
+    #[serde(default, deserialize_with = "double_option")]
+    #[validate(range(max = 100, message = "loan limit must be at most 100"))]
+    pub loan_limit: Option<Option<u32>>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct UpdateFamilyRequest {
+    /// Loans shared by the guardian and all dependents; `null` removes the cap
+    #[validate(range(max = 500, message = "loan cap must be at most 500"))]
+    pub loan_cap: Option<u32>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeleteUserQuery {
+    /// Return the user's borrowed books to stock instead of refusing the deletion
+    #[serde(default)]
+    pub force_return: bool,
+}
+
 #[derive(Debug, Deserialize, Validate)]
 pub struct CreateBookRequest {
     #[validate(length(min = 1, message = "title must not be empty"))]
@@ -77,5 +199,53 @@ pub struct UpdateBookRequest {
     pub title: Option<String>,
     #[validate(length(min = 1, message = "author must not be empty"))]
     pub author: Option<String>,
+    #[validate(range(min = 0, message = "stock must not be negative"))]
     pub stock: Option<i32>,
 }
+
+#[derive(Debug, Default, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportCredentials {
+    /// Email a set-password link to each new user
+    #[default]
+    Invite,
+    /// Generate a password and return it once in the import report
+    Generate,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ImportUsersQuery {
+    /// Validate and report without writing anything
+    #[serde(default)]
+    pub dry_run: bool,
+    /// Update users whose email already exists instead of rejecting the row
+    #[serde(default)]
+    pub upsert: bool,
+    /// How new users without a password in the row get one
+    #[serde(default)]
+    pub credentials: ImportCredentials,
+}
+
+/// One row of a bulk import, as a CSV record or a JSON object.
+#[derive(Debug, Deserialize)]
+pub struct ImportUserRow {
+    pub email: String,
+    pub username: String,
+    pub password: Option<String>,
+    pub is_admin: Option<bool>,
+    pub category: Option<PatronCategory>,
+}
+
+#[derive(Debug, Default, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Json,
+    Csv,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExportUsersQuery {
+    #[serde(default)]
+    pub format: ExportFormat,
+}
@@ -1,3 +1,4 @@
+use crate::models::user::PatronCategory;
 use serde::Serialize;
 
 #[derive(Debug, Serialize)]
@@ -15,7 +16,18 @@ pub struct Token {
 #[derive(Debug, Serialize)]
 pub struct AboutMe {
     pub email: String,
+    pub email_verified: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pending_email: Option<String>,
     pub username: String,
+    /// Whether the request was made with an admin's impersonation token
+    pub impersonated: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub impersonated_by: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub suspension: Option<SuspensionInfo>,
+    pub category: PatronCategory,
+    pub membership: MembershipInfo,
     pub borrowed_books: Vec<BookDetail>,
 }
 
@@ -23,8 +35,62 @@ pub struct AboutMe {
 pub struct UserInfo {
     pub id: String,
     pub email: String,
+    pub email_verified: bool,
     pub username: String,
     pub is_admin: bool,
+    pub disabled: bool,
+    pub category: PatronCategory,
+    pub membership: MembershipInfo,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub suspension: Option<SuspensionInfo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub guardian_id: Option<String>,
+    /// Set for soft-deleted users that can still be restored
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deleted_at: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FamilyInfo {
+    /// Loans shared by the guardian and all dependents, `None` when uncapped
+    pub loan_cap: Option<u32>,
+    pub active_loans: usize,
+    pub dependents: Vec<DependentInfo>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DependentInfo {
+    pub id: String,
+    pub username: String,
+    pub category: PatronCategory,
+    /// Whether the dependent has no login of their own
+    pub managed: bool,
+    /// Effective loan limit, the lower of the category limit and the dependent's own
+    pub loan_limit: usize,
+    pub membership: MembershipInfo,
+    pub borrowed_books: Vec<BookDetail>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MembershipInfo {
+    pub started_at: Option<String>,
+    /// `None` for memberships that never expire
+    pub expires_at: Option<String>,
+    pub expired: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MembershipExtension {
+    pub category: PatronCategory,
+    pub updated: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SuspensionInfo {
+    pub reason: String,
+    pub suspended_at: String,
+    pub expires_at: Option<String>,
+    pub suspended_by: String,
 }
 
 #[derive(Debug, Serialize)]
@@ -42,3 +108,82 @@ pub struct BookDetail {
     pub author: String,
     pub stock: i32,
 }
+
+/// Everything stored about a patron, returned by the self-service data export.
+#[derive(Debug, Serialize)]
+pub struct AccountExport {
+    pub exported_at: String,
+    pub profile: ProfileExport,
+    pub borrowed_books: Vec<BookDetail>,
+    pub api_keys: Vec<ApiKeyInfo>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ProfileExport {
+    pub id: String,
+    pub created_at: String,
+    pub email: String,
+    pub email_verified: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pending_email: Option<String>,
+    pub username: String,
+    pub is_admin: bool,
+    pub disabled: bool,
+    pub category: PatronCategory,
+    pub membership: MembershipInfo,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub suspension: Option<SuspensionInfo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub oidc_subject: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ldap_dn: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub password_changed_at: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ApiKeyInfo {
+    pub id: String,
+    pub name: String,
+    pub prefix: String,
+    pub scopes: Vec<String>,
+    pub created_at: String,
+    pub expires_at: Option<String>,
+    pub last_used_at: Option<String>,
+    /// The full key, only returned once when the key is created
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ImportReport {
+    pub dry_run: bool,
+    pub created: usize,
+    pub updated: usize,
+    pub failed: usize,
+    pub rows: Vec<ImportRowResult>,
+}
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportRowStatus {
+    Created,
+    Updated,
+    Failed,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ImportRowResult {
+    /// 1-based position of the row in the upload, not counting a CSV header
+    pub row: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    pub status: ImportRowStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Generated initial password, only returned once
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+    /// Whether a set-password invite will be emailed
+    pub invited: bool,
+}
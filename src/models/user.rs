@@ -1,5 +1,8 @@
 use mongodb::bson::oid::ObjectId;
+use mongodb::bson::DateTime;
 use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct User {
@@ -8,8 +11,125 @@ pub struct User {
     pub email: String,
     pub username: String,
     pub password_hash: String,
+    /// Hashes of earlier passwords, newest first, kept for the reuse check
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub password_history: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password_changed_at: Option<DateTime>,
+    #[serde(default)]
+    pub email_verified: bool,
+    /// New address requested by the user, applied once it is confirmed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pending_email: Option<String>,
+    /// Subject identifier at the OpenID Connect provider this account is linked to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub oidc_subject: Option<String>,
+    /// Distinguished name of the directory entry this account is linked to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ldap_dn: Option<String>,
+    /// Disabled accounts cannot log in or use existing credentials
+    #[serde(default)]
+    pub disabled: bool,
+    /// Set while an admin has suspended the account
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub suspension: Option<Suspension>,
+    /// Set when an admin deletes the account; it is purged after the retention period
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deleted_at: Option<DateTime>,
+    /// Patron category used by the circulation policy
+    #[serde(default)]
+    pub category: PatronCategory,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub membership_started_at: Option<DateTime>,
+    /// Borrowing is blocked after this time until the membership is renewed; `None`
+    /// never expires
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub membership_expires_at: Option<DateTime>,
+    /// Guardian who can view and act on this account
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub guardian_id: Option<ObjectId>,
+    /// Loan limit set by the guardian, applied on top of the category limit
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub loan_limit: Option<u32>,
+    /// Cap on the loans held by a guardian and all their dependents together
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub family_loan_cap: Option<u32>,
     pub is_admin: bool,
     pub token_version: i32,
     #[serde(default)]
     pub borrowed_books: Vec<ObjectId>,
 }
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Suspension {
+    pub reason: String,
+    pub suspended_at: DateTime,
+    /// The suspension lifts by itself at this time; `None` lasts until reactivation
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<DateTime>,
+    pub suspended_by: ObjectId,
+}
+
+impl Suspension {
+    pub fn is_active(&self) -> bool {
+        self.expires_at.is_none_or(|exp| exp > DateTime::now())
+    }
+}
+
+impl User {
+    /// A dependent without an email or password of its own, reachable only
+    /// through its guardian.
+    pub fn is_managed(&self) -> bool {
+        self.guardian_id.is_some() && self.email.is_empty()
+    }
+
+    /// Username left on an account anonymized at the patron's request.
+    pub fn anonymized_username(id: &ObjectId) -> String {
+        format!("deleted-{}", id.to_hex())
+    }
+
+    pub fn membership_expired(&self) -> bool {
+        self.membership_expires_at
+            .is_some_and(|exp| exp <= DateTime::now())
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum PatronCategory {
+    Student,
+    Staff,
+    #[default]
+    Public,
+    Child,
+}
+
+impl PatronCategory {
+    pub const ALL: [PatronCategory; 4] = [Self::Student, Self::Staff, Self::Public, Self::Child];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Student => "student",
+            Self::Staff => "staff",
+            Self::Public => "public",
+            Self::Child => "child",
+        }
+    }
+}
+
+impl fmt::Display for PatronCategory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for PatronCategory {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|category| category.as_str().eq_ignore_ascii_case(s.trim()))
+            .ok_or_else(|| format!("unknown patron category: {}", s))
+    }
+}
@@ -0,0 +1,130 @@
+//! Applies configuration and certificate changes while the server runs, on SIGHUP
+//! and whenever the configuration file or the TLS certificate or key changes.
+
+use crate::config::app_config::AppConfig;
+use crate::config::live::LiveConfig;
+use crate::config::rustls_config::CertResolver;
+use crate::config::source::ConfigSource;
+use crate::constants::CONFIG_WATCH_INTERVAL_SECONDS;
+use crate::utils::telemetry::{set_log_level, LogFilter};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::signal::unix::{signal, Signal, SignalKind};
+
+pub struct Reloader {
+    pub config_path: Option<PathBuf>,
+    pub live: LiveConfig,
+    /// Present when serving HTTPS
+    pub certs: Option<Arc<CertResolver>>,
+    pub log_filter: LogFilter,
+}
+
+impl Reloader {
+    fn reload_certs(&self) {
+        let Some(ref certs) = self.certs else {
+            return;
+        };
+        match certs.reload() {
+            Ok(()) => tracing::info!("TLS certificate reloaded"),
+            Err(e) => tracing::error!(
+                "TLS certificate reload failed, keeping the current certificate: {}",
+                e
+            ),
+        }
+    }
+
+    /// Validates the whole configuration again but only applies the live settings;
+    /// the others need a restart.
+    fn reload_config(&self) {
+        let cfg = match AppConfig::load(&ConfigSource::new(self.config_path.clone())) {
+            Ok(cfg) => cfg,
+            Err(e) => {
+                tracing::error!(
+                    "configuration reload failed, keeping the current settings: {}",
+                    e
+                );
+                return;
+            }
+        };
+
+        let previous = self.live.current();
+        let changed = self.live.apply(&cfg);
+        if changed.is_empty() {
+            tracing::info!("configuration reloaded, no live settings changed");
+        } else {
+            tracing::info!("configuration reloaded, updated {}", changed.join(", "));
+        }
+
+        // Changed last, so that the outcome above is logged at the old level.
+        if previous.log_level != cfg.log_level {
+            if let Err(e) = set_log_level(&self.log_filter, &cfg.log_level) {
+                tracing::error!("failed to change the log level: {}", e);
+            }
+        }
+    }
+}
+
+/// Modification time and size of each file, `None` for files that cannot be read.
+fn fingerprint(paths: &[PathBuf]) -> Vec<Option<(SystemTime, u64)>> {
+    paths
+        .iter()
+        .map(|path| {
+            let metadata = std::fs::metadata(path).ok()?;
+            Some((metadata.modified().ok()?, metadata.len()))
+        })
+        .collect()
+}
+
+async fn hangup(signal: &mut Option<Signal>) {
+    match signal {
+        Some(signal) => {
+            signal.recv().await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+/// Watches for changes forever. Files are polled rather than watched with inotify,
+/// which loses track of files replaced through symlinks as Kubernetes does.
+pub async fn run_config_reload(reloader: Reloader) {
+    let mut sighup = signal(SignalKind::hangup())
+        .map_err(|e| tracing::error!("cannot listen for SIGHUP: {}", e))
+        .ok();
+
+    let cert_paths: Vec<PathBuf> = reloader
+        .certs
+        .iter()
+        .flat_map(|certs| certs.paths())
+        .map(PathBuf::from)
+        .collect();
+    let config_paths: Vec<PathBuf> = reloader.config_path.iter().cloned().collect();
+    let mut certs_seen = fingerprint(&cert_paths);
+    let mut config_seen = fingerprint(&config_paths);
+
+    let mut interval =
+        actix_web::rt::time::interval(Duration::from_secs(CONFIG_WATCH_INTERVAL_SECONDS));
+    loop {
+        tokio::select! {
+            _ = hangup(&mut sighup) => {
+                tracing::info!("SIGHUP received, reloading configuration");
+                reloader.reload_certs();
+                reloader.reload_config();
+                certs_seen = fingerprint(&cert_paths);
+                config_seen = fingerprint(&config_paths);
+            }
+            _ = interval.tick() => {
+                let certs_now = fingerprint(&cert_paths);
+                if certs_now != certs_seen {
+                    certs_seen = certs_now;
+                    reloader.reload_certs();
+                }
+                let config_now = fingerprint(&config_paths);
+                if config_now != config_seen {
+                    config_seen = config_now;
+                    reloader.reload_config();
+                }
+            }
+        }
+    }
+}
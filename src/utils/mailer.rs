@@ -0,0 +1,76 @@
+use crate::config::app_config::{AppConfig, SmtpTls};
+use crate::constants::MAIL_FROM;
+use crate::errors::AppError;
+use lettre::message::header::ContentType;
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+
+#[derive(Clone)]
+pub struct Mailer {
+    transport: Option<AsyncSmtpTransport<Tokio1Executor>>,
+    from: Mailbox,
+}
+
+impl Mailer {
+    pub fn new(cfg: &AppConfig) -> Result<Self, String> {
+        let from = cfg
+            .mail_from
+            .parse()
+            .map_err(|_| format!("{} must be a valid mailbox", MAIL_FROM))?;
+
+        let transport = match cfg.smtp_host {
+            Some(ref host) => {
+                let mut builder = match cfg.smtp_tls {
+                    SmtpTls::StartTls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host)
+                        .map_err(|e| format!("invalid SMTP relay {}: {}", host, e))?,
+                    SmtpTls::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(host)
+                        .map_err(|e| format!("invalid SMTP relay {}: {}", host, e))?,
+                    SmtpTls::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host),
+                }
+                .port(cfg.smtp_port);
+
+                if let (Some(username), Some(password)) =
+                    (&cfg.smtp_username, &cfg.smtp_password)
+                {
+                    builder =
+                        builder.credentials(Credentials::new(username.clone(), password.clone()));
+                }
+
+                Some(builder.build())
+            }
+            None => None,
+        };
+
+        Ok(Self { transport, from })
+    }
+
+    /// Sends a plain-text email. Without an SMTP host the message is only logged,
+    /// which keeps local development free of a mail server.
+    pub async fn send(&self, to: &str, subject: &str, body: String) -> Result<(), AppError> {
+        let transport = match self.transport {
+            Some(ref transport) => transport,
+            None => {
+                tracing::warn!("SMTP not configured, email \"{}\" to {} was not sent", subject, to);
+                tracing::debug!("Unsent email body:\n{}", body);
+                return Ok(());
+            }
+        };
+
+        let to: Mailbox = to.parse().map_err(|_| AppError::Internal)?;
+        let message = Message::builder()
+            .from(self.from.clone())
+            .to(to)
+            .subject(subject)
+            .header(ContentType::TEXT_PLAIN)
+            .body(body)
+            .map_err(|_| AppError::Internal)?;
+
+        transport.send(message).await.map_err(|e| {
+            tracing::error!("Failed to send email: {:?}", e);
+            AppError::Internal
+        })?;
+
+        Ok(())
+    }
+}
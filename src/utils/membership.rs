@@ -0,0 +1,23 @@
+use crate::config::app_config::AppConfig;
+use mongodb::bson::DateTime;
+
+const DAY_MILLIS: i64 = 24 * 60 * 60 * 1000;
+
+/// Expiry of a membership starting now, or `None` when memberships never expire.
+pub fn new_membership_expiry(cfg: &AppConfig) -> Option<DateTime> {
+    renewed_membership_expiry(None, cfg.membership_duration_days)
+}
+
+/// Adds `days` to a membership, counting from its current expiry while it is still
+/// running and from now once it has lapsed. Zero days means no expiry.
+pub fn renewed_membership_expiry(current: Option<DateTime>, days: i64) -> Option<DateTime> {
+    if days == 0 {
+        return None;
+    }
+
+    let now = DateTime::now();
+    let from = current.filter(|exp| *exp > now).unwrap_or(now);
+    Some(DateTime::from_millis(
+        from.timestamp_millis() + days * DAY_MILLIS,
+    ))
+}
@@ -0,0 +1,186 @@
+//! Prometheus metrics, rendered in the text exposition format on `/metrics`.
+
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::Error;
+use prometheus::core::Collector;
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, Opts, Registry,
+    TextEncoder,
+};
+use std::sync::LazyLock;
+use std::time::{Duration, Instant};
+
+/// Route label for requests that matched no resource, so unknown paths cannot grow
+/// the number of series.
+const UNMATCHED_ROUTE: &str = "unmatched";
+
+pub static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::new);
+
+pub struct Metrics {
+    registry: Registry,
+    http_requests: IntCounterVec,
+    http_request_duration: HistogramVec,
+    database_operation_duration: HistogramVec,
+    database_operation_errors: IntCounterVec,
+    /// Requests rejected by the rate limiter, per route group
+    pub rate_limited_requests: IntCounterVec,
+    pub books_borrowed: IntCounter,
+    pub books_returned: IntCounter,
+    pub failed_logins: IntCounter,
+    /// Refreshed from the database by scrapes, at most every few seconds
+    pub active_loans: IntGauge,
+    /// Refreshed from the database by scrapes, at most every few seconds
+    pub out_of_stock_titles: IntGauge,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new();
+
+        let http_requests = IntCounterVec::new(
+            Opts::new("http_requests_total", "HTTP requests handled"),
+            &["method", "route", "status"],
+        )
+        .unwrap();
+        let http_request_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "http_request_duration_seconds",
+                "Time taken to handle HTTP requests",
+            ),
+            &["method", "route", "status"],
+        )
+        .unwrap();
+        // Databases answer much faster than a whole request, so the buckets start lower.
+        let database_operation_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "database_operation_duration_seconds",
+                "Time taken by MongoDB and Redis operations",
+            )
+            .buckets(vec![
+                0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5,
+            ]),
+            &["backend", "operation", "target"],
+        )
+        .unwrap();
+        let database_operation_errors = IntCounterVec::new(
+            Opts::new(
+                "database_operation_errors_total",
+                "MongoDB and Redis operations that failed",
+            ),
+            &["backend", "operation", "target"],
+        )
+        .unwrap();
+        let rate_limited_requests = IntCounterVec::new(
+            Opts::new(
+                "http_requests_rate_limited_total",
+                "Requests rejected for exceeding the rate limit",
+            ),
+            &["group"],
+        )
+        .unwrap();
+        let books_borrowed =
+            IntCounter::new("library_books_borrowed_total", "Books borrowed").unwrap();
+        let books_returned =
+            IntCounter::new("library_books_returned_total", "Books returned").unwrap();
+        let failed_logins = IntCounter::new(
+            "library_failed_logins_total",
+            "Login attempts rejected for bad credentials or a blocked account",
+        )
+        .unwrap();
+        let active_loans =
+            IntGauge::new("library_active_loans", "Books currently on loan").unwrap();
+        let out_of_stock_titles = IntGauge::new(
+            "library_out_of_stock_titles",
+            "Titles with no copy left on the shelf",
+        )
+        .unwrap();
+
+        let collectors: [Box<dyn Collector>; 10] = [
+            Box::new(http_requests.clone()),
+            Box::new(http_request_duration.clone()),
+            Box::new(database_operation_duration.clone()),
+            Box::new(database_operation_errors.clone()),
+            Box::new(rate_limited_requests.clone()),
+            Box::new(books_borrowed.clone()),
+            Box::new(books_returned.clone()),
+            Box::new(failed_logins.clone()),
+            Box::new(active_loans.clone()),
+            Box::new(out_of_stock_titles.clone()),
+        ];
+        for collector in collectors {
+            registry.register(collector).unwrap();
+        }
+
+        Self {
+            registry,
+            http_requests,
+            http_request_duration,
+            database_operation_duration,
+            database_operation_errors,
+            rate_limited_requests,
+            books_borrowed,
+            books_returned,
+            failed_logins,
+            active_loans,
+            out_of_stock_titles,
+        }
+    }
+
+    /// Records one database call. `target` is the collection or Redis key namespace.
+    pub fn observe_database(
+        &self,
+        backend: &str,
+        operation: &str,
+        target: &str,
+        elapsed: Duration,
+        succeeded: bool,
+    ) {
+        let labels = [backend, operation, target];
+        self.database_operation_duration
+            .with_label_values(&labels)
+            .observe(elapsed.as_secs_f64());
+        if !succeeded {
+            self.database_operation_errors
+                .with_label_values(&labels)
+                .inc();
+        }
+    }
+
+    /// Every registered metric in the Prometheus text format.
+    pub fn render(&self) -> String {
+        let mut buffer = Vec::new();
+        if let Err(e) = TextEncoder::new().encode(&self.registry.gather(), &mut buffer) {
+            tracing::error!("failed to encode metrics: {}", e);
+        }
+        String::from_utf8(buffer).unwrap_or_default()
+    }
+}
+
+/// Middleware counting requests and timing them per route pattern and status.
+pub async fn track_requests(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let started = Instant::now();
+    let method = req.method().to_string();
+    let route = req
+        .match_pattern()
+        .unwrap_or_else(|| UNMATCHED_ROUTE.into());
+
+    let res = next.call(req).await;
+
+    let status = match &res {
+        Ok(res) => res.status(),
+        Err(e) => e.as_response_error().status_code(),
+    };
+    let labels = [method.as_str(), route.as_str(), status.as_str()];
+    METRICS.http_requests.with_label_values(&labels).inc();
+    METRICS
+        .http_request_duration
+        .with_label_values(&labels)
+        .observe(started.elapsed().as_secs_f64());
+
+    res
+}
@@ -1,2 +1,11 @@
+pub mod config_reload;
+pub mod mailer;
+pub mod membership;
+pub mod metrics;
 pub mod password;
+pub mod password_policy;
+pub mod random_token;
+pub mod rate_limit;
+pub mod telemetry;
 pub mod token;
+pub mod user_purge;
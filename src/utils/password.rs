@@ -1,19 +1,85 @@
+use crate::config::app_config::AppConfig;
 use crate::constants::INVALID_CREDENTIALS;
 use crate::errors::AppError;
-use bcrypt::{DEFAULT_COST, hash, verify};
+use actix_web::web;
+use argon2::password_hash::rand_core::OsRng;
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::{Algorithm, Argon2, Params, Version};
 
-pub fn hash_password(plain: &str) -> Result<String, AppError> {
-    hash(plain, DEFAULT_COST).map_err(|_| AppError::Internal)
+fn argon2(params: Params) -> Argon2<'static> {
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
 }
 
-pub fn verify_password(hash: &str, plain: &str) -> Result<(), AppError> {
-    verify(plain, hash)
-        .map_err(|_| AppError::Unauthorized(INVALID_CREDENTIALS.into()))
-        .and_then(|ok| {
-            if ok {
-                Ok(())
-            } else {
-                Err(AppError::Unauthorized(INVALID_CREDENTIALS.into()))
-            }
-        })
+fn is_bcrypt_hash(hash: &str) -> bool {
+    hash.starts_with("$2a$") || hash.starts_with("$2b$") || hash.starts_with("$2y$")
+}
+
+/// Hashes a password with Argon2id into a PHC string, which records the algorithm
+/// and parameters alongside the salt. Runs on the blocking thread pool.
+pub async fn hash_password(cfg: &AppConfig, plain: &str) -> Result<String, AppError> {
+    let params = cfg.argon2_params.clone();
+    let plain = plain.to_string();
+
+    web::block(move || {
+        let salt = SaltString::generate(&mut OsRng);
+        argon2(params)
+            .hash_password(plain.as_bytes(), &salt)
+            .map(|hash| hash.to_string())
+            .map_err(|_| AppError::Internal)
+    })
+    .await
+    .map_err(|_| AppError::Internal)?
+}
+
+/// Verifies a password against an Argon2 PHC string or a legacy bcrypt hash.
+/// Runs on the blocking thread pool.
+pub async fn verify_password(hash: &str, plain: &str) -> Result<(), AppError> {
+    let hash = hash.to_string();
+    let plain = plain.to_string();
+
+    let ok = web::block(move || {
+        if is_bcrypt_hash(&hash) {
+            return bcrypt::verify(&plain, &hash).unwrap_or(false);
+        }
+
+        match PasswordHash::new(&hash) {
+            // Verification uses the parameters stored in the hash, not the configured ones.
+            Ok(parsed) => Argon2::default()
+                .verify_password(plain.as_bytes(), &parsed)
+                .is_ok(),
+            Err(_) => false,
+        }
+    })
+    .await
+    .map_err(|_| AppError::Internal)?;
+
+    if ok {
+        Ok(())
+    } else {
+        Err(AppError::Unauthorized(INVALID_CREDENTIALS.into()))
+    }
+}
+
+/// Whether a stored hash was produced by another algorithm or with parameters
+/// other than the configured ones, and should be replaced on the next login.
+pub fn needs_rehash(cfg: &AppConfig, hash: &str) -> bool {
+    let parsed = match PasswordHash::new(hash) {
+        Ok(parsed) => parsed,
+        Err(_) => return true,
+    };
+
+    if parsed.algorithm != Algorithm::Argon2id.ident()
+        || parsed.version != Some(Version::V0x13.into())
+    {
+        return true;
+    }
+
+    match Params::try_from(&parsed) {
+        Ok(params) => {
+            params.m_cost() != cfg.argon2_params.m_cost()
+                || params.t_cost() != cfg.argon2_params.t_cost()
+                || params.p_cost() != cfg.argon2_params.p_cost()
+        }
+        Err(_) => true,
+    }
 }
@@ -0,0 +1,178 @@
+use crate::config::app_config::AppConfig;
+use crate::constants::*;
+use crate::errors::AppError;
+use crate::models::user::User;
+use crate::utils::password::verify_password;
+use actix_web::web;
+use mongodb::bson::DateTime;
+use rand_core::{OsRng, RngCore};
+use sha1::{Digest, Sha1};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+
+/// Checks a new password against the configured policy, the breached password list
+/// and, when the account already exists, its recent passwords. All rule violations
+/// are reported together.
+pub async fn validate_new_password(
+    cfg: &AppConfig,
+    plain: &str,
+    email: &str,
+    username: &str,
+    user: Option<&User>,
+) -> Result<(), AppError> {
+    let policy = &cfg.password_policy;
+    let mut violations = Vec::new();
+
+    let length = plain.chars().count();
+    if length < policy.min_length {
+        violations.push(format!(
+            "{} {} characters",
+            PASSWORD_TOO_SHORT, policy.min_length
+        ));
+    }
+    if length > policy.max_length {
+        violations.push(format!(
+            "{} {} characters",
+            PASSWORD_TOO_LONG, policy.max_length
+        ));
+    }
+    if policy.require_lowercase && !plain.chars().any(char::is_lowercase) {
+        violations.push(PASSWORD_NEEDS_LOWERCASE.into());
+    }
+    if policy.require_uppercase && !plain.chars().any(char::is_uppercase) {
+        violations.push(PASSWORD_NEEDS_UPPERCASE.into());
+    }
+    if policy.require_digit && !plain.chars().any(|c| c.is_ascii_digit()) {
+        violations.push(PASSWORD_NEEDS_DIGIT.into());
+    }
+    if policy.require_symbol && plain.chars().all(char::is_alphanumeric) {
+        violations.push(PASSWORD_NEEDS_SYMBOL.into());
+    }
+    if policy.disallow_personal_info && contains_personal_info(plain, email, username) {
+        violations.push(PASSWORD_CONTAINS_PERSONAL_INFO.into());
+    }
+
+    if !violations.is_empty() {
+        return Err(AppError::BadRequest(violations.join("; ")));
+    }
+
+    if let Some(ref dir) = policy.breached_passwords_dir {
+        if is_breached(dir, plain).await? {
+            return Err(AppError::BadRequest(PASSWORD_BREACHED.into()));
+        }
+    }
+
+    if let Some(user) = user {
+        if policy.history_size > 0 && is_reused(user, plain, policy.history_size).await {
+            return Err(AppError::BadRequest(PASSWORD_REUSED.into()));
+        }
+    }
+
+    Ok(())
+}
+
+/// The history to store once `user` moves to a new password.
+pub fn next_password_history(cfg: &AppConfig, user: &User) -> Vec<String> {
+    if user.password_hash.is_empty() {
+        return user.password_history.clone();
+    }
+
+    std::iter::once(user.password_hash.clone())
+        .chain(user.password_history.iter().cloned())
+        .take(cfg.password_policy.history_size)
+        .collect()
+}
+
+/// Whether the password is older than the configured maximum age. Accounts that
+/// predate the change timestamp count from their creation.
+pub fn password_expired(cfg: &AppConfig, user: &User) -> bool {
+    let max_age_days = cfg.password_policy.max_age_days;
+    if max_age_days == 0 {
+        return false;
+    }
+
+    let changed_at = user
+        .password_changed_at
+        .unwrap_or_else(|| user.id.timestamp());
+    let max_age_millis = max_age_days * 24 * 60 * 60 * 1000;
+    DateTime::now().timestamp_millis() - changed_at.timestamp_millis() > max_age_millis
+}
+
+/// Generates an initial password that satisfies every character class rule.
+pub fn generate_password(cfg: &AppConfig) -> String {
+    const LOWERCASE: &[u8] = b"abcdefghijkmnopqrstuvwxyz";
+    const UPPERCASE: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ";
+    const DIGITS: &[u8] = b"23456789";
+    const SYMBOLS: &[u8] = b"!#$%&*+-=?@_";
+
+    let policy = &cfg.password_policy;
+    let length = GENERATED_PASSWORD_LENGTH
+        .max(policy.min_length)
+        .min(policy.max_length);
+    let all: Vec<u8> = [LOWERCASE, UPPERCASE, DIGITS, SYMBOLS].concat();
+    let pick = |set: &[u8]| set[OsRng.next_u32() as usize % set.len()];
+
+    let mut password: Vec<u8> = [LOWERCASE, UPPERCASE, DIGITS, SYMBOLS]
+        .iter()
+        .map(|set| pick(set))
+        .collect();
+    while password.len() < length {
+        password.push(pick(&all));
+    }
+    for i in (1..password.len()).rev() {
+        password.swap(i, OsRng.next_u32() as usize % (i + 1));
+    }
+
+    password.into_iter().map(char::from).collect()
+}
+
+fn contains_personal_info(plain: &str, email: &str, username: &str) -> bool {
+    let plain = plain.to_lowercase();
+    let local_part = email.split('@').next().unwrap_or_default();
+
+    [local_part, username]
+        .iter()
+        .map(|value| value.to_lowercase())
+        .any(|value| value.chars().count() >= MIN_PERSONAL_INFO_LENGTH && plain.contains(&value))
+}
+
+/// Checks the last `history_size` passwords, the current one included.
+async fn is_reused(user: &User, plain: &str, history_size: usize) -> bool {
+    let previous = std::iter::once(&user.password_hash)
+        .chain(user.password_history.iter())
+        .filter(|hash| !hash.is_empty())
+        .take(history_size);
+
+    for hash in previous {
+        if verify_password(hash, plain).await.is_ok() {
+            return true;
+        }
+    }
+    false
+}
+
+/// Looks the password up in a k-anonymity range file: `<dir>/<first five SHA-1 hex
+/// digits>.txt`, one `SUFFIX:COUNT` line per breached hash. A missing range file
+/// means no breached password shares the prefix.
+async fn is_breached(dir: &str, plain: &str) -> Result<bool, AppError> {
+    let digest = hex::encode_upper(Sha1::digest(plain.as_bytes()));
+    let (prefix, suffix) = digest.split_at(5);
+    let path = Path::new(dir).join(format!("{}.txt", prefix));
+    let suffix = suffix.to_string();
+
+    web::block(move || match fs::read_to_string(&path) {
+        Ok(contents) => Ok(contents.lines().any(|line| {
+            let (hash, count) = line.trim().split_once(':').unwrap_or((line.trim(), ""));
+            // Range files may be padded with zero-count decoy entries.
+            hash.eq_ignore_ascii_case(&suffix) && count.trim() != "0"
+        })),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(false),
+        Err(e) => {
+            tracing::error!("failed to read breached password file {:?}: {}", path, e);
+            Err(AppError::Internal)
+        }
+    })
+    .await
+    .map_err(|_| AppError::Internal)?
+}
@@ -0,0 +1,14 @@
+use rand_core::{OsRng, RngCore};
+use sha2::{Digest, Sha256};
+
+/// Generates an opaque, URL-safe token with 256 bits of entropy.
+pub fn generate_random_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+/// Hashes a random token before it is stored, so a leaked store cannot be replayed.
+pub fn hash_random_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
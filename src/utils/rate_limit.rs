@@ -0,0 +1,157 @@
+//! Per-client request budgets for the route groups, counted in Redis (or the embedded
+//! database) so that every instance enforces the same limit.
+
+use crate::auth::{extract_api_key, extract_session_token};
+use crate::config::app_config::{AppConfig, RateLimit, RateLimitConfig};
+use crate::config::live::LiveConfig;
+use crate::constants::{
+    RATE_LIMIT_EXCEEDED, RATE_LIMIT_LIMIT_HEADER, RATE_LIMIT_REMAINING_HEADER,
+    RATE_LIMIT_RESET_HEADER,
+};
+use crate::database::repository::ApiKeyRepository;
+use crate::database::store::{RateLimitDecision, RateLimitStore};
+use crate::errors::AppError;
+use crate::utils::metrics::METRICS;
+use crate::utils::random_token::hash_random_token;
+use crate::utils::token::decode_token;
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderMap, HeaderName, HeaderValue, RETRY_AFTER};
+use actix_web::middleware::Next;
+use actix_web::web::Data;
+use actix_web::{Error, ResponseError};
+use std::net::{IpAddr, SocketAddr};
+
+/// Route groups with a budget of their own.
+#[derive(Clone, Copy, Debug)]
+pub enum RateLimitGroup {
+    Auth,
+    Book,
+    Admin,
+}
+
+impl RateLimitGroup {
+    fn as_str(self) -> &'static str {
+        match self {
+            RateLimitGroup::Auth => "auth",
+            RateLimitGroup::Book => "book",
+            RateLimitGroup::Admin => "admin",
+        }
+    }
+
+    fn limit(self, cfg: &RateLimitConfig) -> Option<RateLimit> {
+        match self {
+            RateLimitGroup::Auth => cfg.auth,
+            RateLimitGroup::Book => cfg.book,
+            RateLimitGroup::Admin => cfg.admin,
+        }
+    }
+}
+
+/// Middleware counting each request against the group's budget. Answers 429 with
+/// `Retry-After` once the budget is spent; every limited response carries the
+/// `X-RateLimit-*` headers. If the counter store is down, requests are let through.
+pub async fn rate_limit<B: MessageBody>(
+    group: RateLimitGroup,
+    req: ServiceRequest,
+    next: Next<B>,
+) -> Result<ServiceResponse<EitherBody<B>>, Error> {
+    let (Some(cfg), Some(live), Some(store)) = (
+        req.app_data::<Data<AppConfig>>().cloned(),
+        req.app_data::<Data<LiveConfig>>().cloned(),
+        req.app_data::<Data<dyn RateLimitStore>>().cloned(),
+    ) else {
+        return pass(req, next).await;
+    };
+    // Budgets can change on a configuration reload.
+    let settings = live.current();
+    let rate_limits = &settings.rate_limits;
+    let Some(limit) = group.limit(rate_limits) else {
+        return pass(req, next).await;
+    };
+
+    let ip = client_ip(&req, rate_limits);
+    if ip.is_some_and(|ip| rate_limits.allowlist.iter().any(|net| net.contains(ip))) {
+        return pass(req, next).await;
+    }
+
+    let key = format!("{}:{}", group.as_str(), client_key(&req, &cfg, ip).await);
+    let window_ms = limit.window_seconds * 1000;
+    let decision = match store.hit(&key, limit.requests, window_ms).await {
+        Ok(decision) => decision,
+        Err(e) => {
+            tracing::warn!("rate limiter unavailable, not limiting: {:?}", e);
+            return pass(req, next).await;
+        }
+    };
+
+    if !decision.allowed {
+        METRICS
+            .rate_limited_requests
+            .with_label_values(&[group.as_str()])
+            .inc();
+        let mut res = AppError::TooManyRequests(RATE_LIMIT_EXCEEDED.into()).error_response();
+        let headers = res.headers_mut();
+        set_limit_headers(headers, limit, &decision);
+        headers.insert(
+            RETRY_AFTER,
+            HeaderValue::from(decision.reset_after_ms.div_ceil(1000)),
+        );
+        return Ok(req.into_response(res).map_into_right_body());
+    }
+
+    let mut res = next.call(req).await?;
+    set_limit_headers(res.headers_mut(), limit, &decision);
+    Ok(res.map_into_left_body())
+}
+
+async fn pass<B: MessageBody>(
+    req: ServiceRequest,
+    next: Next<B>,
+) -> Result<ServiceResponse<EitherBody<B>>, Error> {
+    Ok(next.call(req).await?.map_into_left_body())
+}
+
+fn client_ip(req: &ServiceRequest, cfg: &RateLimitConfig) -> Option<IpAddr> {
+    if !cfg.trust_forwarded_for {
+        return req.peer_addr().map(|addr| addr.ip());
+    }
+    let info = req.connection_info();
+    let addr = info.realip_remote_addr()?;
+    addr.parse::<IpAddr>()
+        .ok()
+        .or_else(|| addr.parse::<SocketAddr>().ok().map(|addr| addr.ip()))
+}
+
+/// Who the request is counted against: the API key or signed-in user when the
+/// credentials check out, otherwise the client address. Unverified credentials fall
+/// back to the address, so made-up keys cannot buy a fresh budget.
+async fn client_key(req: &ServiceRequest, cfg: &AppConfig, ip: Option<IpAddr>) -> String {
+    if let Some(api_key) = extract_api_key(req.request()) {
+        if let Some(key_repo) = req.app_data::<Data<dyn ApiKeyRepository>>() {
+            if let Ok(Some(key)) = key_repo.find_by_hash(&hash_random_token(&api_key)).await {
+                return format!("key:{}", key.id.to_hex());
+            }
+        }
+    } else if let Ok(Some(token)) = extract_session_token(req.request(), cfg) {
+        if let Ok(claims) = decode_token(cfg, &token) {
+            return format!("user:{}", claims.sub);
+        }
+    }
+
+    match ip {
+        Some(ip) => format!("ip:{}", ip.to_canonical()),
+        None => "ip:unknown".into(),
+    }
+}
+
+fn set_limit_headers(headers: &mut HeaderMap, limit: RateLimit, decision: &RateLimitDecision) {
+    let reset = decision.reset_after_ms.div_ceil(1000);
+    for (name, value) in [
+        (RATE_LIMIT_LIMIT_HEADER, u64::from(limit.requests)),
+        (RATE_LIMIT_REMAINING_HEADER, u64::from(decision.remaining)),
+        (RATE_LIMIT_RESET_HEADER, reset),
+    ] {
+        headers.insert(HeaderName::from_static(name), HeaderValue::from(value));
+    }
+}
@@ -0,0 +1,110 @@
+//! Log output and, when an OTLP endpoint is configured, export of the `tracing` spans
+//! to a tracing backend with W3C trace context propagation.
+
+use crate::constants::{
+    DEFAULT_LOG_LEVEL, DEFAULT_OTEL_SERVICE_NAME, OTEL_EXPORTER_OTLP_ENDPOINT,
+    OTEL_EXPORTER_OTLP_TRACES_ENDPOINT, OTEL_SERVICE_NAME,
+};
+use openidconnect::http::header::{HeaderName, HeaderValue};
+use openidconnect::http::HeaderMap;
+use opentelemetry::global;
+use opentelemetry::propagation::Injector;
+use opentelemetry::trace::TracerProvider;
+use opentelemetry_otlp::SpanExporter;
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::SdkTracerProvider;
+use opentelemetry_sdk::Resource;
+use std::env;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::{
+    layer::SubscriberExt, reload, util::SubscriberInitExt, EnvFilter, Registry,
+};
+
+/// Handle for changing the log filter while the server runs.
+pub type LogFilter = reload::Handle<EnvFilter, Registry>;
+
+/// Installs the global subscriber. Spans are exported over OTLP/HTTP only when
+/// `OTEL_EXPORTER_OTLP_ENDPOINT` or `OTEL_EXPORTER_OTLP_TRACES_ENDPOINT` is set; the
+/// exporter reads the other standard `OTEL_EXPORTER_OTLP_*` variables itself.
+///
+/// The returned provider must be shut down on exit to flush buffered spans.
+pub fn init_tracing() -> Result<(Option<SdkTracerProvider>, LogFilter), String> {
+    // Incoming `traceparent` headers are picked up by `TracingLogger`.
+    global::set_text_map_propagator(TraceContextPropagator::new());
+
+    let provider = if otlp_configured() {
+        let exporter = SpanExporter::builder()
+            .with_http()
+            .build()
+            .map_err(|e| format!("failed to build OTLP span exporter: {}", e))?;
+        let service_name =
+            env::var(OTEL_SERVICE_NAME).unwrap_or_else(|_| DEFAULT_OTEL_SERVICE_NAME.into());
+        Some(
+            SdkTracerProvider::builder()
+                .with_batch_exporter(exporter)
+                .with_resource(Resource::builder().with_service_name(service_name).build())
+                .build(),
+        )
+    } else {
+        None
+    };
+
+    let otel_layer = provider.as_ref().map(|provider| {
+        tracing_opentelemetry::layer().with_tracer(provider.tracer(DEFAULT_OTEL_SERVICE_NAME))
+    });
+
+    let (filter, log_filter) = reload::Layer::new(
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| DEFAULT_LOG_LEVEL.into()),
+    );
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(tracing_subscriber::fmt::layer())
+        .with(otel_layer)
+        .init();
+
+    Ok((provider, log_filter))
+}
+
+/// Replaces the log filter, e.g. `info` or `info,lib_management_sys=debug`.
+pub fn set_log_level(log_filter: &LogFilter, level: &str) -> Result<(), String> {
+    let filter = EnvFilter::try_new(level).map_err(|e| e.to_string())?;
+    log_filter.reload(filter).map_err(|e| e.to_string())
+}
+
+fn otlp_configured() -> bool {
+    [
+        OTEL_EXPORTER_OTLP_ENDPOINT,
+        OTEL_EXPORTER_OTLP_TRACES_ENDPOINT,
+    ]
+    .iter()
+    .any(|name| env::var(name).is_ok_and(|value| !value.is_empty()))
+}
+
+/// Flushes the spans still buffered for export.
+pub fn shutdown_tracing(provider: SdkTracerProvider) {
+    if let Err(e) = provider.shutdown() {
+        tracing::warn!("failed to flush spans on shutdown: {}", e);
+    }
+}
+
+struct HeaderInjector<'a>(&'a mut HeaderMap);
+
+impl Injector for HeaderInjector<'_> {
+    fn set(&mut self, key: &str, value: String) {
+        if let (Ok(name), Ok(value)) = (
+            HeaderName::from_bytes(key.as_bytes()),
+            HeaderValue::from_str(&value),
+        ) {
+            self.0.insert(name, value);
+        }
+    }
+}
+
+/// Adds the current span's trace context to an outgoing request, so the callee's
+/// spans join the same trace.
+pub fn inject_trace_context(headers: &mut HeaderMap) {
+    let context = tracing::Span::current().context();
+    global::get_text_map_propagator(|propagator| {
+        propagator.inject_context(&context, &mut HeaderInjector(headers))
+    });
+}
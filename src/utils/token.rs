@@ -1,7 +1,7 @@
 use crate::config::app_config::AppConfig;
 use crate::constants::AUTH_REQUIRED;
 use crate::errors::AppError;
-use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use jsonwebtoken::{decode, decode_header, encode, Header, Validation};
 use serde::{Deserialize, Serialize};
 use time::{Duration, OffsetDateTime};
 
@@ -9,42 +9,94 @@ fn default_claims_ver() -> i32 {
     0
 }
 
+/// The party acting on behalf of the subject (RFC 8693 `act` claim).
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Actor {
+    pub sub: String, // impersonating admin's user id
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Claims {
     pub sub: String, // user id
     pub exp: usize,
     pub iat: usize, // issued at
+    pub iss: String,
+    pub aud: String,
     #[serde(default = "default_claims_ver")]
     pub ver: i32,
+    /// Present only on impersonation tokens
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub act: Option<Actor>,
 }
 
 pub fn generate_token(cfg: &AppConfig, user_id: &str, token_version: i32) -> Result<String, AppError> {
+    sign_token(cfg, user_id, token_version, Duration::hours(cfg.jwt_exp_hours), None)
+}
+
+/// Issues a short-lived token for `user_id` that records `admin_id` as the actor.
+pub fn generate_impersonation_token(
+    cfg: &AppConfig,
+    user_id: &str,
+    token_version: i32,
+    admin_id: &str,
+) -> Result<String, AppError> {
+    let actor = Actor {
+        sub: admin_id.into(),
+    };
+    let ttl = Duration::minutes(cfg.impersonation_ttl_minutes);
+    sign_token(cfg, user_id, token_version, ttl, Some(actor))
+}
+
+fn sign_token(
+    cfg: &AppConfig,
+    user_id: &str,
+    token_version: i32,
+    ttl: Duration,
+    act: Option<Actor>,
+) -> Result<String, AppError> {
     let now = OffsetDateTime::now_utc();
     let iat = now.unix_timestamp() as usize;
-    let exp = (now + Duration::hours(cfg.jwt_exp_hours)).unix_timestamp() as usize;
+    let exp = (now + ttl).unix_timestamp() as usize;
     let claims = Claims {
         sub: user_id.into(),
         exp,
         iat,
+        iss: cfg.jwt_issuer.clone(),
+        aud: cfg.jwt_audience.clone(),
         ver: token_version,
+        act,
     };
-    encode(
-        &Header::default(),
-        &claims,
-        &EncodingKey::from_secret(cfg.jwt_secret.as_bytes()),
-    )
-    .map_err(|_| AppError::Internal)
+
+    let mut header = Header::new(cfg.jwt_keys.algorithm);
+    header.kid = Some(cfg.jwt_keys.signing_kid.clone());
+
+    encode(&header, &claims, &cfg.jwt_keys.encoding_key).map_err(|_| AppError::Internal)
 }
 
 pub fn decode_token(cfg: &AppConfig, token: &str) -> Result<Claims, AppError> {
-    decode::<Claims>(
-        token,
-        &DecodingKey::from_secret(cfg.jwt_secret.as_bytes()),
-        &Validation::default(),
-    )
-    .map(|data| data.claims)
-    .map_err(|e| {
+    let unauthorized = |e: jsonwebtoken::errors::Error| {
         tracing::warn!("Token decode error: {:?}", e);
         AppError::Unauthorized(AUTH_REQUIRED.into())
-    })
+    };
+
+    let header = decode_header(token).map_err(unauthorized)?;
+
+    // Every token this server issues names its signing key. Older tokens without a
+    // `kid` also lack the now mandatory `iss` and `aud`, so they are not accepted.
+    let Some(kid) = header.kid.as_deref() else {
+        tracing::warn!("Token without a key id");
+        return Err(AppError::Unauthorized(AUTH_REQUIRED.into()));
+    };
+    let key = cfg.jwt_keys.find(kid).ok_or_else(|| {
+        tracing::warn!("Token signed with unknown key id {}", kid);
+        AppError::Unauthorized(AUTH_REQUIRED.into())
+    })?;
+
+    let mut validation = Validation::new(key.algorithm);
+    validation.set_issuer(&[&cfg.jwt_issuer]);
+    validation.set_audience(&[&cfg.jwt_audience]);
+
+    decode::<Claims>(token, &key.decoding_key, &validation)
+        .map(|data| data.claims)
+        .map_err(unauthorized)
 }
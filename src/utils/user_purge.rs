@@ -0,0 +1,40 @@
+use crate::constants::USER_PURGE_INTERVAL_SECONDS;
+use crate::database::repository::{ApiKeyRepository, UserRepository};
+use crate::errors::AppError;
+use mongodb::bson::DateTime;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Hard-deletes users whose restore window has passed, along with their API keys.
+async fn purge_deleted_users(
+    user_repo: &dyn UserRepository,
+    key_repo: &dyn ApiKeyRepository,
+    retention_days: i64,
+) -> Result<(), AppError> {
+    let retention_millis = retention_days * 24 * 60 * 60 * 1000;
+    let cutoff = DateTime::from_millis(DateTime::now().timestamp_millis() - retention_millis);
+
+    for id in user_repo.purge_deleted(cutoff).await? {
+        key_repo.delete_by_user(&id).await?;
+        tracing::info!("purged deleted user {}", id);
+    }
+    Ok(())
+}
+
+/// Runs the purge forever at a fixed interval.
+pub async fn run_user_purge(
+    user_repo: Arc<dyn UserRepository>,
+    key_repo: Arc<dyn ApiKeyRepository>,
+    retention_days: i64,
+) {
+    let mut interval =
+        actix_web::rt::time::interval(Duration::from_secs(USER_PURGE_INTERVAL_SECONDS));
+    loop {
+        interval.tick().await;
+        if let Err(e) =
+            purge_deleted_users(user_repo.as_ref(), key_repo.as_ref(), retention_days).await
+        {
+            tracing::error!("user purge failed: {:?}", e);
+        }
+    }
+}